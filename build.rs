@@ -4,6 +4,16 @@ use std::path::Path;
 use dotenv::dotenv;
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // the SDL2 DLL only needs to be located and copied for the SDL frontend on Windows,
+    // everywhere else SDL2 is found by the linker like any other system library
+    let sdl_enabled = env::var("CARGO_FEATURE_SDL").is_ok();
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if !sdl_enabled || target_os != "windows" {
+        return;
+    }
+
     dotenv().ok();
 
     let sdl2_lib_path = match env::var("SDL2_LIB_PATH") {
@@ -33,7 +43,6 @@ fn main() {
     let dll_path = Path::new(&sdl2_lib_path).join(&sdl2_lib_name);
     let target_lib_path = Path::new(&target_dir).join(&sdl2_lib_name);
 
-    println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed={}", dll_path.display());
     println!("cargo:rustc-link-search=native={}", sdl2_lib_path);
 
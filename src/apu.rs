@@ -0,0 +1,735 @@
+/*
+Useful documentation:
+https://www.nesdev.org/wiki/APU
+https://www.nesdev.org/wiki/APU_Frame_Counter
+https://www.nesdev.org/wiki/APU_Mixer
+
+The APU is clocked once per cpu cycle, the pulse and noise timers run at half that rate.
+The mixed output is sampled down to SAMPLE_RATE and collected in `samples` until the host drains them.
+The PAL APU has its own noise and DMC periods and frame counter steps, Dendy famiclones use the NTSC ones.
+ */
+
+use crate::region::Region;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// the frame counter steps in cpu cycles, see https://www.nesdev.org/wiki/APU_Frame_Counter
+struct FrameCounterTiming {
+    steps: [u32; 4],
+    four_step_length: u32,
+    five_step_last: u32,
+    five_step_length: u32,
+}
+
+const NTSC_FRAME_COUNTER: FrameCounterTiming = FrameCounterTiming {
+    steps: [7457, 14913, 22371, 29829],
+    four_step_length: 29830,
+    five_step_last: 37281,
+    five_step_length: 37282,
+};
+
+const PAL_FRAME_COUNTER: FrameCounterTiming = FrameCounterTiming {
+    steps: [8313, 16627, 24939, 33253],
+    four_step_length: 33254,
+    five_step_last: 41565,
+    five_step_length: 41566,
+};
+
+// cutoff frequency of the high pass filter, which removes the dc offset like the NES output circuit does
+const HIGH_PASS_FREQUENCY: f64 = 90.0;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const PULSE_DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+const NOISE_PERIOD_TABLE: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const PAL_NOISE_PERIOD_TABLE: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+const DMC_RATE_TABLE: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const PAL_DMC_RATE_TABLE: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope { start: false, looping: false, constant_volume: false, volume: 0, divider: 0, decay: 0 }
+    }
+
+    fn write(&mut self, data: u8) {
+        self.looping = data & 0b0010_0000 > 0;
+        self.constant_volume = data & 0b0001_0000 > 0;
+        self.volume = data & 0b0000_1111;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant_volume { self.volume } else { self.decay }
+    }
+}
+
+struct Pulse {
+    // the first pulse channel uses ones complement for the sweep negation, the second one twos complement
+    is_first_channel: bool,
+    enabled: bool,
+    duty: u8,
+    sequence_position: u8,
+    timer_period: u16,
+    timer: u16,
+    length_counter: u8,
+    // the length counter halt flag doubles as envelope loop flag
+    length_halt: bool,
+    envelope: Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    fn new(is_first_channel: bool) -> Self {
+        Pulse {
+            is_first_channel,
+            enabled: false,
+            duty: 0,
+            sequence_position: 0,
+            timer_period: 0,
+            timer: 0,
+            length_counter: 0,
+            length_halt: false,
+            envelope: Envelope::new(),
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length_halt = data & 0b0010_0000 > 0;
+                self.envelope.write(data);
+            },
+            1 => {
+                self.sweep_enabled = data & 0b1000_0000 > 0;
+                self.sweep_period = (data >> 4) & 0b111;
+                self.sweep_negate = data & 0b0000_1000 > 0;
+                self.sweep_shift = data & 0b111;
+                self.sweep_reload = true;
+            },
+            2 => self.timer_period = (self.timer_period & 0xFF00) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((data & 0b111) as u16) << 8);
+                if self.enabled {
+                    self.length_counter = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.sequence_position = 0;
+                self.envelope.start = true;
+            },
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_position = (self.sequence_position + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if !self.length_halt && self.length_counter > 0 {
+            self.length_counter -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            let negated = if self.is_first_channel { change + 1 } else { change };
+            self.timer_period.saturating_sub(negated)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.is_muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn is_muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    fn output(&self) -> u8 {
+        if self.length_counter == 0 || self.is_muted() || PULSE_DUTY_TABLE[self.duty as usize][self.sequence_position as usize] == 0 {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+struct Triangle {
+    enabled: bool,
+    timer_period: u16,
+    timer: u16,
+    sequence_position: u8,
+    length_counter: u8,
+    // the control flag halts the length counter and controls the linear counter reload
+    control: bool,
+    linear_counter_period: u8,
+    linear_counter: u8,
+    linear_counter_reload: bool,
+}
+
+impl Triangle {
+    fn new() -> Self {
+        Triangle {
+            enabled: false,
+            timer_period: 0,
+            timer: 0,
+            sequence_position: 0,
+            length_counter: 0,
+            control: false,
+            linear_counter_period: 0,
+            linear_counter: 0,
+            linear_counter_reload: false,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.control = data & 0b1000_0000 > 0;
+                self.linear_counter_period = data & 0b0111_1111;
+            },
+            2 => self.timer_period = (self.timer_period & 0xFF00) | data as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((data & 0b111) as u16) << 8);
+                if self.enabled {
+                    self.length_counter = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.linear_counter_reload = true;
+            },
+            _ => {},
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length_counter > 0 && self.linear_counter > 0 {
+                self.sequence_position = (self.sequence_position + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear_counter(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if !self.control && self.length_counter > 0 {
+            self.length_counter -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        // ultrasonic periods are silenced to avoid popping, like most emulators do
+        if self.timer_period < 2 {
+            return 7;
+        }
+        TRIANGLE_SEQUENCE[self.sequence_position as usize]
+    }
+}
+
+struct Noise {
+    enabled: bool,
+    mode: bool,
+    period_table: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+    length_counter: u8,
+    length_halt: bool,
+    envelope: Envelope,
+}
+
+impl Noise {
+    fn new() -> Self {
+        Noise {
+            enabled: false,
+            mode: false,
+            period_table: &NOISE_PERIOD_TABLE,
+            timer_period: NOISE_PERIOD_TABLE[0],
+            timer: 0,
+            shift_register: 1,
+            length_counter: 0,
+            length_halt: false,
+            envelope: Envelope::new(),
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length_halt = data & 0b0010_0000 > 0;
+                self.envelope.write(data);
+            },
+            2 => {
+                self.mode = data & 0b1000_0000 > 0;
+                self.timer_period = self.period_table[(data & 0b1111) as usize];
+            },
+            3 => {
+                if self.enabled {
+                    self.length_counter = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.envelope.start = true;
+            },
+            _ => {},
+        }
+    }
+
+    // the noise timer is clocked every cpu cycle, the period table is given in cpu cycles
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if !self.length_halt && self.length_counter > 0 {
+            self.length_counter -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length_counter == 0 || self.shift_register & 1 == 1 {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+struct Dmc {
+    irq_enabled: bool,
+    irq_pending: bool,
+    looping: bool,
+    rate_table: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Dmc {
+    fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            irq_pending: false,
+            looping: false,
+            rate_table: &DMC_RATE_TABLE,
+            timer_period: DMC_RATE_TABLE[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = data & 0b1000_0000 > 0;
+                self.looping = data & 0b0100_0000 > 0;
+                self.timer_period = self.rate_table[(data & 0b1111) as usize];
+                if !self.irq_enabled {
+                    self.irq_pending = false;
+                }
+            },
+            1 => self.output_level = data & 0b0111_1111,
+            2 => self.sample_address = 0xC000 + data as u16 * 64,
+            _ => self.sample_length = data as u16 * 16 + 1,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    // the address of the next sample byte, in case the sample buffer needs to be refilled
+    fn pending_fetch(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    fn fill_sample_buffer(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        // the sample address wraps around to $8000
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                },
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+
+    region: Region,
+    frame_counter: &'static FrameCounterTiming,
+    frame_counter_cycle: u32,
+    five_step_mode: bool,
+    frame_irq_inhibit: bool,
+    frame_irq_pending: bool,
+    cycle: u64,
+
+    sample_timer: f64,
+    high_pass_previous_input: f32,
+    high_pass_previous_output: f32,
+    pub samples: Vec<f32>,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            region: Region::Ntsc,
+            frame_counter: &NTSC_FRAME_COUNTER,
+            frame_counter_cycle: 0,
+            five_step_mode: false,
+            frame_irq_inhibit: false,
+            frame_irq_pending: false,
+            cycle: 0,
+            sample_timer: 0.0,
+            high_pass_previous_input: 0.0,
+            high_pass_previous_output: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    // a reset silences all channels, the frame counter mode is kept
+    pub fn reset(&mut self) {
+        self.write_register(0x4015, 0);
+        self.frame_irq_pending = false;
+        self.dmc.irq_pending = false;
+        self.frame_counter_cycle = 0;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        let pal = region == Region::Pal;
+        self.frame_counter = if pal { &PAL_FRAME_COUNTER } else { &NTSC_FRAME_COUNTER };
+        self.noise.period_table = if pal { &PAL_NOISE_PERIOD_TABLE } else { &NOISE_PERIOD_TABLE };
+        self.dmc.rate_table = if pal { &PAL_DMC_RATE_TABLE } else { &DMC_RATE_TABLE };
+    }
+
+    /*
+        Register access
+     */
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr - 0x4000, data),
+            0x4004..=0x4007 => self.pulse2.write(addr - 0x4004, data),
+            0x4008..=0x400B => self.triangle.write(addr - 0x4008, data),
+            0x400C..=0x400F => self.noise.write(addr - 0x400C, data),
+            0x4010..=0x4013 => self.dmc.write(addr - 0x4010, data),
+            0x4015 => {
+                self.pulse1.enabled = data & 0b0000_0001 > 0;
+                self.pulse2.enabled = data & 0b0000_0010 > 0;
+                self.triangle.enabled = data & 0b0000_0100 > 0;
+                self.noise.enabled = data & 0b0000_1000 > 0;
+
+                if !self.pulse1.enabled { self.pulse1.length_counter = 0; }
+                if !self.pulse2.enabled { self.pulse2.length_counter = 0; }
+                if !self.triangle.enabled { self.triangle.length_counter = 0; }
+                if !self.noise.enabled { self.noise.length_counter = 0; }
+
+                if data & 0b0001_0000 == 0 {
+                    self.dmc.bytes_remaining = 0;
+                } else if self.dmc.bytes_remaining == 0 {
+                    self.dmc.restart();
+                }
+                self.dmc.irq_pending = false;
+            },
+            0x4017 => {
+                self.five_step_mode = data & 0b1000_0000 > 0;
+                self.frame_irq_inhibit = data & 0b0100_0000 > 0;
+                if self.frame_irq_inhibit {
+                    self.frame_irq_pending = false;
+                }
+                self.frame_counter_cycle = 0;
+
+                // the 5 step mode immediately clocks the envelopes, length counters and sweeps
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // $4015 status, reading it acknowledges the frame interrupt
+    pub fn read_status(&mut self) -> u8 {
+        let result = self.peek_status();
+        self.frame_irq_pending = false;
+        result
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        let mut result = 0;
+        if self.pulse1.length_counter > 0 { result |= 0b0000_0001; }
+        if self.pulse2.length_counter > 0 { result |= 0b0000_0010; }
+        if self.triangle.length_counter > 0 { result |= 0b0000_0100; }
+        if self.noise.length_counter > 0 { result |= 0b0000_1000; }
+        if self.dmc.bytes_remaining > 0 { result |= 0b0001_0000; }
+        if self.frame_irq_pending { result |= 0b0100_0000; }
+        if self.dmc.irq_pending { result |= 0b1000_0000; }
+        result
+    }
+
+    pub fn irq(&self) -> bool {
+        self.frame_irq_pending || self.dmc.irq_pending
+    }
+
+    /*
+        DMC sample fetches, the memory reads are served by the bus
+     */
+    pub fn pending_dmc_fetch(&self) -> Option<u16> {
+        self.dmc.pending_fetch()
+    }
+
+    pub fn fill_dmc_sample(&mut self, data: u8) {
+        self.dmc.fill_sample_buffer(data);
+    }
+
+    /*
+        Timing
+     */
+    pub fn tick(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycle % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.cycle += 1;
+
+        self.clock_frame_counter();
+        self.generate_sample();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_counter_cycle += 1;
+        let timing = self.frame_counter;
+
+        match self.frame_counter_cycle {
+            c if c == timing.steps[0] || c == timing.steps[2] => self.clock_quarter_frame(),
+            c if c == timing.steps[1] => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            },
+            c if c == timing.steps[3] && !self.five_step_mode => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.frame_irq_inhibit {
+                    self.frame_irq_pending = true;
+                }
+            },
+            c if c == timing.five_step_last && self.five_step_mode => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            },
+            _ => {},
+        }
+
+        let length = if self.five_step_mode { timing.five_step_length } else { timing.four_step_length };
+        if self.frame_counter_cycle >= length {
+            self.frame_counter_cycle = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_length();
+        self.pulse2.clock_length();
+        self.triangle.clock_length();
+        self.noise.clock_length();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    /*
+        Output
+     */
+    // the nonlinear mixer approximation of https://www.nesdev.org/wiki/APU_Mixer
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+        let tnd = self.triangle.output() as f32 / 8227.0 + self.noise.output() as f32 / 12241.0 + self.dmc.output_level as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+        pulse_out + tnd_out
+    }
+
+    fn generate_sample(&mut self) {
+        let cycles_per_sample = self.region.cpu_clock_rate() / SAMPLE_RATE as f64;
+        self.sample_timer += 1.0;
+        if self.sample_timer < cycles_per_sample {
+            return;
+        }
+        self.sample_timer -= cycles_per_sample;
+
+        // first order high pass filter
+        let rc = 1.0 / (2.0 * std::f64::consts::PI * HIGH_PASS_FREQUENCY);
+        let alpha = (rc / (rc + 1.0 / SAMPLE_RATE as f64)) as f32;
+
+        let input = self.output();
+        let filtered = alpha * (self.high_pass_previous_output + input - self.high_pass_previous_input);
+        self.high_pass_previous_input = input;
+        self.high_pass_previous_output = filtered;
+
+        self.samples.push(filtered);
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU::new()
+    }
+}
@@ -0,0 +1,288 @@
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use rust_nes::frame::Overscan;
+use rust_nes::golden::{self, GoldenResult};
+use rust_nes::mem::RamPattern;
+use rust_nes::movie::Movie;
+use rust_nes::nes::Nes;
+use rust_nes::ntsc::{NtscFilter, NtscSettings};
+use rust_nes::palette::Palette;
+use rust_nes::ppu_viewer;
+use rust_nes::recorder::AviRecorder;
+use rust_nes::region::Region;
+use rust_nes::screenshot;
+use rust_nes::trace::{TraceFormat, Tracer};
+use rust_nes::video::Video;
+
+fn file_stem(path: &str) -> String {
+    Path::new(path).file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string())
+}
+
+// invalid values of the command line options end the program with the error, like a rom that fails to load
+fn exit_with_error(error: impl Display) -> ! {
+    println!("{}", error);
+    std::process::exit(1);
+}
+
+fn parse_option<T: FromStr<Err = String>>(value: &str) -> T {
+    value.parse().unwrap_or_else(|error| exit_with_error(error))
+}
+
+fn main() {
+    // usage: headless <rom> [--frames 60] [--start-address C000] [--ram-pattern zeros|ones|random] [--bus-trace file] [--trace file] [--trace-format nestest|mesen|fceux] [--ppu-dump prefix] [--screenshot] [--screenshot-scale 2] [--overscan 8,8,0,0] [--record file.avi] [--movie file.fm2] [--golden dir] [--golden-frames 60,120] [--golden-name name] [--update-golden] [--region ntsc|pal|dendy] [--palette default|ntsc|file.pal] [--ntsc] [--ntsc-settings sharpness=0.2,artifacts=0.5]
+    let mut file_path = None;
+    let mut frames = None;
+    let mut start_address = None;
+    let mut ram_pattern = RamPattern::Zeros;
+    let mut bus_trace_path = None;
+    let mut ppu_dump_prefix = None;
+    let mut take_screenshot = false;
+    let mut record_path = None;
+    let mut movie_path = None;
+    let mut golden_directory = None;
+    let mut golden_frames: Vec<u64> = Vec::new();
+    let mut golden_name = None;
+    let mut update_golden = false;
+    let mut region = None;
+    let mut video = Video::default();
+    let mut screenshot_scale = 1;
+    let mut overscan = None;
+    let mut tracer = Tracer::new(TraceFormat::Nestest);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let value = args.next().expect("--frames requires a number");
+                frames = Some(value.parse().expect("--frames requires a number"));
+            },
+            "--ram-pattern" => {
+                let value = args.next().expect("--ram-pattern requires zeros, ones or random");
+                ram_pattern = parse_option(&value);
+            },
+            "--start-address" => {
+                let value = args.next().expect("--start-address requires a hexadecimal address");
+                start_address = Some(u16::from_str_radix(&value, 16).expect("--start-address requires a hexadecimal address"));
+            },
+            "--trace" => {
+                let value = args.next().expect("--trace requires a file");
+                tracer.set_output_file(&value).unwrap_or_else(|error| exit_with_error(format!("the trace {} could not be created: {}", value, error)));
+            },
+            "--trace-format" => {
+                let value = args.next().expect("--trace-format requires nestest, mesen or fceux");
+                tracer.format = parse_option(&value);
+            },
+            "--bus-trace" => {
+                bus_trace_path = Some(args.next().expect("--bus-trace requires a file"));
+            },
+            "--ppu-dump" => {
+                ppu_dump_prefix = Some(args.next().expect("--ppu-dump requires a file prefix"));
+            },
+            "--record" => {
+                record_path = Some(args.next().expect("--record requires a file"));
+            },
+            "--movie" => {
+                movie_path = Some(args.next().expect("--movie requires a file"));
+            },
+            "--golden" => {
+                golden_directory = Some(args.next().expect("--golden requires a directory"));
+            },
+            "--golden-frames" => {
+                let value = args.next().expect("--golden-frames requires frame numbers like 60,120");
+                golden_frames = value.split(',')
+                    .map(|frame| frame.parse().unwrap_or_else(|_| exit_with_error("--golden-frames requires frame numbers like 60,120")))
+                    .collect();
+            },
+            "--golden-name" => {
+                golden_name = Some(args.next().expect("--golden-name requires a name"));
+            },
+            "--update-golden" => update_golden = true,
+            "--palette" => {
+                let value = args.next().expect("--palette requires default, ntsc or a .pal file");
+                video.palette = Palette::select(&value).unwrap_or_else(|error| {
+                    println!("the palette could not be loaded: {}", error);
+                    std::process::exit(1);
+                });
+            },
+            "--ntsc" => video.ntsc = Some(NtscFilter::new(NtscSettings::default())),
+            "--ntsc-settings" => {
+                let value = args.next().expect("--ntsc-settings requires settings like sharpness=0.2,artifacts=0.5");
+                video.ntsc = Some(NtscFilter::new(parse_option(&value)));
+            },
+            "--region" => {
+                let value = args.next().expect("--region requires ntsc, pal or dendy");
+                region = Some(parse_option::<Region>(&value));
+            },
+            "--screenshot" => take_screenshot = true,
+            "--screenshot-scale" => {
+                let value = args.next().expect("--screenshot-scale requires a number");
+                screenshot_scale = match value.parse() {
+                    Ok(0) | Err(_) => exit_with_error("--screenshot-scale requires a number of at least 1"),
+                    Ok(scale) => scale,
+                };
+            },
+            "--overscan" => {
+                let value = args.next().expect("--overscan requires top,bottom,left,right or none");
+                overscan = Some(parse_option::<Overscan>(&value));
+            },
+            _ => file_path = Some(arg),
+        }
+    }
+
+    let Some(file_path) = file_path else {
+        println!("usage: headless <rom> [--frames 60] [--start-address C000] [--ram-pattern zeros|ones|random] [--bus-trace file] [--trace file] [--trace-format nestest|mesen|fceux] [--ppu-dump prefix] [--screenshot] [--screenshot-scale 2] [--overscan 8,8,0,0] [--record file.avi] [--movie file.fm2] [--golden dir] [--golden-frames 60,120] [--golden-name name] [--update-golden] [--region ntsc|pal|dendy] [--palette default|ntsc|file.pal] [--ntsc] [--ntsc-settings sharpness=0.2,artifacts=0.5]");
+        std::process::exit(1);
+    };
+
+    let rom_contents = fs::read(&file_path).unwrap();
+    let mut nes = match Nes::new(&rom_contents) {
+        Ok(nes) => nes,
+        Err(error) => {
+            println!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    // a movie provides the input of every frame and runs until its end unless --frames is given
+    let movie = movie_path.as_ref().map(|path| match Movie::load(path) {
+        Ok(movie) => movie,
+        Err(error) => {
+            println!("the movie {} could not be loaded: {}", path, error);
+            std::process::exit(1);
+        }
+    });
+    if let Some(movie) = &movie {
+        if !movie.matches_rom(&rom_contents) {
+            println!("the movie has been recorded with a different rom");
+        }
+    }
+
+    // the region comes from the rom header, a movie may override it and --region overrides both
+    if let Some(region) = region.or_else(|| movie.as_ref().map(|movie| movie.region(nes.region()))) {
+        nes.set_region(region);
+    }
+    video.overscan = overscan.unwrap_or(Overscan::for_region(nes.region()));
+
+    nes.set_ram_pattern(ram_pattern);
+    nes.set_start_address(start_address);
+    nes.power_on();
+
+    // every cpu bus access is streamed to the file, one line per cycle
+    if let Some(bus_trace_path) = bus_trace_path {
+        let file = File::create(&bus_trace_path)
+            .unwrap_or_else(|error| exit_with_error(format!("the bus trace {} could not be created: {}", bus_trace_path, error)));
+        let mut output = Some(BufWriter::new(file));
+        nes.cpu_mut().set_bus_trace(Some(Box::new(move |access| {
+            // a failing output (e.g. a full disk) stops the bus trace instead of the emulation
+            if let Some(file) = &mut output {
+                if writeln!(file, "{}", access).is_err() {
+                    output = None;
+                }
+            }
+        })));
+    }
+
+    // the golden images are named after the movie or the rom, e.g. "smb_120.png"
+    let golden_name = golden_name.unwrap_or_else(|| file_stem(movie_path.as_ref().unwrap_or(&file_path)));
+
+    let frames = frames
+        .or(movie.as_ref().map(|movie| movie.frames.len() as u64))
+        .or(golden_frames.iter().max().copied())
+        .unwrap_or(60);
+    // without selected frames the last frame is compared
+    if golden_frames.is_empty() {
+        golden_frames.push(frames);
+    }
+    // the frames are counted from 1, a frame after the end of the run would never be compared
+    let invalid_frame = golden_frames.iter().find(|frame| **frame == 0 || **frame > frames);
+    if let (Some(frame), Some(_)) = (invalid_frame, &golden_directory) {
+        exit_with_error(format!("the golden frame {} is not within the {} frames of the run", frame, frames));
+    }
+    let mut golden_failures = 0;
+
+    let mut recorder = record_path.map(|path| {
+        let (width, height) = video.cropped_size();
+        match AviRecorder::create(&path, nes.region(), width, height) {
+            Ok(recorder) => recorder,
+            Err(error) => {
+                println!("the recording {} could not be created: {}", path, error);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    for frame in 0..frames {
+        // no buttons are pressed after the end of the movie
+        if let Some(movie) = &movie {
+            movie.frames.get(frame as usize).copied().unwrap_or_default().apply(&mut nes);
+        }
+
+        if let Err(error) = nes.run_frame_with_callback(|cpu, opcode| tracer.trace(cpu, opcode)) {
+            println!("frame {}: {}", frame, error);
+            // dropping the bus trace flushes its file
+            nes.cpu_mut().set_bus_trace(None);
+            tracer.flush().unwrap();
+            if let Some(recorder) = recorder {
+                recorder.finish().unwrap();
+            }
+            std::process::exit(1);
+        }
+
+        if let Some(active_recorder) = &mut recorder {
+            let image = video.render_cropped(nes.frame_buffer(), nes.ppu().frame_count);
+            if let Err(error) = active_recorder.record_frame(&image, nes.audio_samples()) {
+                println!("the recording stopped: {}", error);
+                recorder.take().unwrap().finish().unwrap();
+            }
+        }
+
+        // the frames are counted from 1, frame 60 is the frame shown after one second
+        let frame_number = frame + 1;
+        if let Some(directory) = &golden_directory {
+            if golden_frames.contains(&frame_number) {
+                let path = golden::golden_path(directory, &golden_name, frame_number);
+                let hash = nes.frame_buffer().hash();
+                match golden::check_frame(nes.frame_buffer(), &path, update_golden) {
+                    Ok(GoldenResult::Match) => println!("frame {}: matches {} (hash {:016x})", frame_number, path, hash),
+                    Ok(GoldenResult::Updated) => println!("frame {}: written to {} (hash {:016x})", frame_number, path, hash),
+                    Ok(GoldenResult::Mismatch { differing_pixels, actual_path, diff_path }) => {
+                        println!("frame {}: {} pixels differ from {}, see {} and {}", frame_number, differing_pixels, path, actual_path, diff_path);
+                        golden_failures += 1;
+                    },
+                    Err(error) => {
+                        println!("frame {}: {}", frame_number, error);
+                        golden_failures += 1;
+                    },
+                }
+            }
+        }
+    }
+    tracer.flush().unwrap();
+    if let Some(recorder) = recorder {
+        recorder.finish().unwrap();
+    }
+
+    // the PPU views after the last frame, e.g. to attach them to a bug report
+    if let Some(prefix) = ppu_dump_prefix {
+        let files = ppu_viewer::export(nes.ppu(), 0, &prefix).unwrap();
+        println!("exported {}", files.join(", "));
+    }
+
+    if take_screenshot {
+        let image = video.render(nes.frame_buffer(), nes.ppu().frame_count);
+        let file = screenshot::save(&image, &file_path, video.overscan, screenshot_scale).unwrap();
+        println!("saved screenshot {}", file);
+    }
+
+    println!("ran {} frames ({}), {} cpu cycles, frame hash {:016x}", frames, nes.region().name(), nes.cpu().cycles, nes.frame_buffer().hash());
+
+    if golden_failures > 0 {
+        println!("{} frames do not match their golden images", golden_failures);
+        std::process::exit(1);
+    }
+}
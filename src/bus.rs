@@ -1,5 +1,9 @@
-use crate::mem::Mem;
-use crate::cartridge::Cartridge;
+use crate::apu::APU;
+use crate::cartridge::{Cartridge, Flags6};
+use crate::joypad::Joypad;
+use crate::mem::{Mem, RamPattern};
+use crate::ppu::PPU;
+use crate::region::Region;
 
 /*
 NES memory map illustrated using ChatGPT 4o
@@ -28,80 +32,308 @@ NES memory map illustrated using ChatGPT 4o
 const RAM_START: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 
-const EXPANSION_REGISTERS_START: u16 = 0x4000;
-const EXPANSION_REGISTERS_END: u16 = 0x5FFF;
+const PPU_REGISTERS_START: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+
+const APU_REGISTERS_START: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x4013;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD_1: u16 = 0x4016;
+// writes to $4017 go to the APU frame counter, reads to the second joypad
+const JOYPAD_2: u16 = 0x4017;
+
+const EXPANSION_START: u16 = 0x4018;
+const EXPANSION_END: u16 = 0x5FFF;
+
+const PROGRAM_RAM_START: u16 = 0x6000;
+const PROGRAM_RAM_END: u16 = 0x7FFF;
 
 const CARTRIDGE_START: u16 = 0x8000;
-const PROGRAM_START_ADDRESS: u16 = 0xFFFC;
-const PROGRAM_START_END_ADDRESS: u16 = 0xFFFE;
+const CARTRIDGE_END: u16 = 0xFFFF;
 
 #[derive(Debug, PartialEq)]
 enum BusReadFrom {
     CpuRam,
-    CartridgeProgramRom,
+    Ppu,
+    Apu,
+    OamDma,
+    ApuStatus,
+    Joypad1,
+    Joypad2,
     Expansion,
+    CartridgeProgramRam,
+    CartridgeProgramRom,
+}
+
+// the memories of the console which can be inspected and edited by debugging tools
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemoryRegion {
+    CpuRam,
+    ProgramRam,
+    // the four logical nametables at $2000 - $2FFF, as seen through the mirroring
+    Vram,
+    Oam,
+    Palette,
+}
+
+impl MemoryRegion {
+    pub const ALL: [MemoryRegion; 5] = [
+        MemoryRegion::CpuRam,
+        MemoryRegion::ProgramRam,
+        MemoryRegion::Vram,
+        MemoryRegion::Oam,
+        MemoryRegion::Palette,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryRegion::CpuRam => "CPU RAM",
+            MemoryRegion::ProgramRam => "PRG RAM",
+            MemoryRegion::Vram => "VRAM",
+            MemoryRegion::Oam => "OAM",
+            MemoryRegion::Palette => "PALETTE",
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            MemoryRegion::CpuRam => 0x0800,
+            MemoryRegion::ProgramRam => 0x2000,
+            MemoryRegion::Vram => 0x1000,
+            MemoryRegion::Oam => 0x0100,
+            MemoryRegion::Palette => 0x0020,
+        }
+    }
+
+    // the address of the first byte in the address space of the cpu, the PPU or the OAM
+    pub fn base_address(&self) -> u16 {
+        match self {
+            MemoryRegion::CpuRam => RAM_START,
+            MemoryRegion::ProgramRam => PROGRAM_RAM_START,
+            MemoryRegion::Vram => 0x2000,
+            MemoryRegion::Oam => 0x0000,
+            MemoryRegion::Palette => 0x3F00,
+        }
+    }
+
+    // maps a cpu address onto the region and the offset inside it
+    pub fn from_cpu_address(addr: u16) -> Option<(MemoryRegion, usize)> {
+        match addr {
+            RAM_START ..= RAM_MIRRORS_END => Some((MemoryRegion::CpuRam, (addr & 0x07FF) as usize)),
+            PROGRAM_RAM_START ..= PROGRAM_RAM_END => Some((MemoryRegion::ProgramRam, (addr - PROGRAM_RAM_START) as usize)),
+            _ => None,
+        }
+    }
 }
 
 pub struct Bus {
     cpu_ram: [u8; 0x0800],
-    program_start: u16, // this is a workaround until ROM loading is implemented
     cartridge: Cartridge,
+    pub ppu: PPU,
+    pub apu: APU,
+    pub joypad1: Joypad,
+    pub joypad2: Joypad,
+    oam_dma_page: Option<u8>,
+    region: Region,
+    // the PPU dots in fifths of a dot which are due but not yet run, PAL consoles run 3.2 dots per cpu cycle
+    ppu_dot_fraction: u16,
+    // the last value driven on the cpu data bus, unmapped addresses do not drive the bus and return it
+    open_bus: u8,
+    // the addresses of all cpu writes since the log was last cleared, only kept while enabled
+    write_log: Option<Vec<u16>>,
 }
 
 impl Bus {
     pub fn new(cartridge: Cartridge) -> Self {
+        let ppu = PPU::new(cartridge.character_rom.clone(), cartridge.mirroring());
         Bus {
             cpu_ram: [0; 0x0800],
-            program_start: 0,
-            cartridge
+            cartridge,
+            ppu,
+            apu: APU::new(),
+            joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            oam_dma_page: None,
+            region: Region::Ntsc,
+            ppu_dot_fraction: 0,
+            open_bus: 0,
+            write_log: None,
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu_dot_fraction = 0;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    // fills the cpu ram and the program ram of cartridges without battery with the given pattern
+    pub fn fill_ram(&mut self, pattern: RamPattern) {
+        pattern.fill(&mut self.cpu_ram);
+        if !self.cartridge.flags_6.contains(Flags6::BatteryBacke) {
+            pattern.fill(&mut self.cartridge.program_ram);
+        }
+    }
+
+    // the write log includes dummy writes and stack pushes, it is cleared at the start of every frame
+    pub fn set_write_log_enabled(&mut self, enabled: bool) {
+        self.write_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    pub fn write_log(&self) -> &[u16] {
+        self.write_log.as_deref().unwrap_or(&[])
+    }
+
+    pub fn clear_write_log(&mut self) {
+        if let Some(write_log) = &mut self.write_log {
+            write_log.clear();
+        }
+    }
+
+    // reads a byte of a memory region without any side effects
+    pub fn peek_region(&self, region: MemoryRegion, offset: usize) -> u8 {
+        let offset = offset % region.size();
+        match region {
+            MemoryRegion::CpuRam => self.cpu_ram[offset],
+            MemoryRegion::ProgramRam => self.cartridge.program_ram[offset],
+            MemoryRegion::Vram | MemoryRegion::Palette => self.ppu.read_vram(region.base_address() + offset as u16),
+            MemoryRegion::Oam => self.ppu.oam[offset],
+        }
+    }
+
+    pub fn poke_region(&mut self, region: MemoryRegion, offset: usize, data: u8) {
+        let offset = offset % region.size();
+        match region {
+            MemoryRegion::CpuRam => self.cpu_ram[offset] = data,
+            MemoryRegion::ProgramRam => self.cartridge.program_ram[offset] = data,
+            MemoryRegion::Vram | MemoryRegion::Palette => self.ppu.write_vram(region.base_address() + offset as u16, data),
+            MemoryRegion::Oam => self.ppu.oam[offset] = data,
         }
     }
 
     fn match_address(addr: u16, program_rom_mirrored: bool) -> (BusReadFrom, u16) {
         match addr {
-            RAM_START .. RAM_MIRRORS_END => {
+            RAM_START ..= RAM_MIRRORS_END => {
                 let real_addr = addr & 0b0000_0111_1111_1111;
                 (BusReadFrom::CpuRam, real_addr)
             },
-            EXPANSION_REGISTERS_START .. EXPANSION_REGISTERS_END => {
-                let real_addr = addr;
-                (BusReadFrom::Expansion, real_addr)
-            }
-            CARTRIDGE_START .. PROGRAM_START_END_ADDRESS => {
+            PPU_REGISTERS_START ..= PPU_REGISTERS_MIRRORS_END => {
+                let real_addr = addr & 0b0010_0000_0000_0111;
+                (BusReadFrom::Ppu, real_addr)
+            },
+            APU_REGISTERS_START ..= APU_REGISTERS_END => (BusReadFrom::Apu, addr),
+            OAM_DMA => (BusReadFrom::OamDma, addr),
+            APU_STATUS => (BusReadFrom::ApuStatus, addr),
+            JOYPAD_1 => (BusReadFrom::Joypad1, addr),
+            JOYPAD_2 => (BusReadFrom::Joypad2, addr),
+            EXPANSION_START ..= EXPANSION_END => (BusReadFrom::Expansion, addr),
+            PROGRAM_RAM_START ..= PROGRAM_RAM_END => (BusReadFrom::CartridgeProgramRam, addr - PROGRAM_RAM_START),
+            CARTRIDGE_START ..= CARTRIDGE_END => {
                 let mut real_addr = addr - CARTRIDGE_START;
-                if program_rom_mirrored && real_addr >= 0x4000{
-                    real_addr = real_addr - 0x4000;
+                if program_rom_mirrored && real_addr >= 0x4000 {
+                    real_addr -= 0x4000;
                 }
                 (BusReadFrom::CartridgeProgramRom, real_addr)
             },
-            _ => {
-                println!("Read at {:x} not yet implemented", addr);
-                todo!()
-            },
         }
     }
 }
 
 impl Mem for Bus {
-    fn mem_read(&self, addr: u16) -> u8 {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        let (read_from, real_addr) = Bus::match_address(addr, self.cartridge.is_program_rom_mirrored);
+        let value = match read_from {
+            BusReadFrom::Ppu => self.ppu.read_register(real_addr),
+            // $4015 is internal to the 2A03, reading it does not change the external data bus
+            BusReadFrom::ApuStatus => return self.apu.read_status() | (self.open_bus & 0b0010_0000),
+            // the controllers only drive the lower bits
+            BusReadFrom::Joypad1 => (self.open_bus & 0b1110_0000) | self.joypad1.read(),
+            BusReadFrom::Joypad2 => (self.open_bus & 0b1110_0000) | self.joypad2.read(),
+            _ => self.mem_peek(addr),
+        };
+
+        self.open_bus = value;
+        value
+    }
+
+    fn mem_peek(&self, addr: u16) -> u8 {
         let (read_from, real_addr) = Bus::match_address(addr, self.cartridge.is_program_rom_mirrored);
         match read_from {
             BusReadFrom::CpuRam => self.cpu_ram[real_addr as usize],
+            BusReadFrom::Ppu => self.ppu.peek_register(real_addr),
+            BusReadFrom::ApuStatus => self.apu.peek_status() | (self.open_bus & 0b0010_0000),
+            BusReadFrom::Joypad1 => (self.open_bus & 0b1110_0000) | self.joypad1.peek(),
+            BusReadFrom::Joypad2 => (self.open_bus & 0b1110_0000) | self.joypad2.peek(),
+            // write only registers and unmapped addresses
+            BusReadFrom::Apu | BusReadFrom::OamDma | BusReadFrom::Expansion => self.open_bus,
+            BusReadFrom::CartridgeProgramRam => self.cartridge.program_ram[real_addr as usize],
             BusReadFrom::CartridgeProgramRom => self.cartridge.program_rom[real_addr as usize],
-            BusReadFrom::Expansion => 0xFF,
         }
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        if let Some(write_log) = &mut self.write_log {
+            write_log.push(addr);
+        }
+
         let (write_to, real_addr) = Bus::match_address(addr, self.cartridge.is_program_rom_mirrored);
         match write_to {
             BusReadFrom::CpuRam => {self.cpu_ram[real_addr as usize] = data;},
-            BusReadFrom::CartridgeProgramRom => {
-                panic!("Write to cartridge rom detected!")
+            BusReadFrom::Ppu => self.ppu.write_register(real_addr, data),
+            BusReadFrom::Apu | BusReadFrom::ApuStatus => self.apu.write_register(addr, data),
+            BusReadFrom::OamDma => self.oam_dma_page = Some(data),
+            BusReadFrom::Joypad1 => {
+                // the strobe is connected to both controllers
+                self.joypad1.write(data);
+                self.joypad2.write(data);
+            },
+            BusReadFrom::Joypad2 => self.apu.write_register(addr, data),
+            BusReadFrom::CartridgeProgramRam => {self.cartridge.program_ram[real_addr as usize] = data;},
+            BusReadFrom::CartridgeProgramRom | BusReadFrom::Expansion => {
+                // NROM cartridges have no registers, writes to the rom are ignored
             }
-            BusReadFrom::Expansion => {
-                // nop
+        }
+    }
+
+    fn tick(&mut self, cycles: u16) {
+        for _ in 0..cycles {
+            self.ppu_dot_fraction += self.region.ppu_dots_per_five_cpu_cycles();
+            self.ppu.tick(self.ppu_dot_fraction / 5);
+            self.ppu_dot_fraction %= 5;
+            self.apu.tick();
+
+            if let Some(addr) = self.apu.pending_dmc_fetch() {
+                let data = self.mem_peek(addr);
+                self.open_bus = data;
+                self.apu.fill_dmc_sample(data);
             }
         }
     }
-}
\ No newline at end of file
+
+    fn poll_nmi(&mut self) -> bool {
+        self.ppu.poll_nmi()
+    }
+
+    fn poll_irq(&self) -> bool {
+        self.apu.irq()
+    }
+
+    fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma_page.take()
+    }
+
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        Some((self.ppu.scanline, self.ppu.dot))
+    }
+}
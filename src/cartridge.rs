@@ -1,5 +1,7 @@
 extern crate bitflags;
 
+use crate::region::Region;
+
 /* iNES 1.0 format
 +--------+--------------------------------------------+
 | Byte   |                Description                 |
@@ -14,11 +16,15 @@ extern crate bitflags;
 | 10     | Flags 10 (Unused, PRG RAM, TV System)      |
 | 11-15  | Unused (Should be zero-filled)             |
 +--------+--------------------------------------------+
+
+NES 2.0 headers are marked by the bits 2 - 3 of byte 7 being 0b10 and store the timing in byte 12:
+0 NTSC, 1 PAL, 2 multiple regions, 3 Dendy, see https://www.nesdev.org/wiki/NES_2.0#Byte_12_(CPU/PPU_Timing)
  */
 const NES_SIGNATURE: u32 = 0x4E45531A;
 
 const PROGRAM_ROM_PAGE_SIZE: usize = 0x4000;
 const CHARACTER_ROM_PAGE_SIZE: usize = 0x2000;
+const PROGRAM_RAM_SIZE: usize = 0x2000;
 
 /* Flags 6
 +-------+--------------------------------------------+
@@ -103,7 +109,7 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
@@ -113,13 +119,17 @@ pub enum Mirroring {
 pub struct Cartridge {
     pub program_rom: Vec<u8>,
     pub character_rom: Vec<u8>,
+    // the (optionally battery backed) ram at 0x6000 - 0x7FFF
+    pub program_ram: Vec<u8>,
     pub flags_6: Flags6,
     pub flags_7: Flags7,
     pub is_program_rom_mirrored: bool,
+    // the region the game has been made for according to the header
+    pub region: Region,
 }
 
 impl Cartridge {
-    pub fn new (raw_data: &Vec<u8>) -> Result<Cartridge, String> {
+    pub fn new (raw_data: &[u8]) -> Result<Cartridge, String> {
         if raw_data.len() < 16 {
             return Err("File does not seem to be in the correct format".to_string());
         }
@@ -139,16 +149,57 @@ impl Cartridge {
         let program_rom_start = 16 + if flags_6.contains(Flags6::TrainerData) {512} else {0};
         let character_rom_start = program_rom_start + program_rom_size;
 
+        if raw_data.len() < character_rom_start + character_rom_size {
+            return Err(format!("Rom is truncated, expected {} bytes but got {}!", character_rom_start + character_rom_size, raw_data.len()));
+        }
+
         Ok(Cartridge {
             program_rom: raw_data[program_rom_start .. (program_rom_start + program_rom_size)].to_vec(),
             character_rom: raw_data[character_rom_start .. (character_rom_start + character_rom_size)].to_vec(),
+            program_ram: vec![0; PROGRAM_RAM_SIZE],
             flags_6,
             flags_7,
-            is_program_rom_mirrored: raw_data[4] == 1
+            is_program_rom_mirrored: raw_data[4] == 1,
+            region: Cartridge::detect_region(&raw_data[..16]),
         })
     }
+
+    fn detect_region(header: &[u8]) -> Region {
+        if header[7] & 0b0000_1100 == 0b0000_1000 {
+            return match header[12] & 0b11 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            };
+        }
+
+        // old dumps often have garbage like "DiskDude!" in the unused bytes, then flags 9 and 10 are ignored as well
+        if header[11..16].iter().any(|byte| *byte != 0) {
+            return Region::Ntsc;
+        }
+        if header[9] & 0b1 == 1 || header[10] & 0b11 == 2 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    pub fn mapper(&self) -> u8 {
+        (self.flags_7.bits() & 0xF0) | (self.flags_6.bits() >> 4)
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        if self.flags_6.contains(Flags6::FourScreenVRAM) {
+            Mirroring::FourScreen
+        } else if self.flags_6.contains(Flags6::VerticalMirroring) {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
 }
 
+#[cfg(test)]
 pub fn create_test_cartridge(dummy_trainer_data: bool) -> Cartridge {
     let mut raw_data = Vec::new();
     raw_data.extend_from_slice(&NES_SIGNATURE.to_be_bytes());
@@ -157,7 +208,7 @@ pub fn create_test_cartridge(dummy_trainer_data: bool) -> Cartridge {
     let flags_7 = Flags7::from_bits(0b0000_0000).unwrap();
 
     if dummy_trainer_data {
-        flags_6 = flags_6 | Flags6::TrainerData;
+        flags_6 |= Flags6::TrainerData;
     }
 
     let program_rom_pages: u8 = 2;
@@ -9,16 +9,25 @@ ToDo:
 Move to bitflags: https://docs.rs/bitflags/latest/bitflags/
  */
 
+use std::fmt;
+
 use crate::opcodes;
 use crate::mem::Mem;
 use crate::bus::Bus;
+use crate::trace::{BusAccess, BusAccessKind, BusTrace};
 
 const STACK_START: u16 = 0x0100;
-const STACK_SIZE: u16 = 0x0100;
-const STACK_RESET: u8 = 0xFD;
+// the reset sequence decrements the stack pointer by 3 without writing to the stack
+const STACK_RESET_DECREMENT: u8 = 3;
 const STATUS_RESET: u8 = 0b0010_0100;
+const MAGIC_CONSTANT_DEFAULT: u8 = 0xEE;
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+const OAM_DATA_REGISTER: u16 = 0x2004;
 
-pub struct CPU {
+pub struct CPU<M: Mem = Bus> {
     pub register_a: u8,
     // pushes to the stack decrement the stack pointer
     // pulling from it increments it
@@ -33,13 +42,57 @@ pub struct CPU {
     // N   V   -   B   D   I   Z   C
     pub status: u8,
     pub program_counter: u16,
-    pub start_override: u16,
+    // starts execution at the given address instead of the one stored in the RESET vector
+    pub start_address: Option<u16>,
     pub last_mem_write_value: u8,
     pub last_mem_write_value_u16: u16,
     pub last_mem_write_address: u16,
-    bus: Bus,
+    // the unstable opcodes XAA and LXA or the accumulator with a chip specific "magic constant"
+    // before the and operation, 0xEE is the commonly accepted value
+    pub magic_constant: u8,
+    pub variant: CpuVariant,
+    // set once a JAM/KIL opcode has been executed, the cpu stays halted until the next reset
+    pub jammed: bool,
+    // the number of cpu cycles since the last power on, every bus access takes one cycle and ticks the bus
+    pub cycles: u64,
+    // read instructions only do the dummy read of indexed addressing when the indexing crosses a page,
+    // write and read-modify-write instructions always do it
+    dummy_read_on_page_cross_only: bool,
+    bus_trace: Option<BusTrace>,
+    bus: M,
+}
+
+// the NES uses the Ricoh 2A03 which is a NMOS 6502 with the decimal mode disconnected,
+// the NMOS 6502 variant allows to run generic 6502 programs which rely on BCD arithmetic
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CpuVariant {
+    Nes2A03,
+    Nmos6502,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CpuError {
+    // the cpu executed one of the JAM/KIL opcodes and is halted at the given address
+    Jammed { opcode: u8, address: u16 },
+    // the opcode at the given address is not (yet) supported by the emulator
+    UnknownOpcode { opcode: u8, address: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::Jammed { opcode, address } => {
+                write!(f, "CPU jammed by opcode {:02x} at {:04x}", opcode, address)
+            },
+            CpuError::UnknownOpcode { opcode, address } => {
+                write!(f, "OpCode {:02x} at {:04x} is not recognized", opcode, address)
+            },
+        }
+    }
 }
 
+impl std::error::Error for CpuError {}
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum AddressingMode {
     Immediate,
@@ -55,29 +108,33 @@ pub enum AddressingMode {
     NoneAddressing,
 }
 
-impl Mem for CPU {
+impl<M: Mem> Mem for CPU<M> {
     /*
         Memory access
      */
-    fn mem_read(&self, addr: u16) -> u8 {
+    fn mem_read(&mut self, addr: u16) -> u8 {
         self.bus.mem_read(addr)
     }
 
+    fn mem_peek(&self, addr: u16) -> u8 {
+        self.bus.mem_peek(addr)
+    }
+
     fn mem_write(&mut self, addr: u16, data: u8) {
         self.bus.mem_write(addr, data);
         self.last_mem_write_address = addr;
         self.last_mem_write_value = data;
     }
 
-    fn mem_read_u16(&self, addr: u16) -> u16 {
-        u16::from_le_bytes([self.mem_read(addr), self.mem_read(addr + 1)])
+    fn mem_read_u16(&mut self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.mem_read(addr), self.mem_read(addr.wrapping_add(1))])
     }
 
     fn mem_write_u16(&mut self, addr: u16, data: u16) {
         let bytes = data.to_le_bytes();
 
         self.mem_write(addr, bytes[0]);
-        self.mem_write(addr + 1, bytes[1]);
+        self.mem_write(addr.wrapping_add(1), bytes[1]);
 
         self.last_mem_write_address = addr;
         self.last_mem_write_value_u16 = data;
@@ -88,8 +145,12 @@ impl Mem for CPU {
 The NES CPU uses little endian addressing: least significant bits first
  -> real adress 0x8000 is stored as 0x00 0x80
  */
-impl CPU {
-    pub fn new(bus: Bus) -> Self {
+impl<M: Mem> CPU<M> {
+    pub fn new(bus: M) -> Self {
+        CPU::new_with_variant(bus, CpuVariant::Nes2A03)
+    }
+
+    pub fn new_with_variant(bus: M, variant: CpuVariant) -> Self {
         CPU {
             register_a: 0,
             register_s: 0,
@@ -97,258 +158,447 @@ impl CPU {
             register_y: 0,
             status: 0,
             program_counter: 0,
-            start_override: 0,
+            start_address: None,
             last_mem_write_address: 0,
             last_mem_write_value: 0,
             last_mem_write_value_u16: 0,
+            magic_constant: MAGIC_CONSTANT_DEFAULT,
+            variant,
+            jammed: false,
+            cycles: 0,
+            dummy_read_on_page_cross_only: false,
+            bus_trace: None,
             bus,
         }
     }
 
-    pub fn interpret(&mut self, program: Vec<u8>) {
-        self.load_and_run(program, true, 0x0600);
+    pub fn bus(&self) -> &M {
+        &self.bus
     }
 
-    pub fn interpret_without_reset(&mut self, program: Vec<u8>, program_base_address: u16) {
-        self.program_counter = program_base_address;
-        self.load_and_run(program, false, program_base_address);
+    pub fn bus_mut(&mut self) -> &mut M {
+        &mut self.bus
+    }
+
+    pub fn interpret(&mut self, program: Vec<u8>) -> Result<(), CpuError> {
+        self.load_and_run(program, true, 0x0600)
     }
 
-    pub fn load_and_run (&mut self, program: Vec<u8>, reset: bool, program_base_address: u16) {
+    pub fn interpret_without_reset(&mut self, program: Vec<u8>, program_base_address: u16) -> Result<(), CpuError> {
+        self.load_and_run(program, false, program_base_address)
+    }
+
+    // loads the program, optionally powers the cpu on and runs the program from its base address
+    pub fn load_and_run (&mut self, program: Vec<u8>, power_on: bool, program_base_address: u16) -> Result<(), CpuError> {
         self.load(program, program_base_address);
 
-        if reset {
-            self.reset();
+        if power_on {
+            self.power_on();
         }
+        self.program_counter = program_base_address;
 
-        self.run(|_, _|{});
+        self.run(|_, _|{})
     }
 
     pub fn load (&mut self, program: Vec<u8>, program_base_address: u16) {
         for i in 0..(program.len() as u16) {
             self.mem_write(program_base_address + i, program[i as usize]);
         }
-        self.start_override = program_base_address;
     }
 
-    pub fn reset (&mut self) {
+    // the state after switching the console on, see https://www.nesdev.org/wiki/CPU_power_up_state
+    // the stack pointer starts at 0 and ends up at 0xFD after the reset sequence, memory is left untouched
+    pub fn power_on (&mut self) {
         self.register_a = 0;
-        self.register_s = STACK_RESET;
+        self.register_s = 0;
         self.register_x = 0;
         self.register_y = 0;
+        self.status = STATUS_RESET;
         self.last_mem_write_value = 0;
         self.last_mem_write_value_u16 = 0;
         self.last_mem_write_address = 0;
+        self.cycles = 0;
 
-        self.status = STATUS_RESET;
+        self.reset();
+    }
 
-        for i in STACK_START..(STACK_SIZE+STACK_START) {
-            self.mem_write(i, 0);
+    // pressing the reset button only decrements the stack pointer and sets the interrupt disable flag,
+    // the registers and the memory keep their values
+    pub fn reset (&mut self) {
+        self.jammed = false;
+
+        // the reset sequence takes 7 cycles, it runs like an interrupt whose stack writes are turned into reads
+        self.dummy_read(self.program_counter);
+        self.dummy_read(self.program_counter);
+        for _ in 0..STACK_RESET_DECREMENT {
+            self.dummy_read(self.register_s as u16 + STACK_START);
+            self.register_s = self.register_s.wrapping_sub(1);
         }
+        self.set_interrupt_disable_flag();
 
-        if self.start_override != 0 {
-            self.program_counter = self.start_override;
-        } else {
-            self.program_counter = self.mem_read_u16(0xFFFC)
+        let reset_vector = self.read_vector(RESET_VECTOR);
+        self.program_counter = self.start_address.unwrap_or(reset_vector);
+    }
+
+    // advances the cycle counter and all devices attached to the bus
+    fn add_cycles (&mut self, cycles: u16) {
+        self.cycles += cycles as u64;
+        self.bus.tick(cycles);
+    }
+
+    // records every bus access of the cpu from now on, None stops the trace
+    pub fn set_bus_trace (&mut self, bus_trace: Option<BusTrace>) {
+        self.bus_trace = bus_trace;
+    }
+
+    // a bus access of the cpu itself, each one takes a cycle
+    fn read_as (&mut self, addr: u16, kind: BusAccessKind) -> u8 {
+        let value = self.bus.mem_read(addr);
+        self.trace_bus_access(addr, value, false, kind);
+        self.add_cycles(1);
+        value
+    }
+
+    fn write_as (&mut self, addr: u16, data: u8, kind: BusAccessKind) {
+        self.mem_write(addr, data);
+        self.trace_bus_access(addr, data, true, kind);
+        self.add_cycles(1);
+    }
+
+    fn trace_bus_access (&mut self, address: u16, value: u8, write: bool, kind: BusAccessKind) {
+        if let Some(bus_trace) = &mut self.bus_trace {
+            bus_trace(&BusAccess { cycle: self.cycles, address, value, write, kind });
         }
     }
 
-    pub fn run<F> (&mut self, mut callback: F)
+    fn bus_read (&mut self, addr: u16) -> u8 {
+        self.read_as(addr, BusAccessKind::Operand)
+    }
+
+    fn bus_write (&mut self, addr: u16, data: u8) {
+        self.write_as(addr, data, BusAccessKind::Operand);
+    }
+
+    fn bus_read_u16 (&mut self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.bus_read(addr), self.bus_read(addr.wrapping_add(1))])
+    }
+
+    // a read whose value is discarded by the cpu
+    fn dummy_read (&mut self, addr: u16) {
+        self.read_as(addr, BusAccessKind::Dummy);
+    }
+
+    fn read_vector (&mut self, vector: u16) -> u16 {
+        let lo = self.read_as(vector, BusAccessKind::Vector);
+        let hi = self.read_as(vector.wrapping_add(1), BusAccessKind::Vector);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    // read-modify-write instructions write the unmodified value back while they modify it
+    fn read_for_modify (&mut self, addr: u16) -> u8 {
+        let value = self.bus_read(addr);
+        self.write_as(addr, value, BusAccessKind::Dummy);
+        value
+    }
+
+    // runs until a BRK instruction is hit, BRK does not trigger the interrupt but returns instead
+    pub fn run<F> (&mut self, mut callback: F) -> Result<(), CpuError>
     where 
-        F: FnMut(&mut CPU, &&opcodes::OpCode),
+        F: FnMut(&mut CPU<M>, &&opcodes::OpCode),
     {
-        let ref opcodes = *opcodes::OPCODES_MAP;
-
-        loop {
-            let code = self.mem_read(self.program_counter);
-
-            let opcode = opcodes.get(&code).expect(
-                &format!("OpCode {:x} is not recognized!", code)
-            );
-
-            callback(self, opcode);
-
-            self.program_counter += 1;
-
-            let program_counter_state = self.program_counter;
-
-            match code {
-                0x61 | 0x65 | 0x69 | 0x6D | 0x71 | 0x75 | 0x79 | 0x7D => {
-                    self.adc(&opcode.mode);
-                },
-                0x0B | 0x2B => {
-                    self.anc(&opcode.mode);
-                },
-                0x21 | 0x25 | 0x29 | 0x2D | 0x31 | 0x35 | 0x39 | 0x3D => {
-                    self.and(&opcode.mode);
-                },
-                0x06 | 0x0A | 0x0E | 0x16 | 0x1E => {
-                    self.asl(&opcode.mode);
-                },
-                0x90 => self.bcc(&opcode.mode),
-                0xB0 => self.bcs(&opcode.mode),
-                0xF0 => self.beq(&opcode.mode),
-                0x24 | 0x2C => {
-                    self.bit(&opcode.mode);
-                }
-                0x30 => self.bmi(&opcode.mode),
-                0xD0 => self.bne(&opcode.mode),
-                0x10 => self.bpl(&opcode.mode),
-                0x50 => self.bvc(&opcode.mode),
-                0x70 => self.bvs(&opcode.mode),
-                0x18 => self.clc(),
-                0xD8 => self.cld(),
-                0x58 => self.cli(),
-                0xB8 => self.clv(),
-                0xC1 | 0xC5 | 0xC9 | 0xCD | 0xD1 | 0xD5 | 0xD9 | 0xDD => {
-                    self.cmp(&opcode.mode);
-                },
-                0xE0 | 0xE4 | 0xEC => {
-                    self.cpx(&opcode.mode);
-                },
-                0xC0 | 0xC4 | 0xCC => {
-                    self.cpy(&opcode.mode);
-                },
-                0xC7| 0xD7| 0xCF| 0xDF| 0xDB| 0xC3| 0xD3 => self.dcp(&opcode.mode),
-                0xC6 | 0xCE | 0xD6 | 0xDE => {
-                    self.dec(&opcode.mode);
-                },
-                0xCA => self.dex(),
-                0x88 => self.dey(),
-                0x41 | 0x45 | 0x49 | 0x4D | 0x51 | 0x55 | 0x59 | 0x5D => {
-                    self.eor(&opcode.mode);
-                },
-                0xE6 | 0xEE | 0xF6 | 0xFE => {
-                     self.inc(&opcode.mode);
-                },
-                0xE8 => self.inx(),
-                0xC8 => self.iny(),
-                0xE7 | 0xF7 | 0xEF | 0xFF | 0xFB | 0xE3 | 0xF3 => {
-                    self.isb(&opcode.mode);
-                }
-                0x4C | 0x6C => {
-                    self.jmp(&opcode.mode);
-                },
-                0x20 => {
-                    self.jsr(&opcode.mode);
-                },
-                0xA7| 0xB7| 0xAF| 0xBF| 0xA3| 0xB3 => {
-                    self.lax(&opcode.mode);
-                },
-                0xA1 |0xA5 | 0xA9 | 0xAD | 0xB1 | 0xB5 | 0xB9 | 0xBD => {
-                    self.lda(&opcode.mode);
-                },
-                0xA2 | 0xA6 | 0xAE | 0xB6 | 0xBE  => {
-                    self.ldx(&opcode.mode);
-                },
-                0xA0 | 0xA4 | 0xAC | 0xB4 | 0xBC  => {
-                    self.ldy(&opcode.mode);
-                },
-                0x46 | 0x4A | 0x4E | 0x56 | 0x5E => {
-                    self.lsr(&opcode.mode);
-                },
-                // the "normal nop"
-                0xEA => self.nop(),
-                // illegal nop opcodes
-                0x1A| 0x3A| 0x5A| 0x7A| 0xDA| 0xFA => self.nop(),
-                // the illegal opcode dops = double no operation
-                0x04| 0x14| 0x34| 0x44| 0x54| 0x64| 0x74| 0x80| 0x82| 0x89| 0xC2| 0xD4| 0xE2| 0xF4 => self.nop(),
-                // illegal top opcodes = triple no operation
-                0x0C| 0x1C| 0x3C| 0x5C| 0x7C| 0xDC| 0xFC => self.nop(),
-                0x01 | 0x05 | 0x09 | 0x0D | 0x11 | 0x15 | 0x19 | 0x1D => {
-                    self.ora(&opcode.mode);
-                },
-                0x48 => self.pha(),
-                0x08 => self.php(),
-                0x68 => self.pla(),
-                0x28 => self.plp(),
-                0x27 | 0x37 | 0x2F | 0x3F | 0x3B | 0x23 | 0x33 => {
-                    self.rla(&opcode.mode);
-                }
-                0x26 | 0x2A | 0x2E | 0x36 | 0x3E => {
-                    self.rol(&opcode.mode);
-                },
-                0x66 | 0x6A | 0x6E | 0x76 | 0x7E => {
-                    self.ror(&opcode.mode);
-                },
-                0x67 | 0x77 | 0x6F | 0x7F | 0x7B | 0x63 | 0x73 => {
-                    self.rra(&opcode.mode);
-                }
-                0x40 => {
-                    self.rti();
-                },
-                0x60 => {
-                    self.rts();
-                },
-                0x87 | 0x97 | 0x83 | 0x8F => {
-                    self.sax(&opcode.mode);
-                },
-                0xEB => self.sbc(&opcode.mode),
-                0xE1 | 0xE5 | 0xE9 | 0xED | 0xF1 | 0xF5 | 0xF9 | 0xFD => {
-                    self.sbc(&opcode.mode);
-                }
-                0x38 => {
-                    self.sec();
-                },
-                0xF8 => {
-                    self.sed();
-                },
-                0x78 => {
-                    self.sei();
-                },
-                0x03 | 0x07 | 0x0F | 0x13 | 0x17 | 0x1B | 0x1F => {
-                    self.slo(&opcode.mode);
-                },
-                0x47 | 0x57 | 0x4F | 0x5F | 0x5B | 0x43 | 0x53 => {
-                    self.sre(&opcode.mode);
-                }
-                0x81 | 0x85 | 0x8D | 0x91 | 0x95 | 0x99 | 0x9D => {
-                    self.sta(&opcode.mode);
-                },
-                0x86 | 0x8E | 0x96 => {
-                    self.stx(&opcode.mode);
-                },
-                0x84 | 0x8C | 0x94 => {
-                    self.sty(&opcode.mode);
-                },
-                0xAA => self.tax(),
-                0xA8 => self.tay(),
-                0xBA => self.tsx(),
-                0x8A => self.txa(),
-                0x9A => self.txs(),
-                0x98 => self.tya(),
-                0x00 => return,
-                _ => todo!()
-            }
+        while self.execute_instruction(&mut callback, true)? {}
+
+        Ok(())
+    }
+
+    // executes exactly one instruction, BRK is executed like on the real hardware
+    pub fn step<F> (&mut self, mut callback: F) -> Result<(), CpuError>
+    where 
+        F: FnMut(&mut CPU<M>, &&opcodes::OpCode),
+    {
+        self.execute_instruction(&mut callback, false)?;
+
+        Ok(())
+    }
+
+    // returns false in case a BRK was hit and return_on_brk is set
+    fn execute_instruction<F> (&mut self, callback: &mut F, return_on_brk: bool) -> Result<bool, CpuError>
+    where 
+        F: FnMut(&mut CPU<M>, &&opcodes::OpCode),
+    {
+        let opcodes = &*opcodes::OPCODES_MAP;
+
+        if self.jammed {
+            let code = self.mem_peek(self.program_counter);
+            return Err(CpuError::Jammed { opcode: code, address: self.program_counter });
+        }
+
+        // interrupts are checked between instructions, the NMI has priority over the IRQ
+        if self.bus.poll_nmi() {
+            self.interrupt(NMI_VECTOR);
+        } else if self.bus.poll_irq() && !self.is_interrupt_disable_flag_set() {
+            self.interrupt(IRQ_VECTOR);
+        }
+
+        let code = self.mem_peek(self.program_counter);
+
+        let opcode = match opcodes.get(&code) {
+            Some(opcode) => opcode,
+            None => return Err(CpuError::UnknownOpcode { opcode: code, address: self.program_counter }),
+        };
+
+        callback(self, opcode);
+
+        // the BRK which ends the program is not executed, the program counter is left right after it
+        if return_on_brk && code == 0x00 {
+            self.program_counter = self.program_counter.wrapping_add(1);
+            return Ok(false);
+        }
+
+        self.read_as(self.program_counter, BusAccessKind::OpcodeFetch);
+        self.program_counter = self.program_counter.wrapping_add(1);
+
+        // single byte instructions read the byte after the opcode and discard it
+        if opcode.len == 1 {
+            self.dummy_read(self.program_counter);
+        }
 
-            if self.program_counter == program_counter_state {
-                self.program_counter += (opcode.len - 1) as u16
+        self.dummy_read_on_page_cross_only = opcode.has_page_cross_penalty();
+
+        match code {
+            0x61 | 0x65 | 0x69 | 0x6D | 0x71 | 0x75 | 0x79 | 0x7D => {
+                self.adc(&opcode.mode);
+            },
+            0x4B => self.alr(&opcode.mode),
+            0x0B | 0x2B => {
+                self.anc(&opcode.mode);
+            },
+            0x21 | 0x25 | 0x29 | 0x2D | 0x31 | 0x35 | 0x39 | 0x3D => {
+                self.and(&opcode.mode);
+            },
+            0x6B => self.arr(&opcode.mode),
+            0x06 | 0x0A | 0x0E | 0x16 | 0x1E => {
+                self.asl(&opcode.mode);
+            },
+            0xCB => self.axs(&opcode.mode),
+            0x90 => self.bcc(&opcode.mode),
+            0xB0 => self.bcs(&opcode.mode),
+            0xF0 => self.beq(&opcode.mode),
+            0x24 | 0x2C => {
+                self.bit(&opcode.mode);
+            }
+            0x30 => self.bmi(&opcode.mode),
+            0xD0 => self.bne(&opcode.mode),
+            0x10 => self.bpl(&opcode.mode),
+            0x50 => self.bvc(&opcode.mode),
+            0x70 => self.bvs(&opcode.mode),
+            0x18 => self.clc(),
+            0xD8 => self.cld(),
+            0x58 => self.cli(),
+            0xB8 => self.clv(),
+            0xC1 | 0xC5 | 0xC9 | 0xCD | 0xD1 | 0xD5 | 0xD9 | 0xDD => {
+                self.cmp(&opcode.mode);
+            },
+            0xE0 | 0xE4 | 0xEC => {
+                self.cpx(&opcode.mode);
+            },
+            0xC0 | 0xC4 | 0xCC => {
+                self.cpy(&opcode.mode);
+            },
+            0xC7| 0xD7| 0xCF| 0xDF| 0xDB| 0xC3| 0xD3 => self.dcp(&opcode.mode),
+            0xC6 | 0xCE | 0xD6 | 0xDE => {
+                self.dec(&opcode.mode);
+            },
+            0xCA => self.dex(),
+            0x88 => self.dey(),
+            0x41 | 0x45 | 0x49 | 0x4D | 0x51 | 0x55 | 0x59 | 0x5D => {
+                self.eor(&opcode.mode);
+            },
+            0xE6 | 0xEE | 0xF6 | 0xFE => {
+                 self.inc(&opcode.mode);
+            },
+            0xE8 => self.inx(),
+            0xC8 => self.iny(),
+            0xE7 | 0xF7 | 0xEF | 0xFF | 0xFB | 0xE3 | 0xF3 => {
+                self.isb(&opcode.mode);
             }
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                // the program counter stays on the jam opcode, just like the real cpu which stops fetching
+                self.program_counter = self.program_counter.wrapping_sub(1);
+                self.jammed = true;
+                return Err(CpuError::Jammed { opcode: code, address: self.program_counter });
+            },
+            0x4C | 0x6C => {
+                self.jmp(&opcode.mode);
+            },
+            0x20 => {
+                self.jsr();
+            },
+            0xBB => self.las(&opcode.mode),
+            0xA7| 0xB7| 0xAF| 0xBF| 0xA3| 0xB3 => {
+                self.lax(&opcode.mode);
+            },
+            0xA1 |0xA5 | 0xA9 | 0xAD | 0xB1 | 0xB5 | 0xB9 | 0xBD => {
+                self.lda(&opcode.mode);
+            },
+            0xA2 | 0xA6 | 0xAE | 0xB6 | 0xBE  => {
+                self.ldx(&opcode.mode);
+            },
+            0xA0 | 0xA4 | 0xAC | 0xB4 | 0xBC  => {
+                self.ldy(&opcode.mode);
+            },
+            0x46 | 0x4A | 0x4E | 0x56 | 0x5E => {
+                self.lsr(&opcode.mode);
+            },
+            0xAB => self.lxa(&opcode.mode),
+            // the "normal nop"
+            0xEA => self.nop(&opcode.mode),
+            // illegal nop opcodes
+            0x1A| 0x3A| 0x5A| 0x7A| 0xDA| 0xFA => self.nop(&opcode.mode),
+            // the illegal opcode dops = double no operation
+            0x04| 0x14| 0x34| 0x44| 0x54| 0x64| 0x74| 0x80| 0x82| 0x89| 0xC2| 0xD4| 0xE2| 0xF4 => self.nop(&opcode.mode),
+            // illegal top opcodes = triple no operation
+            0x0C| 0x1C| 0x3C| 0x5C| 0x7C| 0xDC| 0xFC => self.nop(&opcode.mode),
+            0x01 | 0x05 | 0x09 | 0x0D | 0x11 | 0x15 | 0x19 | 0x1D => {
+                self.ora(&opcode.mode);
+            },
+            0x48 => self.pha(),
+            0x08 => self.php(),
+            0x68 => self.pla(),
+            0x28 => self.plp(),
+            0x27 | 0x37 | 0x2F | 0x3F | 0x3B | 0x23 | 0x33 => {
+                self.rla(&opcode.mode);
+            }
+            0x26 | 0x2A | 0x2E | 0x36 | 0x3E => {
+                self.rol(&opcode.mode);
+            },
+            0x66 | 0x6A | 0x6E | 0x76 | 0x7E => {
+                self.ror(&opcode.mode);
+            },
+            0x67 | 0x77 | 0x6F | 0x7F | 0x7B | 0x63 | 0x73 => {
+                self.rra(&opcode.mode);
+            }
+            0x40 => {
+                self.rti();
+            },
+            0x60 => {
+                self.rts();
+            },
+            0x87 | 0x97 | 0x83 | 0x8F => {
+                self.sax(&opcode.mode);
+            },
+            0xEB => self.sbc(&opcode.mode),
+            0xE1 | 0xE5 | 0xE9 | 0xED | 0xF1 | 0xF5 | 0xF9 | 0xFD => {
+                self.sbc(&opcode.mode);
+            }
+            0x38 => {
+                self.sec();
+            },
+            0xF8 => {
+                self.sed();
+            },
+            0x78 => {
+                self.sei();
+            },
+            0x93 | 0x9F => self.sha(&opcode.mode),
+            0x9E => self.shx(&opcode.mode),
+            0x9C => self.shy(&opcode.mode),
+            0x03 | 0x07 | 0x0F | 0x13 | 0x17 | 0x1B | 0x1F => {
+                self.slo(&opcode.mode);
+            },
+            0x47 | 0x57 | 0x4F | 0x5F | 0x5B | 0x43 | 0x53 => {
+                self.sre(&opcode.mode);
+            }
+            0x81 | 0x85 | 0x8D | 0x91 | 0x95 | 0x99 | 0x9D => {
+                self.sta(&opcode.mode);
+            },
+            0x86 | 0x8E | 0x96 => {
+                self.stx(&opcode.mode);
+            },
+            0x84 | 0x8C | 0x94 => {
+                self.sty(&opcode.mode);
+            },
+            0x9B => self.tas(&opcode.mode),
+            0xAA => self.tax(),
+            0xA8 => self.tay(),
+            0xBA => self.tsx(),
+            0x8A => self.txa(),
+            0x9A => self.txs(),
+            0x98 => self.tya(),
+            0x8B => self.xaa(&opcode.mode),
+            0x00 => self.brk(),
+        }
+
+        // the branches, jumps, returns and BRK set the program counter themselves,
+        // all other instructions continue after their operands
+        if !matches!(code, 0x00 | 0x10 | 0x20 | 0x30 | 0x40 | 0x4C | 0x50 | 0x60 | 0x6C | 0x70 | 0x90 | 0xB0 | 0xD0 | 0xF0) {
+            self.program_counter = self.program_counter.wrapping_add((opcode.len - 1) as u16);
+        }
+
+        if let Some(page) = self.bus.take_oam_dma() {
+            self.oam_dma(page);
+        }
+
+        Ok(true)
+    }
+
+    // pushes the program counter and the status (with the B flag cleared) and jumps to the handler of the interrupt,
+    // the sequence starts with two reads of the next opcode which is discarded
+    fn interrupt (&mut self, vector: u16) {
+        self.dummy_read(self.program_counter);
+        self.dummy_read(self.program_counter);
+        self.push_stack_u16(self.program_counter);
+        self.push_stack((self.status & 0b1110_1111) | 0b0010_0000);
+        self.set_interrupt_disable_flag();
+
+        self.program_counter = self.read_vector(vector);
+    }
+
+    // a write to $4014 copies a whole page to the PPU OAM, the cpu is suspended during the transfer,
+    // it halts for one cycle, takes one additional alignment cycle when started on an odd cycle,
+    // then every byte takes a read and a write cycle
+    fn oam_dma (&mut self, page: u8) {
+        let alignment = (self.cycles % 2) as u16;
+        self.add_cycles(1 + alignment);
+
+        let base_address = (page as u16) << 8;
+        for i in 0..0x100 {
+            let value = self.read_as(base_address + i, BusAccessKind::Dma);
+            self.write_as(OAM_DATA_REGISTER, value, BusAccessKind::Dma);
         }
     }
 
     /*
     Stack Operations
      */
+    // the high byte is pushed first, so the value ends up little endian on the stack
     fn push_stack_u16(&mut self, data: u16) {
-        self.mem_write_u16((self.register_s - 1) as u16 + 0x0100, data);
-        self.register_s = self.register_s.wrapping_sub(2);
+        let bytes = data.to_le_bytes();
+        self.push_stack(bytes[1]);
+        self.push_stack(bytes[0]);
+
+        self.last_mem_write_value_u16 = data;
     }
 
     fn pop_stack_u16(&mut self) -> u16 {
-        let value = self.mem_read_u16((self.register_s + 1) as u16 + 0x0100);
-        self.register_s = self.register_s.wrapping_add(2);
+        let lo = self.pop_stack();
+        let hi = self.pop_stack();
 
-        value
+        u16::from_le_bytes([lo, hi])
+    }
+
+    // pulling takes an extra cycle in which the cpu reads the stack before incrementing the stack pointer
+    fn pull_stack_dummy_read(&mut self) {
+        self.dummy_read(self.register_s as u16 + STACK_START);
     }
 
     fn push_stack(&mut self, data: u8) {
-        self.mem_write((self.register_s) as u16 + 0x0100, data);
+        self.write_as(self.register_s as u16 + STACK_START, data, BusAccessKind::Stack);
         self.register_s = self.register_s.wrapping_sub(1);
     }
 
     fn pop_stack(&mut self) -> u8 {
-        let value = self.mem_read((self.register_s + 1) as u16 + 0x0100);
+        let value = self.read_as(self.register_s.wrapping_add(1) as u16 + STACK_START, BusAccessKind::Stack);
         self.register_s = self.register_s.wrapping_add(1);
 
         value
@@ -359,6 +609,14 @@ impl CPU {
      */
 
     fn add_to_register_a_with_carry(&mut self, value: u8, carry: u8) {
+        if self.is_decimal_arithmetic_enabled() {
+            self.add_to_register_a_with_carry_decimal(value, carry);
+        } else {
+            self.add_to_register_a_with_carry_binary(value, carry);
+        }
+    }
+
+    fn add_to_register_a_with_carry_binary(&mut self, value: u8, carry: u8) {
         let result_u16 = value as u16 + self.register_a as u16 + carry as u16;
 
         if result_u16 > 0xFF {
@@ -375,23 +633,115 @@ impl CPU {
             self.clear_overflow_flag();
         }
 
-        self.register_a = result as u8;
+        self.register_a = result;
         self.set_neg_and_zero_flag(self.register_a);
     }
 
+    // decimal addition as done by the NMOS 6502, see http://www.6502.org/tutorials/decimal_mode.html#A
+    // the zero flag is taken from the binary addition, negative and overflow flag from the
+    // intermediate result before the high nibble is corrected
+    fn add_to_register_a_with_carry_decimal(&mut self, value: u8, carry: u8) {
+        let a = self.register_a as u16;
+        let b = value as u16;
+        let c = carry as u16;
+
+        let mut lo = (a & 0x0F) + (b & 0x0F) + c;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+
+        let mut result = (a & 0xF0) + (b & 0xF0) + lo;
+
+        if result & 0x80 != 0 {
+            self.set_neg_flag();
+        } else {
+            self.clear_neg_flag();
+        }
+
+        if !(a ^ b) & (a ^ result) & 0x80 != 0 {
+            self.set_overflow_flag();
+        } else {
+            self.clear_overflow_flag();
+        }
+
+        if (a + b + c) as u8 == 0 {
+            self.set_zero_flag();
+        } else {
+            self.clear_zero_flag();
+        }
+
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+
+        if result > 0xFF {
+            self.set_carry();
+        } else {
+            self.clear_carry();
+        }
+
+        self.register_a = result as u8;
+    }
+
+    // A - M - (1 - C)
+    // the binary subtraction uses two complement arithmetic
+    // therefore we build the two complement of the number to be subtracted
+    // the two complement is built by inverting all bits and adding 1 to
+    // the result, this one cancels with the one from the (1- C) term
+    fn subtract_from_register_a_with_carry(&mut self, value: u8, carry: u8) {
+        if !self.is_decimal_arithmetic_enabled() {
+            self.add_to_register_a_with_carry_binary(!value, carry);
+            return;
+        }
+
+        // on the NMOS 6502 all flags of a decimal subtraction are the ones of the binary subtraction
+        // see http://www.6502.org/tutorials/decimal_mode.html#A
+        let a = self.register_a as i16;
+        let b = value as i16;
+        let c = carry as i16;
+
+        let mut lo = (a & 0x0F) - (b & 0x0F) + c - 1;
+        if lo < 0 {
+            lo = ((lo - 0x06) & 0x0F) - 0x10;
+        }
+
+        let mut result = (a & 0xF0) - (b & 0xF0) + lo;
+        if result < 0 {
+            result -= 0x60;
+        }
+
+        self.add_to_register_a_with_carry_binary(!value, carry);
+
+        self.register_a = result as u8;
+    }
+
     /*
         OP Codes
      */
     fn adc (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
 
         self.add_to_register_a_with_carry(value, self.status & 0b0000_0001);
     }
 
+    fn alr (&mut self, mode: &AddressingMode) {
+        let address = self.get_operand_address(mode);
+        let value = self.bus_read(address) & self.register_a;
+
+        if value & 0b0000_0001 > 0 {
+            self.set_carry();
+        } else {
+            self.clear_carry();
+        }
+
+        self.register_a = value >> 1;
+        self.set_neg_and_zero_flag(self.register_a);
+    }
+
     fn anc (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
         let result = self.register_a & value;
 
         self.register_a = result;
@@ -406,13 +756,82 @@ impl CPU {
 
     fn and (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
         let result = self.register_a & value;
 
         self.register_a = result;
         self.set_neg_and_zero_flag(result);
     }
 
+    // arr: and followed by ror, but the carry is taken from bit 6 of the result
+    // and the overflow flag is bit 6 xor bit 5 of the result
+    fn arr (&mut self, mode: &AddressingMode) {
+        let address = self.get_operand_address(mode);
+        let value = self.bus_read(address) & self.register_a;
+
+        if self.is_decimal_arithmetic_enabled() {
+            self.arr_decimal(value);
+            return;
+        }
+
+        let result = (value >> 1) | ((self.status & 0b0000_0001) << 7);
+        self.register_a = result;
+        self.set_neg_and_zero_flag(result);
+
+        if result & 0b0100_0000 > 0 {
+            self.set_carry();
+        } else {
+            self.clear_carry();
+        }
+
+        if ((result >> 6) ^ (result >> 5)) & 0b0000_0001 > 0 {
+            self.set_overflow_flag();
+        } else {
+            self.clear_overflow_flag();
+        }
+    }
+
+    // in decimal mode the NMOS 6502 applies a bcd fixup to the rotated value
+    // see "No More Secrets" - NMOS 6510 Unintended Opcodes
+    fn arr_decimal (&mut self, value: u8) {
+        let hi = value >> 4;
+        let lo = value & 0x0F;
+        let carry = self.status & 0b0000_0001;
+
+        let mut result = (value >> 1) | (carry << 7);
+
+        if carry > 0 {
+            self.set_neg_flag();
+        } else {
+            self.clear_neg_flag();
+        }
+
+        if result == 0 {
+            self.set_zero_flag();
+        } else {
+            self.clear_zero_flag();
+        }
+
+        if (value ^ result) & 0b0100_0000 > 0 {
+            self.set_overflow_flag();
+        } else {
+            self.clear_overflow_flag();
+        }
+
+        if lo + (lo & 0b0000_0001) > 5 {
+            result = (result & 0xF0) | (result.wrapping_add(6) & 0x0F);
+        }
+
+        if hi + (hi & 0b0000_0001) > 5 {
+            result = result.wrapping_add(0x60);
+            self.set_carry();
+        } else {
+            self.clear_carry();
+        }
+
+        self.register_a = result;
+    }
+
     fn asl (&mut self, mode: &AddressingMode) {
         if *mode == AddressingMode::NoneAddressing {
             let value = self.register_a;
@@ -423,15 +842,25 @@ impl CPU {
             self.set_neg_and_zero_flag(result);
         } else {
             let address = self.get_operand_address(mode);
-            let value = self.mem_read(address);
+            let value = self.read_for_modify(address);
 
             let result = self.shift_left_and_set_carry(value);
 
-            self.mem_write(address, result);
+            self.bus_write(address, result);
             self.set_neg_and_zero_flag(result);
         }
     }
 
+    // axs (also known as sbx): X = (A & X) - M, the flags are set like cmp does
+    fn axs (&mut self, mode: &AddressingMode) {
+        let address = self.get_operand_address(mode);
+        let value = self.bus_read(address);
+        let reference = self.register_a & self.register_x;
+
+        self.compare_and_set_flags(reference, value);
+        self.register_x = reference.wrapping_sub(value);
+    }
+
     fn bcc (&mut self, mode: &AddressingMode) {
         self.branch(mode, !self.is_carry_flag_set());
     }
@@ -442,7 +871,7 @@ impl CPU {
 
     fn bit (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
 
         let result = self.register_a & value;
 
@@ -452,8 +881,8 @@ impl CPU {
             self.clear_zero_flag();
         }
 
-        self.status = self.status & 0b0011_1111;
-        self.status = self.status | (value & 0b1100_0000);
+        self.status &= 0b0011_1111;
+        self.status |= value & 0b1100_0000;
     }
 
     fn beq (&mut self, mode: &AddressingMode) {
@@ -480,6 +909,15 @@ impl CPU {
         self.branch(mode,self.is_overflow_flag_set());
     }
 
+    // brk pushes the address of the instruction after the padding byte and the status with the B flag set
+    fn brk (&mut self) {
+        self.push_stack_u16(self.program_counter.wrapping_add(1));
+        self.push_stack(self.status | 0b0011_0000);
+        self.set_interrupt_disable_flag();
+
+        self.program_counter = self.read_vector(IRQ_VECTOR);
+    }
+
     fn clc (&mut self) {
         self.clear_carry();
     }
@@ -498,40 +936,40 @@ impl CPU {
 
     fn cmp (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
         
         self.compare_and_set_flags(self.register_a, value);
     }
 
     fn cpx (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
         
         self.compare_and_set_flags(self.register_x, value);
     }
 
     fn cpy (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
         
         self.compare_and_set_flags(self.register_y, value);
     }
 
     fn dcp (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address).wrapping_sub(1);
+        let value = self.read_for_modify(address).wrapping_sub(1);
 
-        self.mem_write(address, value);
+        self.bus_write(address, value);
         self.compare_and_set_flags(self.register_a, value);
     }
 
     fn dec (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.read_for_modify(address);
 
         let result = value.wrapping_sub(1);
 
-        self.mem_write(address, result);
+        self.bus_write(address, result);
         self.set_neg_and_zero_flag(result);
     }
 
@@ -547,19 +985,19 @@ impl CPU {
 
     fn eor (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
 
-        self.register_a = value ^ self.register_a;
+        self.register_a ^= value;
         self.set_neg_and_zero_flag(self.register_a);
     }
 
     fn inc (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.read_for_modify(address);
 
         let result = value.wrapping_add(1);
 
-        self.mem_write(address, result);
+        self.bus_write(address, result);
         self.set_neg_and_zero_flag(result);
     }
 
@@ -581,14 +1019,14 @@ impl CPU {
 
     fn isb (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.read_for_modify(address);
 
         let value_incremented = value.wrapping_add(1);
         let carry = self.status & 0b0000_0001;
 
-        self.mem_write(address, value_incremented);
+        self.bus_write(address, value_incremented);
 
-        self.add_to_register_a_with_carry(!value_incremented, carry);
+        self.subtract_from_register_a_with_carry(value_incremented, carry);
     }
 
     // jumps
@@ -601,16 +1039,31 @@ impl CPU {
         self.program_counter = addr;
     }
 
-    fn jsr (&mut self, mode: &AddressingMode) {
-        let return_address = self.program_counter + 1;
+    // jsr reads the low byte of the target, pushes the return address and reads the high byte afterwards
+    fn jsr (&mut self) {
+        let lo = self.bus_read(self.program_counter);
+        self.pull_stack_dummy_read();
+
+        let return_address = self.program_counter.wrapping_add(1);
         self.push_stack_u16(return_address);
 
-        self.jmp(mode)
+        let hi = self.bus_read(return_address);
+        self.program_counter = u16::from_le_bytes([lo, hi]);
+    }
+
+    fn las (&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let value = self.bus_read(addr) & self.register_s;
+
+        self.register_a = value;
+        self.register_x = value;
+        self.register_s = value;
+        self.set_neg_and_zero_flag(value);
     }
 
     fn lax (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        self.register_a = self.mem_read(addr);
+        self.register_a = self.bus_read(addr);
         self.register_x = self.register_a;
         self.set_neg_and_zero_flag(self.register_a);
     }
@@ -618,19 +1071,19 @@ impl CPU {
     // ld* operations
     fn lda (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        self.register_a = self.mem_read(addr);
+        self.register_a = self.bus_read(addr);
         self.set_neg_and_zero_flag(self.register_a);
     }
 
     fn ldx (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        self.register_x = self.mem_read(addr);
+        self.register_x = self.bus_read(addr);
         self.set_neg_and_zero_flag(self.register_x);
     }
 
     fn ldy (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        self.register_y = self.mem_read(addr);
+        self.register_y = self.bus_read(addr);
         self.set_neg_and_zero_flag(self.register_y);
     }
 
@@ -639,7 +1092,7 @@ impl CPU {
         let mut address = 0;
         if *mode != AddressingMode::NoneAddressing {
             address = self.get_operand_address(mode);
-            value = self.mem_read(address);
+            value = self.read_for_modify(address);
         }
 
         let new_carry = value & 0b0000_0001;
@@ -650,21 +1103,34 @@ impl CPU {
         if *mode == AddressingMode::NoneAddressing {
             self.register_a = result;
         } else {
-            self.mem_write(address, result);
+            self.bus_write(address, result);
         }
 
         self.set_neg_and_zero_flag(result);
     }
 
-    fn nop (&mut self) {
-        return;
+    fn lxa (&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let value = (self.register_a | self.magic_constant) & self.bus_read(addr);
+
+        self.register_a = value;
+        self.register_x = value;
+        self.set_neg_and_zero_flag(value);
+    }
+
+    // the nops with an operand read it like any other read instruction
+    fn nop (&mut self, mode: &AddressingMode) {
+        if *mode != AddressingMode::NoneAddressing {
+            let address = self.get_operand_address(mode);
+            self.bus_read(address);
+        }
     }
 
     fn ora (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = self.mem_read(address);
+        let value = self.bus_read(address);
 
-        self.register_a = value | self.register_a;
+        self.register_a |= value;
         self.set_neg_and_zero_flag(self.register_a);
     }
 
@@ -678,20 +1144,26 @@ impl CPU {
     }
 
     fn pla (&mut self) {
+        self.pull_stack_dummy_read();
         self.register_a = self.pop_stack();
         self.set_neg_and_zero_flag(self.register_a);
     }
 
     fn plp (&mut self) {
+        self.pull_stack_dummy_read();
+        self.pull_status();
+    }
+
+    fn pull_status (&mut self) {
         self.status = (self.pop_stack() & 0b1110_1111) | 0b0010_0000;
     }
 
     fn rla (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
+        let value = self.read_for_modify(addr);
 
         let roled_value = (value << 1) + (self.status & 0b0000_0001);
-        self.mem_write(addr, roled_value);
+        self.bus_write(addr, roled_value);
 
         if (value & 0b1000_0000) > 0 {
             self.set_carry();
@@ -700,7 +1172,7 @@ impl CPU {
         }
 
         self.set_neg_and_zero_flag(roled_value);
-        self.register_a = self.register_a & roled_value;
+        self.register_a &= roled_value;
     }
 
     fn rol (&mut self, mode: &AddressingMode) {
@@ -708,7 +1180,7 @@ impl CPU {
         let mut address = 0;
         if *mode != AddressingMode::NoneAddressing {
             address = self.get_operand_address(mode);
-            value = self.mem_read(address);
+            value = self.read_for_modify(address);
         }
 
         let new_carry = (value & 0b1000_0000) >> 7;
@@ -719,7 +1191,7 @@ impl CPU {
         if *mode == AddressingMode::NoneAddressing {
             self.register_a = result;
         } else {
-            self.mem_write(address, result);
+            self.bus_write(address, result);
         }
 
         self.set_neg_and_zero_flag(result);
@@ -730,7 +1202,7 @@ impl CPU {
         let mut address = 0;
         if *mode != AddressingMode::NoneAddressing {
             address = self.get_operand_address(mode);
-            value = self.mem_read(address);
+            value = self.read_for_modify(address);
         }
 
         let new_carry = value & 0b0000_0001;
@@ -741,7 +1213,7 @@ impl CPU {
         if *mode == AddressingMode::NoneAddressing {
             self.register_a = result;
         } else {
-            self.mem_write(address, result);
+            self.bus_write(address, result);
         }
 
         self.set_neg_and_zero_flag(result);
@@ -749,10 +1221,10 @@ impl CPU {
 
     fn rra (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
+        let value = self.read_for_modify(addr);
 
         let result = (value >> 1) + if self.is_carry_flag_set() {0x80} else {0x00};
-        self.mem_write(addr, result);
+        self.bus_write(addr, result);
 
         if (value & 0b0000_0001) > 0 {
             self.set_carry();
@@ -768,35 +1240,35 @@ impl CPU {
     // jump and interrupt returns
 
     fn rti (&mut self) {
-        self.plp();
+        self.pull_stack_dummy_read();
+        self.pull_status();
 
         let return_address = self.pop_stack_u16();
         self.program_counter = return_address;
     }
 
+    // rts reads the pulled address once more while it increments it
     fn rts (&mut self) {
+        self.pull_stack_dummy_read();
         let return_address = self.pop_stack_u16();
-        self.program_counter = return_address + 1;
+        self.dummy_read(return_address);
+        self.program_counter = return_address.wrapping_add(1);
     }
 
     fn sax (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
         let result = self.register_x & self.register_a;
 
-        self.mem_write(address, result);
+        self.bus_write(address, result);
     }
 
     // sbc: A - M - (1 - C)
-    // sbc uses two complement arithmetic
-    // therefore we build the two complement of the number to be subtracted
-    // the two complement is built by inverting all bits and adding 1 to
-    // the result, this one cancels with the one from the (1- C) term
     fn sbc (&mut self, mode: &AddressingMode) {
         let address = self.get_operand_address(mode);
-        let value = !self.mem_read(address);
+        let value = self.bus_read(address);
 
         let carry = self.status & 0b0000_0001;
-        self.add_to_register_a_with_carry(value, carry);
+        self.subtract_from_register_a_with_carry(value, carry);
     }
 
     fn sec (&mut self) {
@@ -811,9 +1283,21 @@ impl CPU {
         self.set_interrupt_disable_flag();
     }
 
+    fn sha (&mut self, mode: &AddressingMode) {
+        self.store_and_high_byte(mode, self.register_a & self.register_x);
+    }
+
+    fn shx (&mut self, mode: &AddressingMode) {
+        self.store_and_high_byte(mode, self.register_x);
+    }
+
+    fn shy (&mut self, mode: &AddressingMode) {
+        self.store_and_high_byte(mode, self.register_y);
+    }
+
     fn slo (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
+        let value = self.read_for_modify(addr);
 
         if value & 0b1000_0000 != 0 {
             self.set_carry();
@@ -823,7 +1307,7 @@ impl CPU {
 
         let shifted_value = value << 1;
 
-        self.mem_write(addr, shifted_value);
+        self.bus_write(addr, shifted_value);
 
         let result = shifted_value | self.register_a;
         self.register_a = result;
@@ -832,10 +1316,10 @@ impl CPU {
 
     fn sre (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
+        let value = self.read_for_modify(addr);
 
         let lsred_value = value >> 1;
-        self.mem_write(addr, lsred_value);
+        self.bus_write(addr, lsred_value);
 
         if (value & 0b0000_0001) > 0 {
             self.set_carry();
@@ -852,17 +1336,22 @@ impl CPU {
     // st* operations
     fn sta (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        self.mem_write(addr, self.register_a);
+        self.bus_write(addr, self.register_a);
     }
 
     fn stx (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        self.mem_write(addr, self.register_x);
+        self.bus_write(addr, self.register_x);
     }
 
     fn sty (&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        self.mem_write(addr, self.register_y);
+        self.bus_write(addr, self.register_y);
+    }
+
+    fn tas (&mut self, mode: &AddressingMode) {
+        self.register_s = self.register_a & self.register_x;
+        self.store_and_high_byte(mode, self.register_s);
     }
 
     // t** operations
@@ -900,45 +1389,58 @@ impl CPU {
         self.set_neg_and_zero_flag(self.register_y);
     }
 
+    fn xaa (&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let value = (self.register_a | self.magic_constant) & self.register_x & self.bus_read(addr);
+
+        self.register_a = value;
+        self.set_neg_and_zero_flag(value);
+    }
+
     /*
         Helper functions
      */
     fn branch (&mut self, mode: &AddressingMode, branch: bool) {
+        let address = self.get_operand_address(mode);
+        let value = self.bus_read(address);
+
+        // the offset is a signed byte relative to the address of the next instruction
+        let next_instruction = self.program_counter.wrapping_add(1);
+        self.program_counter = next_instruction;
         if branch {
-            let address = self.get_operand_address(mode);
-            let value = self.mem_read(address);
+            self.program_counter = next_instruction.wrapping_add(value as i8 as u16);
 
-            if value > 127 {
-                self.program_counter += 1 + (value as u16);
-                self.program_counter -= 256;
-            } else {
-                self.program_counter += 1 + value as u16;
+            // a taken branch reads the next opcode once more, in case it jumps to a different page
+            // the first target address is read without the page fixup
+            self.dummy_read(next_instruction);
+            if next_instruction & 0xFF00 != self.program_counter & 0xFF00 {
+                self.dummy_read((next_instruction & 0xFF00) | (self.program_counter & 0x00FF));
             }
         }
     }
 
     fn clear_carry(&mut self) {
-        self.status = self.status & 0b1111_1110;
+        self.status &= 0b1111_1110;
     }
 
     fn clear_decimal_mode_flag(&mut self) {
-        self.status = self.status & 0b1111_0111;
+        self.status &= 0b1111_0111;
     }
 
     fn clear_interrupt_disable_flag(&mut self) {
-        self.status = self.status & 0b1111_1011;
+        self.status &= 0b1111_1011;
     }
 
     fn clear_neg_flag(&mut self) {
-        self.status = self.status & 0b0111_1111;
+        self.status &= 0b0111_1111;
     }
 
     fn clear_overflow_flag (&mut self) {
-        self.status = self.status & 0b1011_1111;
+        self.status &= 0b1011_1111;
     }
 
     fn clear_zero_flag(&mut self) {
-        self.status = self.status & 0b1111_1101;
+        self.status &= 0b1111_1101;
     }
 
     fn compare_and_set_flags(&mut self, reference: u8, value: u8) {
@@ -969,6 +1471,10 @@ impl CPU {
         self.status & 0b0000_1000 > 0
     }
 
+    fn is_decimal_arithmetic_enabled (&self) -> bool {
+        self.variant == CpuVariant::Nmos6502 && self.is_decimal_mode_flag_set()
+    }
+
     fn is_interrupt_disable_flag_set (&self) -> bool {
         self.status & 0b0000_0100 > 0
     }
@@ -986,27 +1492,27 @@ impl CPU {
     }
 
     fn set_carry(&mut self) {
-        self.status = self.status | 0b0000_0001;
+        self.status |= 0b0000_0001;
     }
 
     fn set_decimal_mode_flag (&mut self) {
-        self.status = self.status | 0b0000_1000;
+        self.status |= 0b0000_1000;
     }
 
     fn set_interrupt_disable_flag (&mut self) {
-        self.status = self.status | 0b0000_0100;
+        self.status |= 0b0000_0100;
     }
 
     fn set_neg_flag(&mut self) {
-        self.status = self.status | 0b1000_0000;
+        self.status |= 0b1000_0000;
     }
 
     fn set_overflow_flag(&mut self) {
-        self.status = self.status | 0b0100_0000;
+        self.status |= 0b0100_0000;
     }
 
     fn set_zero_flag(&mut self) {
-        self.status = self.status | 0b0000_0010;
+        self.status |= 0b0000_0010;
     }
 
     fn set_neg_and_zero_flag(&mut self, result_value: u8) {
@@ -1035,27 +1541,39 @@ impl CPU {
         result
     }
 
-    fn disable_interrupt (&mut self) {
-        self.status = self.status | 0b0000_0100;
-    }
+    // SHA, SHX, SHY and TAS store the value and'ed with the high byte of the base address + 1
+    // in case the indexing crosses a page, the high byte of the target address is replaced by the stored value
+    fn store_and_high_byte(&mut self, mode: &AddressingMode, value: u8) {
+        let (base_address, index) = match mode {
+            AddressingMode::AbsoluteX => (self.bus_read_u16(self.program_counter), self.register_x),
+            AddressingMode::AbsoluteY => (self.bus_read_u16(self.program_counter), self.register_y),
+            AddressingMode::IndirectY => (self.read_indirect_pointer(), self.register_y),
+            _ => panic!("mode {:?} is not supported", mode),
+        };
+
+        let result = value & ((base_address >> 8) as u8).wrapping_add(1);
+        let mut address = self.add_index(base_address, index);
+        if address & 0xFF00 != base_address & 0xFF00 {
+            address = ((result as u16) << 8) | (address & 0x00FF);
+        }
 
-    fn enable_interrupt (&mut self) {
-        self.status = self.status & 0b1111_1011;
+        self.bus_write(address, result);
     }
 
+    // operand addresses are resolved with side effect free reads, used for tracing
     pub fn get_absolute_address (&self, mode: &AddressingMode, address: u16) -> u16 {
         match mode {
-            AddressingMode::Absolute => self.mem_read_u16(address),
+            AddressingMode::Absolute => self.mem_peek_u16(address),
             AddressingMode::AbsoluteX => {
-                let base_address = self.mem_read_u16(address);
+                let base_address = self.mem_peek_u16(address);
                 base_address.wrapping_add(self.register_x as u16)
             },
             AddressingMode::AbsoluteY => {
-                let base_address = self.mem_read_u16(address);
+                let base_address = self.mem_peek_u16(address);
                 base_address.wrapping_add(self.register_y as u16)
             },
             AddressingMode::Indirect => {
-                let indirect_read_address = self.mem_read_u16(address);
+                let indirect_read_address = self.mem_peek_u16(address);
 
                 // in indirect mode, which only the jmp instruction uses the 6502 wraps around the lo byte
                 // the indirect addressing for the jmp instruction was implemented like this to save
@@ -1063,35 +1581,35 @@ impl CPU {
                 let read_address_lo = indirect_read_address as u8;
                 let read_address_hi = indirect_read_address & 0xFF00;
 
-                let lo = self.mem_read(indirect_read_address);
-                let hi = self.mem_read(read_address_hi + (read_address_lo.wrapping_add(1) as u16));
+                let lo = self.mem_peek(indirect_read_address);
+                let hi = self.mem_peek(read_address_hi + (read_address_lo.wrapping_add(1) as u16));
 
                 (lo as u16) + ((hi as u16) << 8)
             },
             AddressingMode::IndirectX => {
-                let base_address = self.mem_read(address).wrapping_add(self.register_x);
+                let base_address = self.mem_peek(address).wrapping_add(self.register_x);
                 // documentation is unclear on how a value of  0xFF would be handled, whether it
                 // is a read from  0xFF and  0x0100 or whether it is a wrapped read from  0xFF and  0x00
-                let lo = self.mem_read(base_address as u16);
-                let hi = self.mem_read(base_address.wrapping_add(1) as u16);
+                let lo = self.mem_peek(base_address as u16);
+                let hi = self.mem_peek(base_address.wrapping_add(1) as u16);
                 (lo as u16) + ((hi as u16) << 8)
             },
             AddressingMode::IndirectY => {
-                let base_address = self.mem_read(address);
+                let base_address = self.mem_peek(address);
                 // documentation is unclear on how a value of  0xFF would be handled, whether it
                 // is a read from  0xFF and  0x0100 or whether it is a wrapped read from  0xFF and  0x00
-                let lo = self.mem_read(base_address as u16);
-                let hi = self.mem_read(base_address.wrapping_add(1) as u16);
+                let lo = self.mem_peek(base_address as u16);
+                let hi = self.mem_peek(base_address.wrapping_add(1) as u16);
                 let indirect_address = (lo as u16) + ((hi as u16) << 8);
                 indirect_address.wrapping_add(self.register_y as u16)
             },
-            AddressingMode::ZeroPage => self.mem_read(address) as u16,
+            AddressingMode::ZeroPage => self.mem_peek(address) as u16,
             AddressingMode::ZeroPageX => {
-                let base_address = self.mem_read(address);
+                let base_address = self.mem_peek(address);
                 base_address.wrapping_add(self.register_x) as u16
             },
             AddressingMode::ZeroPageY => {
-                let base_address = self.mem_read(address);
+                let base_address = self.mem_peek(address);
                 base_address.wrapping_add(self.register_y) as u16
             },
             AddressingMode::NoneAddressing | AddressingMode::Immediate  => {
@@ -1100,14 +1618,76 @@ impl CPU {
         }
     }
 
-    fn get_operand_address (&self, mode: &AddressingMode) -> u16 {
+    // resolves the operand address with the same bus accesses as the real cpu, including its dummy reads,
+    // see https://www.nesdev.org/6502_cpu.txt
+    fn get_operand_address (&mut self, mode: &AddressingMode) -> u16 {
         match mode {
             // use the value right after the opcode
             AddressingMode::Immediate | AddressingMode::NoneAddressing => self.program_counter,
+            AddressingMode::ZeroPage => self.bus_read(self.program_counter) as u16,
+            // zero page indexing reads the unindexed address while adding the index
+            AddressingMode::ZeroPageX => {
+                let base_address = self.bus_read(self.program_counter);
+                self.dummy_read(base_address as u16);
+                base_address.wrapping_add(self.register_x) as u16
+            },
+            AddressingMode::ZeroPageY => {
+                let base_address = self.bus_read(self.program_counter);
+                self.dummy_read(base_address as u16);
+                base_address.wrapping_add(self.register_y) as u16
+            },
+            AddressingMode::Absolute => self.bus_read_u16(self.program_counter),
+            AddressingMode::AbsoluteX => {
+                let base_address = self.bus_read_u16(self.program_counter);
+                self.add_index(base_address, self.register_x)
+            },
+            AddressingMode::AbsoluteY => {
+                let base_address = self.bus_read_u16(self.program_counter);
+                self.add_index(base_address, self.register_y)
+            },
+            AddressingMode::Indirect => {
+                let indirect_read_address = self.bus_read_u16(self.program_counter);
 
-            // for all other cases parsing is more complicated
-            _ => self.get_absolute_address(mode, self.program_counter)
+                // the high byte is read from the same page, see get_absolute_address
+                let lo = self.bus_read(indirect_read_address);
+                let hi = self.bus_read((indirect_read_address & 0xFF00) | ((indirect_read_address as u8).wrapping_add(1) as u16));
+                u16::from_le_bytes([lo, hi])
+            },
+            AddressingMode::IndirectX => {
+                let pointer = self.bus_read(self.program_counter);
+                self.dummy_read(pointer as u16);
+
+                let pointer = pointer.wrapping_add(self.register_x);
+                let lo = self.bus_read(pointer as u16);
+                let hi = self.bus_read(pointer.wrapping_add(1) as u16);
+                u16::from_le_bytes([lo, hi])
+            },
+            AddressingMode::IndirectY => {
+                let base_address = self.read_indirect_pointer();
+                self.add_index(base_address, self.register_y)
+            },
         }
     }
 
+    // reads the zero page pointer of the (indirect),Y addressing and the address it points to
+    fn read_indirect_pointer (&mut self) -> u16 {
+        let pointer = self.bus_read(self.program_counter);
+        let lo = self.bus_read(pointer as u16);
+        let hi = self.bus_read(pointer.wrapping_add(1) as u16);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    // the cpu adds the index to the low byte first and reads from the resulting address before it fixes
+    // the high byte, read instructions skip the second read in case no page is crossed
+    fn add_index (&mut self, base_address: u16, index: u8) -> u16 {
+        let address = base_address.wrapping_add(index as u16);
+        let unfixed_address = (base_address & 0xFF00) | (address & 0x00FF);
+
+        if unfixed_address != address || !self.dummy_read_on_page_cross_only {
+            self.dummy_read(unfixed_address);
+        }
+
+        address
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,186 @@
+use std::io;
+use std::str::FromStr;
+
+use crate::palette::Palette;
+use crate::png;
+use crate::region::Region;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+// the number of pixels cut off at each edge of the frame, TVs hide a part of the picture behind their bezel,
+// the fields are private so that at least one pixel of the frame is always left
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Overscan {
+    top: usize,
+    bottom: usize,
+    left: usize,
+    right: usize,
+}
+
+impl Overscan {
+    pub const NONE: Overscan = Overscan { top: 0, bottom: 0, left: 0, right: 0 };
+    // most NTSC TVs do not show the first and the last 8 scanlines
+    pub const NTSC: Overscan = Overscan { top: 8, bottom: 8, left: 0, right: 0 };
+    // the PAL PPU blanks the first scanline and the 2 pixels at the left and the right edge itself
+    pub const PAL: Overscan = Overscan { top: 1, bottom: 0, left: 2, right: 2 };
+
+    pub fn new(top: usize, bottom: usize, left: usize, right: usize) -> Result<Overscan, String> {
+        if top + bottom >= HEIGHT || left + right >= WIDTH {
+            return Err(format!("the overscan {},{},{},{} leaves nothing of the frame", top, bottom, left, right));
+        }
+        Ok(Overscan { top, bottom, left, right })
+    }
+
+    // what a TV of the region shows, PAL TVs show the whole picture, only the PAL PPU blanks a part of it
+    pub fn for_region(region: Region) -> Overscan {
+        match region {
+            Region::Ntsc => Overscan::NTSC,
+            Region::Pal => Overscan::PAL,
+            Region::Dendy => Overscan::NONE,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        WIDTH - self.left - self.right
+    }
+
+    pub fn height(&self) -> usize {
+        HEIGHT - self.top - self.bottom
+    }
+}
+
+// "top,bottom,left,right" in pixels, e.g. "8,8,0,0", or "none"
+impl FromStr for Overscan {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "none" {
+            return Ok(Overscan::NONE);
+        }
+        let edges = value.split(',')
+            .map(|edge| edge.trim().parse::<usize>().map_err(|_| format!("{} is not a number", edge)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let [top, bottom, left, right] = edges[..] else {
+            return Err(format!("{} is not top,bottom,left,right", value));
+        };
+        Overscan::new(top, bottom, left, right)
+    }
+}
+
+// every pixel holds the 6 bit palette index in bits 0-5 and the PPUMASK color emphasis bits in bits 6-8,
+// which is all the information the real PPU puts into the video signal
+pub struct Frame {
+    pub pixels: Vec<u16>,
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Frame {
+            pixels: vec![0; WIDTH * HEIGHT],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u16) {
+        self.pixels[y * WIDTH + x] = value;
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * WIDTH + x]
+    }
+
+    // converts the frame into RGB24 data, the palette includes the emphasized colors
+    pub fn to_rgb(&self, palette: &Palette) -> Vec<u8> {
+        let mut result = Vec::with_capacity(WIDTH * HEIGHT * 3);
+        for pixel in &self.pixels {
+            let (r, g, b) = palette.color(*pixel);
+            result.extend_from_slice(&[r, g, b]);
+        }
+        result
+    }
+
+    pub fn to_image(&self, palette: &Palette) -> Image {
+        Image::new(WIDTH, HEIGHT, self.to_rgb(palette))
+    }
+
+    // converts the frame into RGB24 data with the overscan cut off and every pixel scaled up,
+    // the result has overscan.width() * scale x overscan.height() * scale pixels
+    pub fn to_rgb_cropped(&self, palette: &Palette, overscan: Overscan, scale: usize) -> Vec<u8> {
+        self.to_image(palette).crop(overscan).scale(scale).pixels
+    }
+
+    // FNV-1a hash of the pixels, allows to compare frames without looking at them
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for pixel in &self.pixels {
+            for byte in pixel.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    // saves the frame as PNG file, see to_rgb_cropped
+    pub fn save_png(&self, path: &str, palette: &Palette, overscan: Overscan, scale: usize) -> io::Result<()> {
+        self.to_image(palette).crop(overscan).scale(scale).save_png(path)
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::new()
+    }
+}
+
+// an RGB24 image of the frame as it is presented, which may be wider than the frame, e.g. after the NTSC filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<u8>) -> Self {
+        debug_assert_eq!(pixels.len(), width * height * 3);
+        Image { width, height, pixels }
+    }
+
+    // cuts off the overscan, which is given in pixels of the frame and scaled to the size of the image
+    pub fn crop(&self, overscan: Overscan) -> Image {
+        let left = overscan.left * self.width / WIDTH;
+        let top = overscan.top * self.height / HEIGHT;
+        let width = overscan.width() * self.width / WIDTH;
+        let height = overscan.height() * self.height / HEIGHT;
+
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in top..top + height {
+            let start = (y * self.width + left) * 3;
+            pixels.extend_from_slice(&self.pixels[start..start + width * 3]);
+        }
+        Image::new(width, height, pixels)
+    }
+
+    // scales every pixel up to a square of scale x scale pixels
+    pub fn scale(&self, scale: usize) -> Image {
+        let width = self.width * scale;
+        let mut pixels = Vec::with_capacity(width * self.height * scale * 3);
+        for row in self.pixels.chunks(self.width * 3) {
+            let mut scaled_row = Vec::with_capacity(width * 3);
+            for pixel in row.chunks(3) {
+                for _ in 0..scale {
+                    scaled_row.extend_from_slice(pixel);
+                }
+            }
+            for _ in 0..scale {
+                pixels.extend_from_slice(&scaled_row);
+            }
+        }
+        Image::new(width, self.height * scale, pixels)
+    }
+
+    pub fn save_png(&self, path: &str) -> io::Result<()> {
+        png::save_rgb(path, self.width, self.height, &self.pixels)
+    }
+}
@@ -0,0 +1,69 @@
+/*
+Regression tests against golden images: selected frames of a run are compared pixel by pixel with PNG files
+which have been committed before. A mismatch writes the actual frame and a diff image next to the golden image,
+e.g. "smb_120.actual.png" and "smb_120.diff.png" for "smb_120.png", in which the differing pixels are red.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use crate::frame::{Frame, Overscan, HEIGHT, WIDTH};
+use crate::palette::Palette;
+use crate::png;
+
+const DIFF_COLOR: (u8, u8, u8) = (0xFF, 0x00, 0x00);
+
+#[derive(Debug, PartialEq)]
+pub enum GoldenResult {
+    Match,
+    // the golden image has been written with the frame
+    Updated,
+    Mismatch { differing_pixels: usize, actual_path: String, diff_path: String },
+}
+
+// the golden image of a frame, e.g. "golden/smb_120.png"
+pub fn golden_path(directory: &str, name: &str, frame: u64) -> String {
+    Path::new(directory).join(format!("{}_{}.png", name, frame)).to_string_lossy().to_string()
+}
+
+// the matching pixels are drawn darkened, the differing ones red
+pub fn diff_image(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    let mut diff = Vec::with_capacity(actual.len());
+    for (expected, actual) in expected.chunks(3).zip(actual.chunks(3)) {
+        if expected == actual {
+            diff.extend(expected.iter().map(|value| value / 4));
+        } else {
+            diff.extend_from_slice(&[DIFF_COLOR.0, DIFF_COLOR.1, DIFF_COLOR.2]);
+        }
+    }
+    diff
+}
+
+// compares the frame with the golden image, with update the golden image is (re)written instead,
+// the default palette is used, so the golden images do not depend on the palette selected for the run
+pub fn check_frame(frame: &Frame, path: &str, update: bool) -> Result<GoldenResult, String> {
+    let actual = frame.to_rgb_cropped(&Palette::default(), Overscan::NONE, 1);
+    if update {
+        png::save_rgb(path, WIDTH, HEIGHT, &actual).map_err(|error| format!("{}: {}", path, error))?;
+        return Ok(GoldenResult::Updated);
+    }
+
+    let golden = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+    let (width, height, expected) = png::decode_rgb(&golden).map_err(|error| format!("{}: {}", path, error))?;
+    if (width, height) != (WIDTH, HEIGHT) {
+        return Err(format!("{}: the golden image has {}x{} instead of {}x{} pixels", path, width, height, WIDTH, HEIGHT));
+    }
+
+    if expected == actual {
+        return Ok(GoldenResult::Match);
+    }
+
+    let differing_pixels = expected.chunks(3).zip(actual.chunks(3)).filter(|(expected, actual)| expected != actual).count();
+    let stem = path.strip_suffix(".png").unwrap_or(path);
+    let actual_path = format!("{}.actual.png", stem);
+    let diff_path = format!("{}.diff.png", stem);
+    png::save_rgb(&actual_path, WIDTH, HEIGHT, &actual).map_err(|error| format!("{}: {}", actual_path, error))?;
+    png::save_rgb(&diff_path, WIDTH, HEIGHT, &diff_image(&expected, &actual)).map_err(|error| format!("{}: {}", diff_path, error))?;
+
+    Ok(GoldenResult::Mismatch { differing_pixels, actual_path, diff_path })
+}
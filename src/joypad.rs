@@ -0,0 +1,64 @@
+/*
+Standard controller, see https://www.nesdev.org/wiki/Standard_controller
+Writing 1 to $4016 keeps the shift register reloaded (strobe), writing 0 afterwards allows
+to read the button states one after another in the order of the bits below
+ */
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub struct JoypadButton : u8 {
+        const A = 0b0000_0001;
+        const B = 0b0000_0010;
+        const Select = 0b0000_0100;
+        const Start = 0b0000_1000;
+        const Up = 0b0001_0000;
+        const Down = 0b0010_0000;
+        const Left = 0b0100_0000;
+        const Right = 0b1000_0000;
+    }
+}
+
+pub struct Joypad {
+    strobe: bool,
+    button_index: u8,
+    pub buttons: JoypadButton,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            strobe: false,
+            button_index: 0,
+            buttons: JoypadButton::empty(),
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 0b0000_0001 > 0;
+        if self.strobe {
+            self.button_index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let result = self.peek();
+        if !self.strobe && self.button_index < 8 {
+            self.button_index += 1;
+        }
+        result
+    }
+
+    // after all 8 buttons have been read, official controllers return 1
+    pub fn peek(&self) -> u8 {
+        if self.button_index > 7 {
+            return 1;
+        }
+        (self.buttons.bits() >> self.button_index) & 0b0000_0001
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
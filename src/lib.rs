@@ -0,0 +1,32 @@
+// the register and chip names (CPU, PPU, APU) are kept in upper case like in the documentation
+#![allow(clippy::upper_case_acronyms)]
+
+pub(crate) mod apu;
+pub(crate) mod bus;
+pub(crate) mod cartridge;
+pub(crate) mod cpu;
+pub(crate) mod font;
+pub mod frame;
+pub mod golden;
+pub mod joypad;
+pub mod mem;
+pub mod memory_viewer;
+pub mod movie;
+pub mod nes;
+pub mod ntsc;
+pub(crate) mod opcodes;
+pub mod pacing;
+pub mod palette;
+pub mod png;
+pub(crate) mod ppu;
+pub mod ppu_viewer;
+pub mod recorder;
+pub mod region;
+pub mod scaler;
+pub mod screenshot;
+pub mod trace;
+pub mod video;
+
+#[cfg(test)]
+mod json;
+mod test;
@@ -1,132 +1,621 @@
+use std::env;
+use std::fmt::Display;
 use std::fs;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Instant, SystemTime};
 
-use bus::Bus;
-use cartridge::Cartridge;
-use rand::Rng;
-use sdl2::event::Event;
+use sdl2::audio::AudioSpecDesired;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::EventPump;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
 
-use cpu::CPU;
-use mem::Mem;
-use trace::trace;
+use rust_nes::frame::{Image, Overscan};
+use rust_nes::joypad::JoypadButton;
+use rust_nes::mem::RamPattern;
+use rust_nes::memory_viewer::{self, MemoryViewer};
+use rust_nes::movie::{Movie, MovieFrame};
+use rust_nes::nes::{Nes, SAMPLE_RATE};
+use rust_nes::ntsc::{NtscFilter, NtscSettings};
+use rust_nes::pacing::{self, FrameLimiter};
+use rust_nes::palette::Palette;
+use rust_nes::ppu_viewer;
+use rust_nes::recorder::AviRecorder;
+use rust_nes::region::Region;
+use rust_nes::screenshot;
+use rust_nes::trace::{TraceFormat, Tracer};
+use rust_nes::video::Video;
 
-mod bus;
-mod cpu;
-mod mem;
-mod opcodes;
-mod cartridge;
-mod test;
-mod trace;
+// the default integer scale of the window, the NTSC filter outputs a larger image and is not scaled by default
+const SCALE: usize = 3;
 
-fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
+fn map_key(keycode: Keycode) -> Option<JoypadButton> {
+    match keycode {
+        Keycode::Up => Some(JoypadButton::Up),
+        Keycode::Down => Some(JoypadButton::Down),
+        Keycode::Left => Some(JoypadButton::Left),
+        Keycode::Right => Some(JoypadButton::Right),
+        Keycode::A => Some(JoypadButton::A),
+        Keycode::S => Some(JoypadButton::B),
+        Keycode::Space => Some(JoypadButton::Select),
+        Keycode::Return => Some(JoypadButton::Start),
+        _ => None,
+    }
+}
+
+// the file the last traced instructions are written to in case the emulation fails
+const CRASH_TRACE_FILE: &str = "crash_trace.log";
+// the prefix of the files the PPU viewer exports, e.g. ppu_nametables.png
+const PPU_EXPORT_PREFIX: &str = "ppu";
+
+// F12 saves the frame as PNG file named after the rom, with the overscan of the window cut off
+struct ScreenshotSettings {
+    scale: usize,
+}
+
+// the state of the debugging tools of the frontend
+struct Debugger {
+    // screenshots and recordings are named after the rom
+    rom_path: String,
+    // the palette and the filter of the window and the screenshots, the recordings use the palette,
+    // all of them cut off its overscan
+    video: Video,
+    paused: bool,
+    // runs a single frame while paused
+    frame_advance: bool,
+    // Tab fast-forwards while it is held, Backspace toggles the slow motion
+    fast_forward: bool,
+    slow_motion: bool,
+    memory_viewer: MemoryViewer,
+    memory_viewer_visible: bool,
+    memory_window_id: u32,
+    // the palette the pattern tables are drawn with
+    ppu_viewer_palette: u8,
+    ppu_viewer_visible: bool,
+    ppu_window_id: u32,
+    screenshot: ScreenshotSettings,
+    recorder: Option<AviRecorder<BufWriter<File>>>,
+}
+
+// F9 starts a recording named after the rom or finishes the running one
+fn toggle_recording(debugger: &mut Debugger, region: Region) {
+    match debugger.recorder.take() {
+        Some(recorder) => {
+            let frames = recorder.frames();
+            match recorder.finish() {
+                Ok(_) => println!("recorded {} frames", frames),
+                Err(error) => println!("the recording could not be finished: {}", error),
+            }
+        },
+        None => {
+            let file = screenshot::file_name(&debugger.rom_path, SystemTime::now(), "avi");
+            let (width, height) = debugger.video.cropped_size();
+            match AviRecorder::create(&file, region, width, height) {
+                Ok(recorder) => {
+                    println!("recording to {}", file);
+                    debugger.recorder = Some(recorder);
+                },
+                Err(error) => println!("the recording could not be started: {}", error),
+            }
+        },
+    }
+}
+
+// keys of the memory viewer window: Tab switches the memory region, the arrow and page keys move the cursor,
+// while paused the hex digits edit the byte at the cursor
+fn handle_memory_viewer_key(keycode: Keycode, debugger: &mut Debugger, nes: &mut Nes) {
+    let page = (memory_viewer::BYTES_PER_ROW * memory_viewer::VISIBLE_ROWS) as isize;
+    match keycode {
+        Keycode::Tab => debugger.memory_viewer.next_region(),
+        Keycode::Left => debugger.memory_viewer.move_cursor(-1),
+        Keycode::Right => debugger.memory_viewer.move_cursor(1),
+        Keycode::Up => debugger.memory_viewer.move_cursor(-(memory_viewer::BYTES_PER_ROW as isize)),
+        Keycode::Down => debugger.memory_viewer.move_cursor(memory_viewer::BYTES_PER_ROW as isize),
+        Keycode::PageUp => debugger.memory_viewer.move_cursor(-page),
+        Keycode::PageDown => debugger.memory_viewer.move_cursor(page),
+        _ => {
+            if let Ok(nibble) = u8::from_str_radix(&keycode.name(), 16) {
+                if debugger.paused && keycode.name().len() == 1 {
+                    debugger.memory_viewer.enter_nibble(nes, nibble);
+                }
+            }
+        },
+    }
+}
+
+// keys of the PPU viewer window: Tab switches the palette of the pattern tables, E exports the views as PNG files
+fn handle_ppu_viewer_key(keycode: Keycode, debugger: &mut Debugger, nes: &Nes) {
+    match keycode {
+        Keycode::Tab => debugger.ppu_viewer_palette = (debugger.ppu_viewer_palette + 1) % ppu_viewer::PALETTE_COUNT,
+        Keycode::E => match ppu_viewer::export(nes.ppu(), debugger.ppu_viewer_palette, PPU_EXPORT_PREFIX) {
+            Ok(files) => println!("exported {}", files.join(", ")),
+            Err(error) => println!("the PPU views could not be exported: {}", error),
+        },
+        _ => {},
+    }
+}
+
+// returns false once the user wants to quit, F1 shows the memory viewer, F2 toggles the tracing,
+// F3 shows the PPU viewer, F5 switches the scaler, F6 toggles the scanlines, F7 the 8:7 aspect ratio,
+// F9 starts and stops a recording, F12 takes a screenshot, P pauses, N advances a single frame (and pauses),
+// Tab fast-forwards while it is held and Backspace toggles the slow motion
+fn handle_user_input(buttons: &mut JoypadButton, event_pump: &mut EventPump, tracer: &mut Tracer, debugger: &mut Debugger, nes: &mut Nes) -> bool {
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                std::process::exit(0);
+                return false;
+            },
+            Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                if window_id == debugger.memory_window_id {
+                    debugger.memory_viewer_visible = false;
+                } else if window_id == debugger.ppu_window_id {
+                    debugger.ppu_viewer_visible = false;
+                } else {
+                    return false;
+                }
+            },
+            Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
+                debugger.memory_viewer_visible = !debugger.memory_viewer_visible;
+            },
+            Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
+                tracer.set_enabled(!tracer.is_enabled());
+                println!("tracing {}", if tracer.is_enabled() { "enabled" } else { "disabled" });
+            },
+            Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
+                debugger.ppu_viewer_visible = !debugger.ppu_viewer_visible;
+            },
+            Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                debugger.video.scaler = debugger.video.scaler.next();
+                println!("scaler {}", debugger.video.scaler.name());
+            },
+            Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                debugger.video.scanlines = !debugger.video.scanlines;
+            },
+            Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                debugger.video.aspect_correction = !debugger.video.aspect_correction;
+            },
+            Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                toggle_recording(debugger, nes.region());
+            },
+            Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                let settings = &debugger.screenshot;
+                let image = debugger.video.render(nes.frame_buffer(), nes.ppu().frame_count);
+                match screenshot::save(&image, &debugger.rom_path, debugger.video.overscan, settings.scale) {
+                    Ok(file) => println!("saved screenshot {}", file),
+                    Err(error) => println!("the screenshot could not be saved: {}", error),
+                }
+            },
+            Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                debugger.paused = !debugger.paused;
+            },
+            Event::KeyDown { keycode: Some(Keycode::N), .. } => {
+                debugger.paused = true;
+                debugger.frame_advance = true;
+            },
+            Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                debugger.slow_motion = !debugger.slow_motion;
+            },
+            Event::KeyDown { keycode: Some(keycode), window_id, .. } if window_id == debugger.memory_window_id => {
+                handle_memory_viewer_key(keycode, debugger, nes);
+            },
+            Event::KeyDown { keycode: Some(keycode), window_id, .. } if window_id == debugger.ppu_window_id => {
+                handle_ppu_viewer_key(keycode, debugger, nes);
+            },
+            // Tab has its own meaning in the viewer windows
+            Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
+                debugger.fast_forward = true;
+            },
+            Event::KeyUp { keycode: Some(Keycode::Tab), .. } => {
+                debugger.fast_forward = false;
+            },
+            Event::KeyDown { keycode: Some(keycode), .. } => {
+                if let Some(button) = map_key(keycode) {
+                    buttons.insert(button);
+                }
+            },
+            Event::KeyUp { keycode: Some(keycode), .. } => {
+                if let Some(button) = map_key(keycode) {
+                    buttons.remove(button);
+                }
             },
-            Event::KeyDown { keycode: Some(Keycode::W), .. } => {
-                cpu.mem_write(0xff, 0x77);
-            }
-            Event::KeyDown { keycode: Some(Keycode::S), .. } => {
-                cpu.mem_write(0xff, 0x73);
-            }
-            Event::KeyDown { keycode: Some(Keycode::A), .. } => {
-                cpu.mem_write(0xff, 0x61);
-            }
-            Event::KeyDown { keycode: Some(Keycode::D), .. } => {
-                cpu.mem_write(0xff, 0x64);
-            }
             _ => {}
         }
     }
+
+    true
 }
 
-fn color (byte: u8) -> Color {
-    match byte {
-        0 => sdl2::pixels::Color::BLACK,
-        1 => sdl2::pixels::Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
+// the next frame of the played movie, the movie is dropped once it has finished
+fn next_movie_frame(playback: &mut Option<(Movie, usize)>) -> Option<MovieFrame> {
+    let (movie, position) = playback.as_mut()?;
+    match movie.frames.get(*position) {
+        Some(frame) => {
+            *position += 1;
+            Some(*frame)
+        },
+        None => {
+            println!("the movie has finished after {} frames", position);
+            *playback = None;
+            None
+        },
     }
 }
 
-fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200 ..0x600 {
-        let color_idx = cpu.mem_read(i as u16);
-        let (b1, b2, b3) = color(color_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
-        }
-        frame_idx += 3;
+// shows the image in the main window, which takes the size of the image
+fn show_image<'a>(canvas: &mut Canvas<Window>, creator: &'a TextureCreator<WindowContext>, texture: &mut Texture<'a>, image: &Image) {
+    let query = texture.query();
+    if (query.width as usize, query.height as usize) != (image.width, image.height) {
+        *texture = creator.create_texture_target(PixelFormatEnum::RGB24, image.width as u32, image.height as u32).unwrap();
+        canvas.window_mut().set_size(image.width as u32, image.height as u32).unwrap();
     }
-    update
+    texture.update(None, &image.pixels, image.width * 3).unwrap();
+    canvas.copy(texture, None, None).unwrap();
+    canvas.present();
+}
+
+fn parse_address(value: &str) -> u16 {
+    u16::from_str_radix(value, 16).expect("addresses have to be hexadecimal")
+}
+
+// invalid values of the command line options end the program with the error, like a rom that fails to load
+fn exit_with_error(error: impl Display) -> ! {
+    println!("{}", error);
+    std::process::exit(1);
+}
+
+fn parse_option<T: FromStr<Err = String>>(value: &str) -> T {
+    value.parse().unwrap_or_else(|error| exit_with_error(error))
 }
 
 fn main() {
-    let file_path = "nestest.nes";
-    let rom_contents = fs::read(file_path).unwrap();
+    // usage: rust-nes [rom] [--start-address C000] [--ram-pattern zeros|ones|random]
+    //     [--trace file] [--trace-format nestest|mesen|fceux] [--trace-range 8000-FFFF]
+    //     [--trace-from C000] [--trace-count 1000] [--trace-ring 10000]
+    //     [--screenshot-scale 2] [--overscan 8,8,0,0] [--record file.avi]
+    //     [--record-movie file.fm2] [--play-movie file.fm2] [--region ntsc|pal|dendy]
+    //     [--palette default|ntsc|file.pal] [--ntsc] [--ntsc-settings sharpness=0.2,artifacts=0.5]
+    //     [--scale 3] [--scaler none|scale2x|scale3x|xbr|hq2x] [--scanlines] [--aspect-correction]
+    //     [--fast-forward 4] [--slow-motion 0.25]
+    let mut file_path = "nestest.nes".to_string();
+    let mut start_address = None;
+    let mut ram_pattern = RamPattern::Zeros;
+    let mut screenshot_scale = 1;
+    let mut overscan = None;
+    let mut record_path = None;
+    let mut record_movie_path = None;
+    let mut play_movie_path = None;
+    let mut region = None;
+    let mut video = Video::default();
+    let mut scale = None;
+    // fast-forward is uncapped unless a speed is given
+    let mut fast_forward_speed = pacing::UNCAPPED;
+    let mut slow_motion_speed = 0.25;
+    let mut tracer = Tracer::new(TraceFormat::Nestest);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--ram-pattern" {
+            let value = args.next().expect("--ram-pattern requires zeros, ones or random");
+            ram_pattern = parse_option(&value);
+        } else if arg == "--trace" {
+            let value = args.next().expect("--trace requires a file");
+            tracer.set_output_file(&value).unwrap_or_else(|error| exit_with_error(format!("the trace {} could not be created: {}", value, error)));
+        } else if arg == "--trace-format" {
+            let value = args.next().expect("--trace-format requires nestest, mesen or fceux");
+            tracer.format = parse_option(&value);
+        } else if arg == "--trace-range" {
+            let value = args.next().expect("--trace-range requires a range like 8000-FFFF");
+            let (start, end) = value.split_once('-').expect("--trace-range requires a range like 8000-FFFF");
+            tracer.set_address_range(Some(parse_address(start)..=parse_address(end)));
+        } else if arg == "--trace-from" {
+            let value = args.next().expect("--trace-from requires a hexadecimal address");
+            tracer.set_start_trigger(Some(parse_address(&value)));
+        } else if arg == "--trace-count" {
+            let value = args.next().expect("--trace-count requires a number");
+            tracer.set_instruction_limit(Some(value.parse().unwrap_or_else(|_| exit_with_error("--trace-count requires a number"))));
+        } else if arg == "--trace-ring" {
+            let value = args.next().expect("--trace-ring requires a number");
+            tracer.set_ring_buffer_capacity(value.parse().unwrap_or_else(|_| exit_with_error("--trace-ring requires a number")));
+        } else if arg == "--screenshot-scale" {
+            let value = args.next().expect("--screenshot-scale requires a number");
+            screenshot_scale = match value.parse() {
+                Ok(0) | Err(_) => exit_with_error("--screenshot-scale requires a number of at least 1"),
+                Ok(scale) => scale,
+            };
+        } else if arg == "--overscan" {
+            let value = args.next().expect("--overscan requires top,bottom,left,right or none");
+            overscan = Some(parse_option::<Overscan>(&value));
+        } else if arg == "--record" {
+            record_path = Some(args.next().expect("--record requires a file"));
+        } else if arg == "--record-movie" {
+            record_movie_path = Some(args.next().expect("--record-movie requires a file"));
+        } else if arg == "--play-movie" {
+            play_movie_path = Some(args.next().expect("--play-movie requires a file"));
+        } else if arg == "--region" {
+            let value = args.next().expect("--region requires ntsc, pal or dendy");
+            region = Some(parse_option::<Region>(&value));
+        } else if arg == "--palette" {
+            let value = args.next().expect("--palette requires default, ntsc or a .pal file");
+            video.palette = Palette::select(&value).unwrap_or_else(|error| {
+                println!("the palette could not be loaded: {}", error);
+                std::process::exit(1);
+            });
+        } else if arg == "--ntsc" {
+            video.ntsc = Some(NtscFilter::new(NtscSettings::default()));
+        } else if arg == "--ntsc-settings" {
+            let value = args.next().expect("--ntsc-settings requires settings like sharpness=0.2,artifacts=0.5");
+            video.ntsc = Some(NtscFilter::new(parse_option(&value)));
+        } else if arg == "--scale" {
+            let value = args.next().expect("--scale requires a number");
+            scale = Some(value.parse().expect("--scale requires a number"));
+        } else if arg == "--scaler" {
+            let value = args.next().expect("--scaler requires none, scale2x, scale3x, xbr or hq2x");
+            video.scaler = parse_option(&value);
+        } else if arg == "--fast-forward" {
+            let value = args.next().expect("--fast-forward requires a speed like 4, 0 runs uncapped");
+            fast_forward_speed = match value.parse::<f64>() {
+                Ok(0.0) => pacing::UNCAPPED,
+                Ok(speed) if speed > 1.0 => speed,
+                _ => {
+                    println!("--fast-forward requires a speed faster than 1 like 4, 0 runs uncapped");
+                    std::process::exit(1);
+                }
+            };
+        } else if arg == "--slow-motion" {
+            let value = args.next().expect("--slow-motion requires a speed like 0.25");
+            slow_motion_speed = match value.parse::<f64>() {
+                Ok(speed) if (pacing::MIN_SPEED..1.0).contains(&speed) => speed,
+                _ => {
+                    println!("--slow-motion requires a speed from {} to below 1 like 0.25", pacing::MIN_SPEED);
+                    std::process::exit(1);
+                }
+            };
+        } else if arg == "--scanlines" {
+            video.scanlines = true;
+        } else if arg == "--aspect-correction" {
+            video.aspect_correction = true;
+        } else if arg == "--start-address" {
+            let value = args.next().expect("--start-address requires a hexadecimal address");
+            start_address = Some(parse_address(&value));
+        } else {
+            file_path = arg;
+        }
+    }
+
+    let rom_contents = fs::read(&file_path).unwrap();
+    let mut nes = match Nes::new(&rom_contents) {
+        Ok(nes) => nes,
+        Err(error) => {
+            println!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let mut movie_playback = play_movie_path.map(|path| {
+        let movie = match Movie::load(&path) {
+            Ok(movie) => movie,
+            Err(error) => {
+                println!("the movie {} could not be loaded: {}", path, error);
+                std::process::exit(1);
+            }
+        };
+        if !movie.matches_rom(&rom_contents) {
+            println!("the movie {} has been recorded with a different rom", path);
+        }
+        (movie, 0)
+    });
+
+    // the region comes from the rom header, a played movie may override it and --region overrides both
+    if let Some(region) = region.or_else(|| movie_playback.as_ref().map(|(movie, _)| movie.region(nes.region()))) {
+        nes.set_region(region);
+    }
+    println!("running with {} timing", nes.region().name());
+    video.overscan = overscan.unwrap_or(Overscan::for_region(nes.region()));
+
+    nes.set_ram_pattern(ram_pattern);
+    // the memory viewer highlights the written bytes
+    nes.set_write_log_enabled(true);
+    nes.set_start_address(start_address);
+    nes.power_on();
+
+    video.scale = scale.unwrap_or(if video.ntsc.is_some() { 1 } else { SCALE });
+    let image = video.present(nes.frame_buffer(), 0);
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("rust-nes", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
+        .window("rust-nes", image.width as u32, image.height as u32)
         .position_centered()
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    // no vsync, the frame limiter paces the emulation to the frame rate of the region instead of the display
+    let mut canvas = window.into_canvas().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
 
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32).unwrap();
-
-    let cartridge = Cartridge::new(&rom_contents).unwrap();
-    let bus = Bus::new(cartridge);
-
-    let mut cpu = CPU::new(bus);
-    cpu.start_override = 0xC000;
-    cpu.reset();
-
-    let mut screen_state = [0 as u8; 32 * 3 * 32];
-    let mut rng = rand::thread_rng();
-
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open("own_log.log").unwrap();
-
-    cpu.run(move |cpu, opcode| {
-        let trace_line = trace(cpu, opcode);
-        println!("{}", trace_line);
-        writeln!(file, "{}", trace_line).unwrap();
-        
-        handle_user_input(cpu, &mut event_pump);
-        cpu.mem_write(0xfe, rng.gen_range(1, 16));
-
-        if read_screen_state(cpu, &mut screen_state) {
-            texture.update(None, &screen_state, 32*3).unwrap();
-            canvas.copy(&texture, None, None).unwrap();
-            canvas.present();
+        .create_texture_target(PixelFormatEnum::RGB24, image.width as u32, image.height as u32).unwrap();
+
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let audio_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(1),
+        samples: None,
+    };
+    let audio_queue = audio_subsystem.open_queue::<f32, _>(None, &audio_spec).unwrap();
+    audio_queue.resume();
+
+    // the memory viewer window stays hidden until F1 is pressed
+    let memory_window = video_subsystem
+        .window("rust-nes memory", memory_viewer::VIEW_WIDTH as u32 * 2, memory_viewer::VIEW_HEIGHT as u32 * 2)
+        .hidden()
+        .build()
+        .unwrap();
+    // the PPU viewer window stays hidden until F3 is pressed
+    let ppu_window = video_subsystem
+        .window("rust-nes ppu", ppu_viewer::VIEW_WIDTH as u32 * 2, ppu_viewer::VIEW_HEIGHT as u32 * 2)
+        .hidden()
+        .build()
+        .unwrap();
+    let mut debugger = Debugger {
+        recorder: record_path.map(|path| {
+            let (width, height) = video.cropped_size();
+            match AviRecorder::create(&path, nes.region(), width, height) {
+                Ok(recorder) => recorder,
+                Err(error) => {
+                    println!("the recording {} could not be created: {}", path, error);
+                    std::process::exit(1);
+                }
+            }
+        }),
+        rom_path: file_path.clone(),
+        video,
+        paused: false,
+        frame_advance: false,
+        fast_forward: false,
+        slow_motion: false,
+        memory_viewer: MemoryViewer::new(),
+        memory_viewer_visible: false,
+        memory_window_id: memory_window.id(),
+        ppu_viewer_palette: 0,
+        ppu_viewer_visible: false,
+        ppu_window_id: ppu_window.id(),
+        screenshot: ScreenshotSettings {
+            scale: screenshot_scale,
+        },
+    };
+    let mut memory_canvas = memory_window.into_canvas().build().unwrap();
+    let memory_creator = memory_canvas.texture_creator();
+    let mut memory_texture = memory_creator
+        .create_texture_target(PixelFormatEnum::RGB24, memory_viewer::VIEW_WIDTH as u32, memory_viewer::VIEW_HEIGHT as u32).unwrap();
+    let mut memory_window_shown = false;
+    let mut ppu_canvas = ppu_window.into_canvas().build().unwrap();
+    let ppu_creator = ppu_canvas.texture_creator();
+    let mut ppu_texture = ppu_creator
+        .create_texture_target(PixelFormatEnum::RGB24, ppu_viewer::VIEW_WIDTH as u32, ppu_viewer::VIEW_HEIGHT as u32).unwrap();
+    let mut ppu_window_shown = false;
+
+    // movies are recorded and played from power on, the keyboard is ignored while a movie is played
+    let rom_name = Path::new(&file_path).file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let mut recorded_movie = record_movie_path.map(|path| (path, Movie::new(&rom_name, &rom_contents, nes.region())));
+
+    let mut buttons = JoypadButton::empty();
+
+    let mut limiter = FrameLimiter::new(nes.region().frames_per_second(), Instant::now());
+
+    while handle_user_input(&mut buttons, &mut event_pump, &mut tracer, &mut debugger, &mut nes) {
+        if debugger.memory_viewer_visible != memory_window_shown {
+            if debugger.memory_viewer_visible {
+                memory_canvas.window_mut().show();
+            } else {
+                memory_canvas.window_mut().hide();
+            }
+            memory_window_shown = debugger.memory_viewer_visible;
         }
 
-        //std::thread::sleep(std::time::Duration::new(0, 5_000));
-    });
+        if debugger.memory_viewer_visible {
+            let view = debugger.memory_viewer.render(&nes, debugger.paused);
+            memory_texture.update(None, &view, memory_viewer::VIEW_WIDTH * 3).unwrap();
+            memory_canvas.copy(&memory_texture, None, None).unwrap();
+            memory_canvas.present();
+        }
+
+        if debugger.ppu_viewer_visible != ppu_window_shown {
+            if debugger.ppu_viewer_visible {
+                ppu_canvas.window_mut().show();
+            } else {
+                ppu_canvas.window_mut().hide();
+            }
+            ppu_window_shown = debugger.ppu_viewer_visible;
+        }
+
+        if debugger.ppu_viewer_visible {
+            let view = ppu_viewer::render(nes.ppu(), debugger.ppu_viewer_palette);
+            ppu_texture.update(None, &view, ppu_viewer::VIEW_WIDTH * 3).unwrap();
+            ppu_canvas.copy(&ppu_texture, None, None).unwrap();
+            ppu_canvas.present();
+        }
+
+        // the main window keeps presenting the last frame while paused
+        if debugger.paused && !debugger.frame_advance {
+            show_image(&mut canvas, &creator, &mut texture, &debugger.video.present(nes.frame_buffer(), nes.ppu().frame_count));
+            thread::sleep(limiter.native_frame_duration());
+            continue;
+        }
+        debugger.frame_advance = false;
+
+        let speed = if debugger.fast_forward {
+            fast_forward_speed
+        } else if debugger.slow_motion {
+            slow_motion_speed
+        } else {
+            1.0
+        };
+        if speed != limiter.speed() {
+            limiter.set_speed(speed, Instant::now()).expect("the speeds are checked when parsing the arguments");
+            // the audio only plays at normal speed
+            audio_queue.clear();
+        }
+
+        let input = next_movie_frame(&mut movie_playback)
+            .unwrap_or(MovieFrame { commands: 0, buttons: [buttons, JoypadButton::empty()] });
+        input.apply(&mut nes);
+        if let Some((_, movie)) = &mut recorded_movie {
+            movie.record_frame(input);
+        }
+
+        let result = nes.run_frame_with_callback(|cpu, opcode| tracer.trace(cpu, opcode));
+        debugger.memory_viewer.record_writes(&nes);
+        debugger.memory_viewer.end_frame();
+
+        if let Err(error) = result {
+            println!("{}", error);
+            tracer.flush().unwrap();
+            if let Some(recorder) = debugger.recorder.take() {
+                recorder.finish().unwrap();
+            }
+            if let Some((path, movie)) = &recorded_movie {
+                movie.save(path).unwrap();
+            }
+            if tracer.ring_buffer().next().is_some() {
+                let mut file = File::create(CRASH_TRACE_FILE).unwrap();
+                tracer.dump_ring_buffer(&mut file).unwrap();
+                println!("the last traced instructions were written to {}", CRASH_TRACE_FILE);
+            }
+            std::process::exit(1);
+        }
+
+        if limiter.should_present(Instant::now()) {
+            show_image(&mut canvas, &creator, &mut texture, &debugger.video.present(nes.frame_buffer(), nes.ppu().frame_count));
+        }
+
+        if speed == 1.0 {
+            audio_queue.queue(nes.audio_samples());
+        }
+
+        thread::sleep(limiter.end_frame(Instant::now()));
+
+        if let Some(recorder) = &mut debugger.recorder {
+            let image = debugger.video.render_cropped(nes.frame_buffer(), nes.ppu().frame_count);
+            if let Err(error) = recorder.record_frame(&image, nes.audio_samples()) {
+                println!("the recording stopped: {}", error);
+                toggle_recording(&mut debugger, nes.region());
+            }
+        }
+    }
+
+    tracer.flush().unwrap();
+    if let Some(recorder) = debugger.recorder.take() {
+        recorder.finish().unwrap();
+    }
+    if let Some((path, movie)) = &recorded_movie {
+        movie.save(path).unwrap();
+        println!("recorded {} frames into the movie {}", movie.frames.len(), path);
+    }
 }
@@ -1,11 +1,25 @@
+use std::str::FromStr;
+
+use rand::Rng;
+
 pub trait Mem {
-    fn mem_read (&self, addr: u16) -> u8;
+    // reads may have side effects on memory mapped registers (e.g. the PPU status register)
+    fn mem_read (&mut self, addr: u16) -> u8;
+
+    // reads without any side effects, used for tracing and debugging
+    fn mem_peek (&self, addr: u16) -> u8;
 
     fn mem_write (&mut self, addr: u16, data: u8);
 
-    fn mem_read_u16 (&self, addr: u16) -> u16 {
+    fn mem_read_u16 (&mut self, addr: u16) -> u16 {
         let lo = self.mem_read(addr) as u16;
-        let hi = self.mem_read(addr + 1) as u16;
+        let hi = self.mem_read(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn mem_peek_u16 (&self, addr: u16) -> u16 {
+        let lo = self.mem_peek(addr) as u16;
+        let hi = self.mem_peek(addr.wrapping_add(1)) as u16;
         (hi << 8) | lo
     }
 
@@ -14,6 +28,105 @@ pub trait Mem {
         let lo = (data & 0xff) as u8;
 
         self.mem_write(addr, lo);
-        self.mem_write(addr + 1, hi);
+        self.mem_write(addr.wrapping_add(1), hi);
+    }
+
+    /*
+        Timing and interrupts, buses without any devices can keep the defaults
+     */
+
+    // advances the devices attached to the bus by the given amount of cpu cycles
+    fn tick (&mut self, _cycles: u16) {}
+
+    // returns true once for every NMI raised by a device
+    fn poll_nmi (&mut self) -> bool {
+        false
+    }
+
+    // the IRQ line is level triggered, it stays active until the device acknowledges it
+    fn poll_irq (&self) -> bool {
+        false
+    }
+
+    // returns the page of a requested OAM DMA transfer, the transfer itself is done by the cpu
+    fn take_oam_dma (&mut self) -> Option<u8> {
+        None
+    }
+
+    // the scanline and dot the PPU is at, used for trace logs
+    fn ppu_position (&self) -> Option<(u16, u16)> {
+        None
+    }
+}
+
+// the content of the ram after power on is undefined on the real hardware, most consoles show patterns of
+// zeros and ones, some games (unintentionally) rely on it, see https://www.nesdev.org/wiki/CPU_power_up_state
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RamPattern {
+    Zeros,
+    // all bits set, 0xFF
+    Ones,
+    Random,
+}
+
+impl RamPattern {
+    pub fn fill(&self, memory: &mut [u8]) {
+        match self {
+            RamPattern::Zeros => memory.fill(0x00),
+            RamPattern::Ones => memory.fill(0xFF),
+            RamPattern::Random => rand::thread_rng().fill(memory),
+        }
+    }
+}
+
+impl FromStr for RamPattern {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "zeros" => Ok(RamPattern::Zeros),
+            "ones" => Ok(RamPattern::Ones),
+            "random" => Ok(RamPattern::Random),
+            _ => Err(format!("Unknown ram pattern {}, expected zeros, ones or random", value)),
+        }
+    }
+}
+
+// a flat 64KB ram without any mapping, used to run generic 6502 programs without the NES bus
+pub struct FlatRam {
+    memory: Vec<u8>,
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        FlatRam {
+            memory: vec![0; 0x10000],
+        }
+    }
+
+    pub fn load(&mut self, program: &[u8], base_address: u16) {
+        let start = base_address as usize;
+        let end = (start + program.len()).min(self.memory.len());
+        self.memory[start..end].copy_from_slice(&program[..end - start]);
+    }
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        FlatRam::new()
+    }
+}
+
+impl Mem for FlatRam {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn mem_peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
     }
 }
\ No newline at end of file
@@ -0,0 +1,188 @@
+/*
+A live hex view of the memories of the console, see bus::MemoryRegion.
+Bytes written by the cpu are highlighted for a while, the cursor allows to edit bytes nibble by nibble.
+The view is available as text (hex_dump) and as an RGB24 image for a debugging window (render).
+ */
+
+use crate::bus::MemoryRegion;
+use crate::font::{self, CHAR_WIDTH, LINE_HEIGHT};
+use crate::nes::Nes;
+
+pub const BYTES_PER_ROW: usize = 16;
+pub const VISIBLE_ROWS: usize = 32;
+// the number of frames a written byte stays highlighted
+const HIGHLIGHT_FRAMES: u8 = 60;
+
+const MARGIN: usize = 4;
+// "0000: " followed by 16 bytes with a space each
+const CHARS_PER_ROW: usize = 6 + BYTES_PER_ROW * 3;
+pub const VIEW_WIDTH: usize = CHARS_PER_ROW * CHAR_WIDTH + 2 * MARGIN;
+// the header line, an empty line and the rows
+pub const VIEW_HEIGHT: usize = (VISIBLE_ROWS + 2) * LINE_HEIGHT + 2 * MARGIN;
+
+const BACKGROUND_COLOR: (u8, u8, u8) = (0x10, 0x10, 0x18);
+const TEXT_COLOR: (u8, u8, u8) = (0xC0, 0xC0, 0xC0);
+const ADDRESS_COLOR: (u8, u8, u8) = (0x70, 0x90, 0xC0);
+const HIGHLIGHT_COLOR: (u8, u8, u8) = (0xFF, 0x50, 0x30);
+const CURSOR_COLOR: (u8, u8, u8) = (0x50, 0x50, 0x70);
+
+pub struct MemoryViewer {
+    pub region: MemoryRegion,
+    // the first visible row
+    scroll_row: usize,
+    cursor: usize,
+    // the high nibble typed in for the byte at the cursor, the byte is written with the low nibble
+    pending_nibble: Option<u8>,
+    // the written bytes of the cpu and the frames they stay highlighted
+    recent_writes: Vec<(MemoryRegion, usize, u8)>,
+}
+
+impl MemoryViewer {
+    pub fn new() -> Self {
+        MemoryViewer {
+            region: MemoryRegion::CpuRam,
+            scroll_row: 0,
+            cursor: 0,
+            pending_nibble: None,
+            recent_writes: Vec::new(),
+        }
+    }
+
+    pub fn next_region(&mut self) {
+        let index = MemoryRegion::ALL.iter().position(|region| *region == self.region).unwrap();
+        self.region = MemoryRegion::ALL[(index + 1) % MemoryRegion::ALL.len()];
+        self.scroll_row = 0;
+        self.cursor = 0;
+        self.pending_nibble = None;
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // moves the cursor by the given number of bytes and scrolls to keep it visible
+    pub fn move_cursor(&mut self, delta: isize) {
+        let last = self.region.size() as isize - 1;
+        self.cursor = (self.cursor as isize + delta).clamp(0, last) as usize;
+        self.pending_nibble = None;
+
+        let row = self.cursor / BYTES_PER_ROW;
+        if row < self.scroll_row {
+            self.scroll_row = row;
+        } else if row >= self.scroll_row + VISIBLE_ROWS {
+            self.scroll_row = row + 1 - VISIBLE_ROWS;
+        }
+    }
+
+    // call this after every frame, the write log of the console has to be enabled (see Nes::set_write_log_enabled)
+    pub fn record_writes(&mut self, nes: &Nes) {
+        for &address in nes.written_addresses() {
+            if let Some((region, offset)) = MemoryRegion::from_cpu_address(address) {
+                match self.recent_writes.iter_mut().find(|(r, o, _)| *r == region && *o == offset) {
+                    Some(write) => write.2 = HIGHLIGHT_FRAMES,
+                    None => self.recent_writes.push((region, offset, HIGHLIGHT_FRAMES)),
+                }
+            }
+        }
+    }
+
+    // fades the highlights, call this once per frame
+    pub fn end_frame(&mut self) {
+        for write in self.recent_writes.iter_mut() {
+            write.2 -= 1;
+        }
+        self.recent_writes.retain(|(_, _, frames)| *frames > 0);
+    }
+
+    // the remaining highlight frames of the byte, 0 if it has not been written recently
+    pub fn highlight(&self, region: MemoryRegion, offset: usize) -> u8 {
+        self.recent_writes.iter()
+            .find(|(r, o, _)| *r == region && *o == offset)
+            .map_or(0, |(_, _, frames)| *frames)
+    }
+
+    // the first nibble is kept, the second one writes the byte at the cursor and moves on to the next one
+    pub fn enter_nibble(&mut self, nes: &mut Nes, nibble: u8) {
+        match self.pending_nibble.take() {
+            None => self.pending_nibble = Some(nibble & 0x0F),
+            Some(high) => {
+                nes.poke_memory(self.region, self.cursor, (high << 4) | (nibble & 0x0F));
+                self.move_cursor(1);
+            },
+        }
+    }
+
+    fn row_address(&self, row: usize) -> usize {
+        self.region.base_address() as usize + row * BYTES_PER_ROW
+    }
+
+    fn visible_rows(&self) -> std::ops::Range<usize> {
+        let rows = self.region.size() / BYTES_PER_ROW;
+        self.scroll_row..(self.scroll_row + VISIBLE_ROWS).min(rows)
+    }
+
+    // the visible rows as text, e.g. "0000: 00 01 02 ..."
+    pub fn hex_dump(&self, nes: &Nes) -> String {
+        let mut dump = String::new();
+        for row in self.visible_rows() {
+            let bytes: Vec<String> = (0..BYTES_PER_ROW)
+                .map(|i| format!("{:02X}", nes.peek_memory(self.region, row * BYTES_PER_ROW + i)))
+                .collect();
+            dump += &format!("{:04X}: {}\n", self.row_address(row), bytes.join(" "));
+        }
+        dump
+    }
+
+    // renders the view into an RGB24 buffer of VIEW_WIDTH x VIEW_HEIGHT pixels
+    pub fn render(&self, nes: &Nes, paused: bool) -> Vec<u8> {
+        let mut buffer = vec![0; VIEW_WIDTH * VIEW_HEIGHT * 3];
+        font::fill_rect(&mut buffer, VIEW_WIDTH, 0, 0, VIEW_WIDTH, VIEW_HEIGHT, BACKGROUND_COLOR);
+
+        let last_address = self.region.base_address() as usize + self.region.size() - 1;
+        let mut header = format!("{}  {:04X}-{:04X}  CURSOR {:04X}", self.region.name(), self.region.base_address(),
+            last_address, self.region.base_address() as usize + self.cursor);
+        if paused {
+            header += "  PAUSED";
+        }
+        font::draw_text(&mut buffer, VIEW_WIDTH, MARGIN, MARGIN, &header, TEXT_COLOR);
+
+        for (line, row) in self.visible_rows().enumerate() {
+            let y = MARGIN + (line + 2) * LINE_HEIGHT;
+            font::draw_text(&mut buffer, VIEW_WIDTH, MARGIN, y, &format!("{:04X}:", self.row_address(row)), ADDRESS_COLOR);
+
+            for i in 0..BYTES_PER_ROW {
+                let offset = row * BYTES_PER_ROW + i;
+                let x = MARGIN + (6 + i * 3) * CHAR_WIDTH;
+
+                if offset == self.cursor {
+                    font::fill_rect(&mut buffer, VIEW_WIDTH, x - 1, y - 1, 2 * CHAR_WIDTH + 1, LINE_HEIGHT, CURSOR_COLOR);
+                }
+
+                // the highlight fades from the highlight color to the text color
+                let frames = self.highlight(self.region, offset) as u32;
+                let fade = |highlight: u8, text: u8| {
+                    ((highlight as u32 * frames + text as u32 * (HIGHLIGHT_FRAMES as u32 - frames)) / HIGHLIGHT_FRAMES as u32) as u8
+                };
+                let color = (
+                    fade(HIGHLIGHT_COLOR.0, TEXT_COLOR.0),
+                    fade(HIGHLIGHT_COLOR.1, TEXT_COLOR.1),
+                    fade(HIGHLIGHT_COLOR.2, TEXT_COLOR.2),
+                );
+
+                let text = match self.pending_nibble {
+                    Some(high) if offset == self.cursor => format!("{:X}-", high),
+                    _ => format!("{:02X}", nes.peek_memory(self.region, offset)),
+                };
+                font::draw_text(&mut buffer, VIEW_WIDTH, x, y, &text, color);
+            }
+        }
+
+        buffer
+    }
+}
+
+impl Default for MemoryViewer {
+    fn default() -> Self {
+        MemoryViewer::new()
+    }
+}
@@ -0,0 +1,285 @@
+/*
+Input movies in the FM2 format of FCEUX, see https://fceux.com/web/help/fm2.html
+A movie holds the controller state of every frame since power on, playing it back reproduces a session exactly.
+
+The header consists of "key value" lines, every frame is a line like "|0|R..U...A|........||":
+the commands (1 = soft reset, 2 = hard reset) followed by the buttons of each port in the order RLDUTSBA,
+where every character other than '.' and ' ' is a pressed button.
+Movies starting from a save state are not supported, since the emulator has no save states.
+The palFlag header marks movies recorded on a PAL console, FM2 has no flag for Dendy famiclones.
+ */
+
+use std::fs;
+use std::io;
+
+use crate::joypad::JoypadButton;
+use crate::nes::Nes;
+use crate::region::Region;
+
+pub const COMMAND_SOFT_RESET: u8 = 0b01;
+pub const COMMAND_HARD_RESET: u8 = 0b10;
+
+// the buttons in the order of the input log, which is the order of their bits from the highest to the lowest
+const BUTTON_CHARACTERS: &[u8; 8] = b"RLDUTSBA";
+const INES_HEADER_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub buttons: [JoypadButton; 2],
+}
+
+impl MovieFrame {
+    // executes the commands and sets the inputs of the frame, call this before running the frame
+    pub fn apply(&self, nes: &mut Nes) {
+        if self.commands & COMMAND_HARD_RESET != 0 {
+            nes.power_on();
+        } else if self.commands & COMMAND_SOFT_RESET != 0 {
+            nes.reset();
+        }
+
+        nes.set_input(0, self.buttons[0]);
+        nes.set_input(1, self.buttons[1]);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    // "base64:" followed by the MD5 of the PRG and CHR rom
+    pub rom_checksum: String,
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub pal: bool,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    // an empty movie for the rom, which is recorded from power on
+    pub fn new(rom_filename: &str, rom: &[u8], region: Region) -> Self {
+        Movie {
+            rom_filename: rom_filename.to_string(),
+            rom_checksum: Movie::checksum(rom),
+            guid: Movie::random_guid(),
+            rerecord_count: 0,
+            pal: region == Region::Pal,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn checksum(rom: &[u8]) -> String {
+        let data = rom.get(INES_HEADER_SIZE..).unwrap_or_default();
+        format!("base64:{}", base64(&md5(data)))
+    }
+
+    // whether the movie has been recorded with the rom, movies without checksum match every rom
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        self.rom_checksum.is_empty() || self.rom_checksum == Movie::checksum(rom)
+    }
+
+    // the region to play the movie back in, the detected one unless it contradicts the palFlag
+    pub fn region(&self, detected: Region) -> Region {
+        match (self.pal, detected) {
+            (true, _) => Region::Pal,
+            (false, Region::Pal) => Region::Ntsc,
+            (false, detected) => detected,
+        }
+    }
+
+    fn random_guid() -> String {
+        let bytes: Vec<u8> = (0..16).map(|_| rand::random::<u8>()).collect();
+        let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+
+    pub fn record_frame(&mut self, frame: MovieFrame) {
+        self.frames.push(frame);
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut fm2 = String::new();
+        let header = [
+            ("version", "3".to_string()),
+            ("emuVersion", "0".to_string()),
+            ("rerecordCount", self.rerecord_count.to_string()),
+            ("palFlag", (self.pal as u8).to_string()),
+            ("romFilename", self.rom_filename.clone()),
+            ("romChecksum", self.rom_checksum.clone()),
+            ("guid", self.guid.clone()),
+            ("fourscore", "0".to_string()),
+            ("microphone", "0".to_string()),
+            // both ports have a standard controller
+            ("port0", "1".to_string()),
+            ("port1", "1".to_string()),
+            ("port2", "0".to_string()),
+            ("FDS", "0".to_string()),
+            ("NewPPU", "0".to_string()),
+        ];
+        for (key, value) in header {
+            fm2 += &format!("{} {}\n", key, value);
+        }
+
+        for frame in &self.frames {
+            fm2 += &format!("|{}|{}|{}||\n", frame.commands, format_buttons(frame.buttons[0]), format_buttons(frame.buttons[1]));
+        }
+        fm2
+    }
+
+    pub fn parse(fm2: &str) -> Result<Movie, String> {
+        let mut movie = Movie {
+            rom_filename: String::new(),
+            rom_checksum: String::new(),
+            guid: String::new(),
+            rerecord_count: 0,
+            pal: false,
+            frames: Vec::new(),
+        };
+        // the device of each port, 1 is a standard controller and 0 none
+        let mut ports = [1, 1];
+
+        for (number, line) in fm2.lines().enumerate() {
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+
+            if let Some(input) = line.strip_prefix('|') {
+                let fields: Vec<&str> = input.split('|').collect();
+                if fields.len() < 3 {
+                    return Err(error("incomplete input line"));
+                }
+
+                let mut frame = MovieFrame {
+                    commands: fields[0].trim().parse().map_err(|_| error("invalid commands"))?,
+                    ..MovieFrame::default()
+                };
+                for port in 0..2 {
+                    if ports[port] == 1 {
+                        frame.buttons[port] = parse_buttons(fields[port + 1]).ok_or_else(|| error("invalid buttons"))?;
+                    }
+                }
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" if value != "3" => return Err(error("only version 3 of the FM2 format is supported")),
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => movie.rom_checksum = value.to_string(),
+                "guid" => movie.guid = value.to_string(),
+                "rerecordCount" => movie.rerecord_count = value.parse().map_err(|_| error("invalid rerecord count"))?,
+                "savestate" => return Err(error("movies starting from a save state are not supported")),
+                "palFlag" => movie.pal = value == "1",
+                "fourscore" if value != "0" => return Err(error("the four score adapter is not supported")),
+                "binary" if value != "0" => return Err(error("movies with binary input are not supported")),
+                "port0" | "port1" => {
+                    let port = if key == "port0" { 0 } else { 1 };
+                    ports[port] = value.parse().map_err(|_| error("invalid port device"))?;
+                    if ports[port] > 1 {
+                        return Err(error("only standard controllers are supported"));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        Ok(movie)
+    }
+
+    pub fn load(path: &str) -> Result<Movie, String> {
+        let fm2 = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        Movie::parse(&fm2)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_fm2())
+    }
+}
+
+fn format_buttons(buttons: JoypadButton) -> String {
+    BUTTON_CHARACTERS.iter().enumerate()
+        .map(|(i, character)| if buttons.bits() & (0x80 >> i) != 0 { *character as char } else { '.' })
+        .collect()
+}
+
+fn parse_buttons(field: &str) -> Option<JoypadButton> {
+    if field.len() != BUTTON_CHARACTERS.len() {
+        return None;
+    }
+
+    let mut bits = 0;
+    for (i, character) in field.bytes().enumerate() {
+        if character != b'.' && character != b' ' {
+            bits |= 0x80 >> i;
+        }
+    }
+    Some(JoypadButton::from_bits_truncate(bits))
+}
+
+// see https://www.rfc-editor.org/rfc/rfc4648#section-4
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut result = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[(bits >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+// see https://www.rfc-editor.org/rfc/rfc1321
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+        5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+        4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+        6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    // the integer part of abs(sin(i + 1)) * 2^32
+    let constants: Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32).collect();
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+    for block in message.chunks(64) {
+        let words: Vec<u32> = block.chunks(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect();
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(constants[i]).wrapping_add(words[g]).rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0; 16];
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
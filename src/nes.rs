@@ -0,0 +1,172 @@
+/*
+The NES console, which wires the cpu, the bus and the devices attached to it together.
+This is the entry point for frontends, which only need to feed the input and present the
+frame buffer and the audio samples after each frame.
+ */
+
+use crate::cartridge::Cartridge;
+use crate::frame::Frame;
+use crate::joypad::JoypadButton;
+use crate::mem::RamPattern;
+use crate::region::Region;
+
+// the core modules are private to the crate, only the types which appear in the api of the console are public
+pub use crate::apu::SAMPLE_RATE;
+pub use crate::bus::{Bus, MemoryRegion};
+pub use crate::cpu::{CpuError, CPU};
+pub use crate::opcodes::OpCode;
+pub use crate::ppu::PPU;
+
+pub struct Nes {
+    cpu: CPU<Bus>,
+    ram_pattern: RamPattern,
+    // overrides the region detected from the rom header
+    forced_region: Option<Region>,
+}
+
+impl Nes {
+    pub fn new(rom: &[u8]) -> Result<Nes, String> {
+        let mut nes = Nes {
+            cpu: CPU::new(Nes::create_bus(rom, None)?),
+            ram_pattern: RamPattern::Zeros,
+            forced_region: None,
+        };
+        nes.power_on();
+
+        Ok(nes)
+    }
+
+    fn create_bus(rom: &[u8], forced_region: Option<Region>) -> Result<Bus, String> {
+        let cartridge = Cartridge::new(rom)?;
+        if cartridge.mapper() != 0 {
+            return Err(format!("Mapper {} is not supported!", cartridge.mapper()));
+        }
+        // NROM maps 16KB (mirrored) or 32KB of program rom
+        if cartridge.program_rom.len() != 0x4000 && cartridge.program_rom.len() != 0x8000 {
+            return Err(format!("NROM requires 16KB or 32KB of program rom, the rom has {}KB!", cartridge.program_rom.len() / 1024));
+        }
+
+        let region = forced_region.unwrap_or(cartridge.region);
+        let mut bus = Bus::new(cartridge);
+        bus.set_region(region);
+        Ok(bus)
+    }
+
+    // replaces the inserted cartridge and powers the console on again
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        let start_address = self.cpu.start_address;
+        self.cpu = CPU::new(Nes::create_bus(rom, self.forced_region)?);
+        self.cpu.start_address = start_address;
+        self.power_on();
+
+        Ok(())
+    }
+
+    pub fn region(&self) -> Region {
+        self.cpu.bus().region()
+    }
+
+    // runs the console with the timing of the region instead of the one from the rom header,
+    // also for roms loaded later, and powers it on again
+    pub fn set_region(&mut self, region: Region) {
+        self.forced_region = Some(region);
+        self.cpu.bus_mut().set_region(region);
+        self.power_on();
+    }
+
+    // the ram pattern is applied with the next power on
+    pub fn set_ram_pattern(&mut self, pattern: RamPattern) {
+        self.ram_pattern = pattern;
+    }
+
+    // starts execution at the given address instead of the RESET vector with the next power on or reset,
+    // e.g. nestest.nes runs its automated mode from 0xC000
+    pub fn set_start_address(&mut self, start_address: Option<u16>) {
+        self.cpu.start_address = start_address;
+    }
+
+    pub fn power_on(&mut self) {
+        let bus = self.cpu.bus_mut();
+        bus.fill_ram(self.ram_pattern);
+        bus.ppu.reset();
+        bus.apu.reset();
+        self.cpu.power_on();
+    }
+
+    // pressing the reset button, the ram keeps its content
+    pub fn reset(&mut self) {
+        self.cpu.bus_mut().ppu.reset();
+        self.cpu.bus_mut().apu.reset();
+        self.cpu.reset();
+    }
+
+    // runs until the PPU has finished the next frame
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        self.run_frame_with_callback(|_, _| {})
+    }
+
+    // runs until the PPU has finished the next frame, the callback is called before every instruction
+    pub fn run_frame_with_callback<F>(&mut self, mut callback: F) -> Result<(), CpuError>
+    where
+        F: FnMut(&mut CPU<Bus>, &&OpCode),
+    {
+        self.cpu.bus_mut().apu.samples.clear();
+        self.cpu.bus_mut().clear_write_log();
+
+        while !self.cpu.bus_mut().ppu.take_frame_complete() {
+            self.cpu.step(&mut callback)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn frame_buffer(&self) -> &Frame {
+        &self.cpu.bus().ppu.frame
+    }
+
+    // the audio samples (mono, apu::SAMPLE_RATE) generated during the last frame
+    pub fn audio_samples(&self) -> &[f32] {
+        &self.cpu.bus().apu.samples
+    }
+
+    // sets the pressed buttons of the controller in port 0 or 1, other ports do not exist and are ignored
+    pub fn set_input(&mut self, port: usize, buttons: JoypadButton) {
+        let bus = self.cpu.bus_mut();
+        match port {
+            0 => bus.joypad1.buttons = buttons,
+            1 => bus.joypad2.buttons = buttons,
+            _ => {},
+        }
+    }
+
+    // debugger access to the memories of the console, without any side effects on the devices
+    pub fn peek_memory(&self, region: MemoryRegion, offset: usize) -> u8 {
+        self.cpu.bus().peek_region(region, offset)
+    }
+
+    pub fn poke_memory(&mut self, region: MemoryRegion, offset: usize, data: u8) {
+        self.cpu.bus_mut().poke_region(region, offset, data);
+    }
+
+    // collects the addresses written by the cpu during each frame, e.g. for the memory viewer
+    pub fn set_write_log_enabled(&mut self, enabled: bool) {
+        self.cpu.bus_mut().set_write_log_enabled(enabled);
+    }
+
+    // the addresses written during the last frame in the order of the writes
+    pub fn written_addresses(&self) -> &[u16] {
+        self.cpu.bus().write_log()
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.cpu.bus().ppu
+    }
+
+    pub fn cpu(&self) -> &CPU<Bus> {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU<Bus> {
+        &mut self.cpu
+    }
+}
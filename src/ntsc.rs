@@ -0,0 +1,140 @@
+/*
+A composite video filter in the spirit of blargg's nes_ntsc, see http://slack.net/~ant/libs/ntsc.html
+and https://www.nesdev.org/wiki/NTSC_video
+
+Every pixel of the frame becomes 8 samples of the square wave the PPU outputs, the color subcarrier has a period
+of 12 samples. A TV separates the luma from the chroma by filtering, which is imperfect, so the colors bleed into
+their neighbours and luma edges show color fringes. The subcarrier phase advances by 4 samples every scanline
+(341 dots * 8 samples) and shifts between the frames, which makes the artifacts crawl like on a real TV.
+
+The settings range from -1 to 1 like those of nes_ntsc (artifacts from 0 to 1), 0 is the neutral value.
+ */
+
+use std::str::FromStr;
+
+use crate::frame::{Frame, Image, HEIGHT, WIDTH};
+use crate::palette::{ntsc_level, ntsc_phase_angle, yiq_to_rgb, PALETTE_SIZE};
+
+// the output is about 2.35 times wider than the frame and every scanline is output twice,
+// which gives the 8:7 pixel aspect ratio of the NES on a TV
+pub const NTSC_WIDTH: usize = 602;
+pub const NTSC_HEIGHT: usize = HEIGHT * 2;
+
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_CYCLE: usize = 12;
+const SAMPLES_PER_LINE: usize = WIDTH * SAMPLES_PER_PIXEL;
+// the pixels at the edges are repeated, so the filter windows never leave the scanline
+const PADDING_PIXELS: usize = 3;
+const PADDING: usize = PADDING_PIXELS * SAMPLES_PER_PIXEL;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscSettings {
+    // negative values blur, positive values sharpen the luma
+    pub sharpness: f32,
+    pub saturation: f32,
+    // rotates the colors, 1 is 180 degrees
+    pub hue: f32,
+    // how much chroma leaks into the luma, which causes the dot crawl and the rainbows on dithered patterns
+    pub artifacts: f32,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        NtscSettings { sharpness: 0.0, saturation: 0.0, hue: 0.0, artifacts: 0.5 }
+    }
+}
+
+// settings like "sharpness=0.5,hue=-0.1", the missing ones keep their default
+impl FromStr for NtscSettings {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut settings = NtscSettings::default();
+        for setting in value.split(',').filter(|setting| !setting.is_empty()) {
+            let (name, number) = setting.split_once('=').ok_or_else(|| format!("{} is not name=value", setting))?;
+            let number: f32 = number.parse().map_err(|_| format!("{} is not a number", number))?;
+            let (field, range) = match name {
+                "sharpness" => (&mut settings.sharpness, -1.0..=1.0),
+                "saturation" => (&mut settings.saturation, -1.0..=1.0),
+                "hue" => (&mut settings.hue, -1.0..=1.0),
+                "artifacts" => (&mut settings.artifacts, 0.0..=1.0),
+                _ => return Err(format!("unknown setting {}, use sharpness, saturation, hue or artifacts", name)),
+            };
+            if !range.contains(&number) {
+                return Err(format!("{} has to be between {} and {}", name, range.start(), range.end()));
+            }
+            *field = number;
+        }
+        Ok(settings)
+    }
+}
+
+pub struct NtscFilter {
+    pub settings: NtscSettings,
+    // the signal level of every pixel value at every phase of the subcarrier
+    levels: Vec<[f32; SAMPLES_PER_CYCLE]>,
+    // the cosine and the sine the chroma is demodulated with, including the hue setting
+    carrier: [(f32, f32); SAMPLES_PER_CYCLE],
+}
+
+impl NtscFilter {
+    pub fn new(settings: NtscSettings) -> Self {
+        let levels = (0..PALETTE_SIZE as u16)
+            .map(|pixel| std::array::from_fn(|phase| ntsc_level(pixel, phase)))
+            .collect();
+        let carrier = std::array::from_fn(|phase| {
+            let angle = ntsc_phase_angle(phase) + settings.hue * std::f32::consts::PI;
+            (angle.cos(), angle.sin())
+        });
+        NtscFilter { settings, levels, carrier }
+    }
+
+    // the burst phase of a frame, it cycles through three values like in nes_ntsc
+    pub fn burst_phase(frame_number: u64) -> usize {
+        (frame_number % 3) as usize * 4
+    }
+
+    pub fn render(&self, frame: &Frame, burst_phase: usize) -> Image {
+        let mut pixels = Vec::with_capacity(NTSC_WIDTH * NTSC_HEIGHT * 3);
+        // the running sums of the signal and of the demodulated chroma, the windows are differences of two sums
+        let mut luma = vec![0.0; SAMPLES_PER_LINE + 2 * PADDING + 1];
+        let mut in_phase = luma.clone();
+        let mut quadrature = luma.clone();
+
+        for y in 0..HEIGHT {
+            let line_phase = burst_phase + y * 4;
+            for sample in 0..SAMPLES_PER_LINE + 2 * PADDING {
+                let x = (sample / SAMPLES_PER_PIXEL).saturating_sub(PADDING_PIXELS).min(WIDTH - 1);
+                let phase = (line_phase + sample + SAMPLES_PER_CYCLE - PADDING % SAMPLES_PER_CYCLE) % SAMPLES_PER_CYCLE;
+                let level = self.levels[frame.pixel(x, y) as usize % PALETTE_SIZE][phase];
+                let (cos, sin) = self.carrier[phase];
+                luma[sample + 1] = luma[sample] + level;
+                in_phase[sample + 1] = in_phase[sample] + level * cos;
+                quadrature[sample + 1] = quadrature[sample] + level * sin;
+            }
+
+            let line_start = pixels.len();
+            for x in 0..NTSC_WIDTH {
+                let center = PADDING + (x * SAMPLES_PER_LINE + SAMPLES_PER_LINE / 2) / NTSC_WIDTH;
+                let window = |sums: &[f32], size: usize| (sums[center + size / 2] - sums[center - size / 2]) / size as f32;
+
+                // averaging a whole subcarrier cycle removes the chroma, averaging a single pixel lets a part of it through
+                let clean = window(&luma, SAMPLES_PER_CYCLE);
+                let leaky = window(&luma, SAMPLES_PER_PIXEL);
+                let luma_value = clean + self.settings.artifacts * (leaky - clean);
+                let blurred = window(&luma, 2 * SAMPLES_PER_CYCLE);
+                let luma_value = luma_value + self.settings.sharpness * (luma_value - blurred);
+
+                let saturation = 1.0 + self.settings.saturation;
+                let i = window(&in_phase, SAMPLES_PER_CYCLE) * saturation;
+                let q = window(&quadrature, SAMPLES_PER_CYCLE) * saturation;
+
+                let (r, g, b) = yiq_to_rgb(luma_value, i, q);
+                pixels.extend_from_slice(&[r, g, b]);
+            }
+            pixels.extend_from_within(line_start..);
+        }
+
+        Image::new(NTSC_WIDTH, NTSC_HEIGHT, pixels)
+    }
+}
@@ -20,13 +20,22 @@ pub struct OpCode {
 impl OpCode {
     fn new (code: u8, name: &'static str, len: u8, cycles: u8, mode: AddressingMode) -> Self {
         OpCode {
-            code: code,
-            name: name,
-            len: len,
-            cycles: cycles,
-            mode: mode,
+            code,
+            name,
+            len,
+            cycles,
+            mode,
         }
     }
+
+    // read instructions with indexed addressing take one additional cycle in case the indexing crosses a page,
+    // the write and read-modify-write instructions always take the longer path, which is part of their base cycles
+    pub fn has_page_cross_penalty (&self) -> bool {
+        let is_indexed = matches!(self.mode, AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectY);
+        let is_read = matches!(self.name, "ADC" | "AND" | "CMP" | "EOR" | "LDA" | "LDX" | "LDY" | "ORA" | "SBC" | "*LAX" | "*LAS" | "*NOP");
+
+        is_indexed && is_read
+    }
 }
 
 lazy_static! {
@@ -43,6 +52,8 @@ lazy_static! {
         // in case of a page crossing 0x7D is one cycle longer
         OpCode::new(0x7D, "ADC", 3, 4, AddressingMode::AbsoluteX),
 
+        OpCode::new(0x4B, "*ALR", 2, 2, AddressingMode::Immediate),
+
         OpCode::new(0x0B, "*ANC", 2, 2, AddressingMode::Immediate),
         OpCode::new(0x2B, "*ANC", 2, 2, AddressingMode::Immediate),
 
@@ -58,12 +69,16 @@ lazy_static! {
         // in case of a page crossing 0x3D is one cycle longer
         OpCode::new(0x3D, "AND", 3, 4, AddressingMode::AbsoluteX),
 
+        OpCode::new(0x6B, "*ARR", 2, 2, AddressingMode::Immediate),
+
         OpCode::new(0x06, "ASL", 2, 5, AddressingMode::ZeroPage),
         OpCode::new(0x0A, "ASL", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0x0E, "ASL", 3, 6, AddressingMode::Absolute),
         OpCode::new(0x16, "ASL", 2, 6, AddressingMode::ZeroPageX),
         OpCode::new(0x1E, "ASL", 3, 7, AddressingMode::AbsoluteX),
 
+        OpCode::new(0xCB, "*AXS", 2, 2, AddressingMode::Immediate),
+
         // in case of a page crossing 0xF0 is two cycles longer, 1 cycle longer of branch succeeds
         OpCode::new(0x90, "BCC", 2, 2, AddressingMode::NoneAddressing),
 
@@ -163,11 +178,28 @@ lazy_static! {
         OpCode::new(0xE3, "*ISB", 2, 8, AddressingMode::IndirectX),
         OpCode::new(0xF3, "*ISB", 2, 8, AddressingMode::IndirectY),
 
-        OpCode::new(0x4C, "JMP", 3, 2, AddressingMode::Absolute),
+        // the jam opcodes halt the cpu, it can only be recovered by a reset
+        OpCode::new(0x02, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x12, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x22, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x32, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x42, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x52, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x62, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x72, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x92, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xB2, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xD2, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xF2, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+
+        OpCode::new(0x4C, "JMP", 3, 3, AddressingMode::Absolute),
         OpCode::new(0x6C, "JMP", 3, 5, AddressingMode::Indirect),
 
         OpCode::new(0x20, "JSR", 3, 6, AddressingMode::Absolute),
 
+        // in case of page crossing the instruction is one cycle longer
+        OpCode::new(0xBB, "*LAS", 3, 4, AddressingMode::AbsoluteY),
+
         OpCode::new(0xA3, "*LAX", 2, 6, AddressingMode::IndirectX),
         OpCode::new(0xA7, "*LAX", 2, 3, AddressingMode::ZeroPage),
         OpCode::new(0xAF, "*LAX", 3, 4, AddressingMode::Absolute),
@@ -209,6 +241,8 @@ lazy_static! {
         OpCode::new(0x56, "LSR", 2, 6, AddressingMode::ZeroPageX),
         OpCode::new(0x5E, "LSR", 3, 7, AddressingMode::AbsoluteX),
 
+        OpCode::new(0xAB, "*LXA", 2, 2, AddressingMode::Immediate),
+
         // normal nop
         OpCode::new(0xEA, "NOP", 1, 2, AddressingMode::NoneAddressing), // this is the official NOP, the others belong to the undocumented opcodes
 
@@ -221,24 +255,24 @@ lazy_static! {
         OpCode::new(0xFA, "*NOP", 1, 2, AddressingMode::NoneAddressing),
 
         // illegal double no-ops
-        OpCode::new(0x04, "*NOP", 2, 2, AddressingMode::ZeroPage),
-        OpCode::new(0x14, "*NOP", 2, 2, AddressingMode::ZeroPageX),
-        OpCode::new(0x34, "*NOP", 2, 2, AddressingMode::ZeroPageX),
-        OpCode::new(0x44, "*NOP", 2, 2, AddressingMode::ZeroPage),
-        OpCode::new(0x54, "*NOP", 2, 2, AddressingMode::ZeroPageX),
-        OpCode::new(0x64, "*NOP", 2, 2, AddressingMode::ZeroPage),
-        OpCode::new(0x74, "*NOP", 2, 2, AddressingMode::ZeroPageX),
+        OpCode::new(0x04, "*NOP", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0x14, "*NOP", 2, 4, AddressingMode::ZeroPageX),
+        OpCode::new(0x34, "*NOP", 2, 4, AddressingMode::ZeroPageX),
+        OpCode::new(0x44, "*NOP", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0x54, "*NOP", 2, 4, AddressingMode::ZeroPageX),
+        OpCode::new(0x64, "*NOP", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0x74, "*NOP", 2, 4, AddressingMode::ZeroPageX),
         OpCode::new(0x80, "*NOP", 2, 2, AddressingMode::Immediate),
         OpCode::new(0x82, "*NOP", 2, 2, AddressingMode::Immediate),
         OpCode::new(0x89, "*NOP", 2, 2, AddressingMode::Immediate),
         OpCode::new(0xC2, "*NOP", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xD4, "*NOP", 2, 2, AddressingMode::ZeroPageX),
+        OpCode::new(0xD4, "*NOP", 2, 4, AddressingMode::ZeroPageX),
         OpCode::new(0xE2, "*NOP", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xF4, "*NOP", 2, 2, AddressingMode::ZeroPageX),
+        OpCode::new(0xF4, "*NOP", 2, 4, AddressingMode::ZeroPageX),
 
         // illegal triple no-ops
         OpCode::new(0x0C, "*NOP", 3, 4, AddressingMode::Absolute),
-        // in case of crossing of page boundary add 2 cycles for all the following opcodes
+        // in case of crossing of page boundary add 1 cycle for all the following opcodes
         OpCode::new(0x1C, "*NOP", 3, 4, AddressingMode::AbsoluteX),
         OpCode::new(0x3C, "*NOP", 3, 4, AddressingMode::AbsoluteX),
         OpCode::new(0x5C, "*NOP", 3, 4, AddressingMode::AbsoluteX),
@@ -320,6 +354,13 @@ lazy_static! {
         OpCode::new(0xF8, "SED", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0x78, "SEI", 1, 2, AddressingMode::NoneAddressing),
 
+        OpCode::new(0x93, "*SHA", 2, 6, AddressingMode::IndirectY),
+        OpCode::new(0x9F, "*SHA", 3, 5, AddressingMode::AbsoluteY),
+
+        OpCode::new(0x9E, "*SHX", 3, 5, AddressingMode::AbsoluteY),
+
+        OpCode::new(0x9C, "*SHY", 3, 5, AddressingMode::AbsoluteX),
+
         OpCode::new(0x03, "*SLO", 2, 8, AddressingMode::IndirectX),
         OpCode::new(0x07, "*SLO", 2, 5, AddressingMode::ZeroPage),
         OpCode::new(0x0F, "*SLO", 3, 6, AddressingMode::Absolute),
@@ -348,9 +389,11 @@ lazy_static! {
         OpCode::new(0x8E, "STX", 3, 4, AddressingMode::Absolute),
         OpCode::new(0x96, "STX", 2, 4, AddressingMode::ZeroPageY),
 
-        OpCode::new(0x84, "STY", 2, 6, AddressingMode::ZeroPage),
+        OpCode::new(0x84, "STY", 2, 3, AddressingMode::ZeroPage),
         OpCode::new(0x8C, "STY", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0x94, "STY", 2, 3, AddressingMode::ZeroPageX),
+        OpCode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPageX),
+
+        OpCode::new(0x9B, "*TAS", 3, 5, AddressingMode::AbsoluteY),
 
         OpCode::new(0xAA, "TAX", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0xA8, "TAY", 1, 2, AddressingMode::NoneAddressing),
@@ -358,6 +401,8 @@ lazy_static! {
         OpCode::new(0x8A, "TXA", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0x9A, "TXS", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0x98, "TYA", 1, 2, AddressingMode::NoneAddressing),
+
+        OpCode::new(0x8B, "*XAA", 2, 2, AddressingMode::Immediate),
     ];
 
     pub static ref OPCODES_MAP: HashMap<u8, &'static OpCode> = {
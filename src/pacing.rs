@@ -0,0 +1,92 @@
+/*
+Paces the emulation to the exact frame rate of the region, independent of the refresh rate of the display.
+The speed scales the frame rate: fast-forward runs a few times faster or as fast as possible, slow motion slower.
+The caller emulates a frame, then sleeps for the time returned by end_frame.
+ */
+
+use std::time::{Duration, Instant};
+
+// fast-forward without a limit
+pub const UNCAPPED: f64 = f64::INFINITY;
+
+// slower speeds would make a frame last for hours
+pub const MIN_SPEED: f64 = 0.01;
+
+pub struct FrameLimiter {
+    // the duration of a frame at normal speed
+    native_frame_duration: Duration,
+    speed: f64,
+    // when the next frame is due
+    next_frame: Instant,
+    last_present: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new(frames_per_second: f64, now: Instant) -> Self {
+        FrameLimiter {
+            native_frame_duration: Duration::from_secs_f64(1.0 / frames_per_second),
+            speed: 1.0,
+            next_frame: now,
+            last_present: None,
+        }
+    }
+
+    pub fn native_frame_duration(&self) -> Duration {
+        self.native_frame_duration
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    // the pacing starts over from now, so a faster speed does not catch up on frames of the slower one,
+    // a speed of 0 or one which is not finite runs uncapped
+    pub fn set_speed(&mut self, speed: f64, now: Instant) -> Result<(), String> {
+        if speed < 0.0 {
+            return Err(format!("the speed {} is negative", speed));
+        }
+        if speed > 0.0 && speed < MIN_SPEED {
+            return Err(format!("the speed {} is slower than the minimum of {}", speed, MIN_SPEED));
+        }
+        self.speed = if speed == 0.0 || !speed.is_finite() { UNCAPPED } else { speed };
+        self.next_frame = now;
+        Ok(())
+    }
+
+    // the duration of a frame at the current speed, zero when uncapped
+    pub fn frame_duration(&self) -> Duration {
+        if self.speed.is_finite() {
+            self.native_frame_duration.div_f64(self.speed)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    // returns how long to wait before the next frame, the waits add up to the exact frame rate
+    pub fn end_frame(&mut self, now: Instant) -> Duration {
+        let frame_duration = self.frame_duration();
+        self.next_frame += frame_duration;
+        if self.next_frame > now {
+            return self.next_frame - now;
+        }
+        if now - self.next_frame > frame_duration {
+            // the emulation has fallen behind, e.g. after a pause, it continues from now instead of catching up
+            self.next_frame = now;
+        }
+        Duration::ZERO
+    }
+
+    // while fast-forwarding, frames are shown at most at the normal frame rate, the others are only emulated
+    pub fn should_present(&mut self, now: Instant) -> bool {
+        if self.speed <= 1.0 {
+            return true;
+        }
+        match self.last_present {
+            Some(last_present) if now - last_present < self.native_frame_duration => false,
+            _ => {
+                self.last_present = Some(now);
+                true
+            },
+        }
+    }
+}
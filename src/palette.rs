@@ -0,0 +1,162 @@
+/*
+The NES does not output RGB values, the PPU generates a video signal directly from the 6 bit palette indices.
+The system palette below is the approximation of the 2C02 output used in https://bugzmanov.github.io/nes_ebook/chapter_6_3.html
+The PPUMASK emphasis bits darken the colors, so a full palette has 8 variants of the 64 colors,
+which .pal files of other emulators either include (512 colors) or leave to be derived (64 colors).
+ */
+
+use std::fs;
+
+pub static SYSTEM_PALETTE: [(u8, u8, u8); 64] = [
+    (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96), (0xA1, 0x00, 0x5E),
+    (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00), (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00),
+    (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E), (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05),
+    (0x05, 0x05, 0x05), (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
+    (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00), (0xC4, 0x62, 0x00),
+    (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55), (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21),
+    (0x09, 0x09, 0x09), (0x09, 0x09, 0x09), (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF),
+    (0xD4, 0x80, 0xFF), (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
+    (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4), (0x05, 0xFB, 0xFF),
+    (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D), (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF),
+    (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB), (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0),
+    (0xFF, 0xEF, 0xA6), (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
+    (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
+];
+
+// a palette entry for every pixel value of the frame: the 6 bit color index combined with the 3 emphasis bits
+pub const PALETTE_SIZE: usize = 512;
+
+// the attenuation of the signal by an emphasis bit, see https://www.nesdev.org/wiki/NTSC_video
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+// the voltages of the video signal relative to sync, the low and the high level of the four brightness levels
+const SIGNAL_BLACK: f32 = 0.518;
+const SIGNAL_WHITE: f32 = 1.962;
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+// the phase of hue 0 relative to the color burst in twelfths of a color cycle
+const HUE_PHASE: f32 = 3.9;
+
+// the colors of a palette as loaded from a .pal file or generated from the video signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Palette {
+    // the 64 colors without emphasis, the emphasized variants are derived by attenuating the other channels
+    pub fn from_colors(colors: &[(u8, u8, u8)]) -> Palette {
+        let mut result = Vec::with_capacity(PALETTE_SIZE);
+        for emphasis in 0..8 {
+            for &(r, g, b) in colors.iter().take(64) {
+                // an emphasized color darkens the two other ones
+                let attenuate = |value: u8, keep: bool| if keep { value } else { (value as f32 * EMPHASIS_ATTENUATION) as u8 };
+                let (mut r, mut g, mut b) = (r, g, b);
+                for bit in 0..3 {
+                    if emphasis & (1 << bit) != 0 {
+                        r = attenuate(r, bit == 0);
+                        g = attenuate(g, bit == 1);
+                        b = attenuate(b, bit == 2);
+                    }
+                }
+                result.push((r, g, b));
+            }
+        }
+        Palette { colors: result }
+    }
+
+    // a .pal file holds 64 or 512 RGB triplets, the latter including all emphasis variants
+    pub fn parse(data: &[u8]) -> Result<Palette, String> {
+        let colors: Vec<(u8, u8, u8)> = data.chunks_exact(3).map(|rgb| (rgb[0], rgb[1], rgb[2])).collect();
+        match data.len() {
+            192 => Ok(Palette::from_colors(&colors)),
+            1536 => Ok(Palette { colors }),
+            length => Err(format!("a palette has 192 or 1536 bytes, not {}", length)),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let data = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        Palette::parse(&data).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    // a built-in palette by name or a .pal file
+    pub fn select(name: &str) -> Result<Palette, String> {
+        match name {
+            "default" => Ok(Palette::default()),
+            "ntsc" => Ok(Palette::ntsc()),
+            path => Palette::load(path),
+        }
+    }
+
+    // the palette of the 2C02 decoded from its video signal, see https://www.nesdev.org/wiki/NTSC_video
+    pub fn ntsc() -> Palette {
+        let mut colors = Vec::with_capacity(PALETTE_SIZE);
+        for pixel in 0..PALETTE_SIZE as u16 {
+            let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+            for phase in 0..12 {
+                let signal = ntsc_level(pixel, phase);
+                let angle = ntsc_phase_angle(phase);
+                y += signal;
+                i += signal * angle.cos();
+                q += signal * angle.sin();
+            }
+            colors.push(yiq_to_rgb(y / 12.0, i / 12.0, q / 12.0));
+        }
+        Palette { colors }
+    }
+
+    // the color of a pixel of the frame
+    pub fn color(&self, pixel: u16) -> (u8, u8, u8) {
+        self.colors[pixel as usize % PALETTE_SIZE]
+    }
+}
+
+// the palette of the nes_ebook with derived emphasis colors
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::from_colors(&SYSTEM_PALETTE)
+    }
+}
+
+// the voltage the PPU outputs for the pixel value at one of the 12 phases of the color subcarrier:
+// a square wave between the low and the high level of the brightness, in phase with the hue
+pub fn ntsc_signal(pixel: u16, phase: usize) -> f32 {
+    let color = (pixel & 0x0F) as usize;
+    // the colors $xE and $xF are black
+    let level = if color > 13 { 1 } else { (pixel >> 4) as usize & 0b11 };
+    let emphasis = (pixel >> 6) & 0b111;
+
+    let in_color_phase = |color: usize| (color + phase) % 12 < 6;
+    let high = if color > 12 { SIGNAL_LOW[level] } else { SIGNAL_HIGH[level] };
+    let low = if color == 0 { SIGNAL_HIGH[level] } else { SIGNAL_LOW[level] };
+    let signal = if in_color_phase(color) { high } else { low };
+
+    // every emphasis bit attenuates the signal during a third of the color cycle
+    if (emphasis & 0b001 != 0 && in_color_phase(0))
+        || (emphasis & 0b010 != 0 && in_color_phase(4))
+        || (emphasis & 0b100 != 0 && in_color_phase(8)) {
+        signal * EMPHASIS_ATTENUATION
+    } else {
+        signal
+    }
+}
+
+// the signal scaled to 0 for black and 1 for white
+pub fn ntsc_level(pixel: u16, phase: usize) -> f32 {
+    (ntsc_signal(pixel, phase) - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+// the angle of the color subcarrier the I and Q components are demodulated with
+pub fn ntsc_phase_angle(phase: usize) -> f32 {
+    std::f32::consts::PI * (phase as f32 + HUE_PHASE) / 6.0
+}
+
+pub fn yiq_to_rgb(y: f32, i: f32, q: f32) -> (u8, u8, u8) {
+    let clamp = |value: f32| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    (
+        clamp(y + 0.946_882 * i + 0.623_557 * q),
+        clamp(y - 0.274_788 * i - 0.635_691 * q),
+        clamp(y - 1.108_545 * i + 1.709_007 * q),
+    )
+}
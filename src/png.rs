@@ -0,0 +1,181 @@
+/*
+A minimal PNG encoder for RGB24 images, see https://www.w3.org/TR/png/
+The image data is stored uncompressed (deflate "stored" blocks), which keeps the encoder tiny,
+the files are larger than necessary but every viewer can open them.
+The decoder only reads such uncompressed RGB24 files, e.g. the golden images of the regression tests.
+ */
+
+use std::fs;
+use std::io;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const COLOR_TYPE_RGB: u8 = 2;
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+// the CRC-32 of the chunks (polynomial 0xEDB88320)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// the checksum of the zlib stream
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// wraps the data into a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(MAX_STORED_BLOCK).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        let is_last = i == blocks.len() - 1;
+        let length = block.len() as u16;
+
+        stream.push(is_last as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+// encodes an RGB24 image, the pixels are stored row by row
+pub fn encode_rgb(width: usize, height: usize, pixels: &[u8]) -> Result<Vec<u8>, String> {
+    // PNG images have at least one pixel
+    if width == 0 || height == 0 {
+        return Err(format!("an image of {}x{} pixels can not be saved as PNG", width, height));
+    }
+    if pixels.len() != width * height * 3 {
+        return Err(format!("the image has {} bytes instead of width * height RGB pixels", pixels.len()));
+    }
+
+    let mut png = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, no interlacing
+    header.extend_from_slice(&[8, COLOR_TYPE_RGB, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // every row starts with its filter type, 0 = none
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+pub fn save_rgb(path: &str, width: usize, height: usize, pixels: &[u8]) -> io::Result<()> {
+    let png = encode_rgb(width, height, pixels).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    fs::write(path, png)
+}
+
+// decodes an 8 bit RGB image with uncompressed image data into its width, height and RGB24 pixels
+pub fn decode_rgb(png: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+    if !png.starts_with(&SIGNATURE) {
+        return Err("not a PNG file".to_string());
+    }
+
+    let mut header = None;
+    let mut zlib = Vec::new();
+    let mut position = SIGNATURE.len();
+    while position + 12 <= png.len() {
+        let length = u32::from_be_bytes(png[position..position + 4].try_into().unwrap()) as usize;
+        let chunk_type = &png[position + 4..position + 8];
+        let data = png.get(position + 8..position + 8 + length).ok_or("truncated chunk")?;
+        match chunk_type {
+            b"IHDR" => header = Some(data.to_vec()),
+            b"IDAT" => zlib.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {},
+        }
+        position += length + 12;
+    }
+
+    let header = header.ok_or("missing IHDR chunk")?;
+    if header.len() != 13 {
+        return Err(format!("the IHDR chunk has {} instead of 13 bytes", header.len()));
+    }
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    if header[8..13] != [8, COLOR_TYPE_RGB, 0, 0, 0] {
+        return Err("only 8 bit RGB images without interlacing are supported".to_string());
+    }
+
+    let raw = inflate_stored(&zlib)?;
+    let stride = width * 3 + 1;
+    if raw.len() != height * stride {
+        return Err("the image data does not match the size of the image".to_string());
+    }
+
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for row in raw.chunks(stride) {
+        if row[0] != 0 {
+            return Err("only unfiltered rows are supported".to_string());
+        }
+        pixels.extend_from_slice(&row[1..]);
+    }
+    Ok((width, height, pixels))
+}
+
+// the data of a zlib stream of uncompressed deflate blocks, as written by zlib_stored
+fn inflate_stored(stream: &[u8]) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    let mut position = 2;
+    loop {
+        let block = stream.get(position..position + 5).ok_or("truncated image data")?;
+        if block[0] & 0b110 != 0 {
+            return Err("only uncompressed image data is supported".to_string());
+        }
+
+        let length = u16::from_le_bytes([block[1], block[2]]) as usize;
+        data.extend_from_slice(stream.get(position + 5..position + 5 + length).ok_or("truncated image data")?);
+        position += 5 + length;
+
+        if block[0] & 1 != 0 {
+            break;
+        }
+    }
+
+    let checksum = stream.get(position..position + 4).ok_or("truncated image data")?;
+    if checksum != adler32(&data).to_be_bytes() {
+        return Err("the checksum of the image data does not match".to_string());
+    }
+    Ok(data)
+}
@@ -0,0 +1,616 @@
+/*
+Useful documentation:
+https://www.nesdev.org/wiki/PPU_registers
+https://www.nesdev.org/wiki/PPU_scrolling
+https://www.nesdev.org/wiki/PPU_rendering
+https://bugzmanov.github.io/nes_ebook/chapter_6.html
+
+The PPU runs 3 dots per cpu cycle, a frame consists of 262 scanlines with 341 dots each:
+  0 - 239  visible scanlines
+  240      post render scanline
+  241 - 260 vertical blank, the NMI is raised at dot 1 of scanline 241
+  261      pre render scanline
+PAL and Dendy consoles have 312 scanlines, the vertical blank of the PAL PPU starts at scanline 241 as well
+and lasts 70 scanlines, the Dendy one starts 50 scanlines later at 291. Only the NTSC PPU skips a dot on odd frames.
+
+The background and the sprites of a visible scanline are rendered at once at dot 1, the scroll
+registers are updated at the same dots as on the real hardware so mid frame scroll changes work
+on a per scanline basis.
+ */
+
+use crate::cartridge::Mirroring;
+use crate::frame::Frame;
+use crate::region::Region;
+
+pub const DOTS_PER_SCANLINE: u16 = 341;
+const VISIBLE_SCANLINES: u16 = 240;
+
+const NAMETABLE_START: u16 = 0x2000;
+const PALETTE_START: u16 = 0x3F00;
+const CHARACTER_RAM_SIZE: usize = 0x2000;
+
+// the bits of the PPU io latch decay to 0 roughly 600ms after they have been driven the last time
+const IO_LATCH_DECAY_FRAMES: u64 = 36;
+
+bitflags::bitflags! {
+    // $2000
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ControlRegister : u8 {
+        const Nametable1 = 0b0000_0001;
+        const Nametable2 = 0b0000_0010;
+        const VramAddIncrement = 0b0000_0100;
+        const SpritePatternAddress = 0b0000_1000;
+        const BackgroundPatternAddress = 0b0001_0000;
+        const SpriteSize = 0b0010_0000;
+        const MasterSlaveSelect = 0b0100_0000;
+        const GenerateNmi = 0b1000_0000;
+    }
+}
+
+bitflags::bitflags! {
+    // $2001
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct MaskRegister : u8 {
+        const Greyscale = 0b0000_0001;
+        const ShowBackgroundLeft = 0b0000_0010;
+        const ShowSpritesLeft = 0b0000_0100;
+        const ShowBackground = 0b0000_1000;
+        const ShowSprites = 0b0001_0000;
+        const EmphasizeRed = 0b0010_0000;
+        const EmphasizeGreen = 0b0100_0000;
+        const EmphasizeBlue = 0b1000_0000;
+    }
+}
+
+bitflags::bitflags! {
+    // $2002, the lower 5 bits are not driven by the PPU
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct StatusRegister : u8 {
+        const SpriteOverflow = 0b0010_0000;
+        const SpriteZeroHit = 0b0100_0000;
+        const VerticalBlank = 0b1000_0000;
+    }
+}
+
+impl MaskRegister {
+    pub fn is_rendering_enabled(&self) -> bool {
+        self.intersects(MaskRegister::ShowBackground | MaskRegister::ShowSprites)
+    }
+}
+
+pub struct PPU {
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    pub oam_address: u8,
+    pub oam: [u8; 256],
+    pub vram: [u8; 0x1000],
+    pub palette: [u8; 32],
+    pub chr: Vec<u8>,
+    chr_is_ram: bool,
+    pub mirroring: Mirroring,
+    region: Region,
+
+    // the internal scroll registers as described in https://www.nesdev.org/wiki/PPU_scrolling
+    // v: current vram address, t: temporary vram address, x: fine x scroll, w: write toggle
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+    read_buffer: u8,
+
+    // the data bus between cpu and PPU, reads of write only registers return its content,
+    // each bit decays separately, so the frame in which it was driven last is kept per bit
+    io_latch: u8,
+    io_latch_refresh_frame: [u64; 8],
+
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame_count: u64,
+    odd_frame: bool,
+    nmi_pending: bool,
+    frame_complete: bool,
+    sprite_zero_hit_dot: Option<u16>,
+    pub frame: Frame,
+}
+
+impl PPU {
+    pub fn new(chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        // cartridges without character rom provide 8KB of character ram instead
+        let chr_is_ram = chr.is_empty();
+        let chr = if chr_is_ram { vec![0; CHARACTER_RAM_SIZE] } else { chr };
+
+        PPU {
+            ctrl: ControlRegister::empty(),
+            mask: MaskRegister::empty(),
+            status: StatusRegister::empty(),
+            oam_address: 0,
+            oam: [0; 256],
+            vram: [0; 0x1000],
+            palette: [0; 32],
+            chr,
+            chr_is_ram,
+            mirroring,
+            region: Region::Ntsc,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            io_latch_refresh_frame: [0; 8],
+            scanline: 0,
+            dot: 0,
+            frame_count: 0,
+            odd_frame: false,
+            nmi_pending: false,
+            frame_complete: false,
+            sprite_zero_hit_dot: None,
+            frame: Frame::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ctrl = ControlRegister::empty();
+        self.mask = MaskRegister::empty();
+        self.w = false;
+        self.x = 0;
+        self.t = 0;
+        self.read_buffer = 0;
+        self.scanline = 0;
+        self.dot = 0;
+        self.odd_frame = false;
+        self.nmi_pending = false;
+        self.frame_complete = false;
+        self.sprite_zero_hit_dot = None;
+    }
+
+    /*
+        Register access
+     */
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        let result = self.peek_register(addr);
+
+        match addr & 0x0007 {
+            2 => {
+                self.status.remove(StatusRegister::VerticalBlank);
+                self.w = false;
+                self.refresh_io_latch(result, 0b1110_0000);
+            },
+            4 => self.refresh_io_latch(result, 0xFF),
+            7 => {
+                let addr = self.v & 0x3FFF;
+                if addr >= PALETTE_START {
+                    // palette reads are not buffered, but the buffer is filled with the nametable "below"
+                    self.read_buffer = self.read_vram(addr - 0x1000);
+                    self.refresh_io_latch(result, 0b0011_1111);
+                } else {
+                    self.read_buffer = self.read_vram(addr);
+                    self.refresh_io_latch(result, 0xFF);
+                }
+                self.increment_vram_address();
+            },
+            // the other registers are write only
+            _ => {},
+        }
+
+        result
+    }
+
+    // the scroll position within the 512x480 pixels of the four nametables, taken from t and fine x,
+    // which hold the scroll of the next frame once the game has written $2000/$2005
+    pub fn scroll_position(&self) -> (u16, u16) {
+        let x = ((self.t & 0x001F) << 3) | self.x as u16 | ((self.t & 0x0400) >> 2);
+        let y = (((self.t & 0x03E0) >> 5) << 3) | ((self.t & 0x7000) >> 12) | if self.t & 0x0800 != 0 { 240 } else { 0 };
+        (x, y)
+    }
+
+    // reads a register without side effects, used for tracing and debugging,
+    // the bits not driven by the register come from the io latch
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        let io_latch = self.decayed_io_latch();
+
+        match addr & 0x0007 {
+            2 => self.status.bits() | (io_latch & 0b0001_1111),
+            4 => {
+                // bits 2-4 of the sprite attributes do not exist and are read back as 0
+                let value = self.oam[self.oam_address as usize];
+                if self.oam_address & 0b11 == 0b10 { value & 0b1110_0011 } else { value }
+            },
+            7 => {
+                let addr = self.v & 0x3FFF;
+                if addr >= PALETTE_START {
+                    self.read_palette(addr) | (io_latch & 0b1100_0000)
+                } else {
+                    self.read_buffer
+                }
+            },
+            _ => io_latch,
+        }
+    }
+
+    fn refresh_io_latch(&mut self, value: u8, driven_bits: u8) {
+        self.io_latch = (self.decayed_io_latch() & !driven_bits) | (value & driven_bits);
+        for bit in 0..8 {
+            if driven_bits & (1 << bit) != 0 {
+                self.io_latch_refresh_frame[bit] = self.frame_count;
+            }
+        }
+    }
+
+    fn decayed_io_latch(&self) -> u8 {
+        let mut value = self.io_latch;
+        for bit in 0..8 {
+            if self.frame_count - self.io_latch_refresh_frame[bit] > IO_LATCH_DECAY_FRAMES {
+                value &= !(1 << bit);
+            }
+        }
+        value
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        self.refresh_io_latch(data, 0xFF);
+
+        match addr & 0x0007 {
+            0 => {
+                let nmi_was_enabled = self.ctrl.contains(ControlRegister::GenerateNmi);
+                self.ctrl = ControlRegister::from_bits_retain(data);
+                self.t = (self.t & 0xF3FF) | (((data & 0b0000_0011) as u16) << 10);
+
+                // enabling the NMI during vertical blank immediately raises it
+                if !nmi_was_enabled
+                    && self.ctrl.contains(ControlRegister::GenerateNmi)
+                    && self.status.contains(StatusRegister::VerticalBlank) {
+                    self.nmi_pending = true;
+                }
+            },
+            1 => self.mask = MaskRegister::from_bits_retain(data),
+            3 => self.oam_address = data,
+            4 => {
+                self.oam[self.oam_address as usize] = data;
+                self.oam_address = self.oam_address.wrapping_add(1);
+            },
+            5 => {
+                if !self.w {
+                    self.t = (self.t & 0xFFE0) | ((data >> 3) as u16);
+                    self.x = data & 0b0000_0111;
+                } else {
+                    self.t = (self.t & 0x8C1F) | (((data & 0b0000_0111) as u16) << 12) | (((data & 0b1111_1000) as u16) << 2);
+                }
+                self.w = !self.w;
+            },
+            6 => {
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | (((data & 0b0011_1111) as u16) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | data as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            },
+            7 => {
+                let addr = self.v & 0x3FFF;
+                self.write_vram(addr, data);
+                self.increment_vram_address();
+            },
+            // $2002 is read only
+            _ => {},
+        }
+    }
+
+    // returns true once for every NMI raised at the start of the vertical blank
+    pub fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    // returns true once after the last visible scanline of a frame has been rendered
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    /*
+        Timing
+     */
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn tick(&mut self, dots: u16) {
+        for _ in 0..dots {
+            self.tick_dot();
+        }
+    }
+
+    fn tick_dot(&mut self) {
+        let rendering = self.mask.is_rendering_enabled();
+        let scanlines_per_frame = self.region.scanlines_per_frame();
+        let pre_render_scanline = scanlines_per_frame - 1;
+
+        if self.scanline < VISIBLE_SCANLINES {
+            if self.dot == 1 {
+                self.render_scanline();
+            }
+            if self.sprite_zero_hit_dot == Some(self.dot) {
+                self.status.insert(StatusRegister::SpriteZeroHit);
+                self.sprite_zero_hit_dot = None;
+            }
+        }
+
+        if self.scanline == self.region.vblank_scanline() && self.dot == 1 {
+            self.status.insert(StatusRegister::VerticalBlank);
+            if self.ctrl.contains(ControlRegister::GenerateNmi) {
+                self.nmi_pending = true;
+            }
+            self.frame_complete = true;
+        }
+
+        if self.scanline == pre_render_scanline && self.dot == 1 {
+            self.status.remove(StatusRegister::VerticalBlank | StatusRegister::SpriteZeroHit | StatusRegister::SpriteOverflow);
+        }
+
+        if rendering && (self.scanline < VISIBLE_SCANLINES || self.scanline == pre_render_scanline) {
+            if self.dot == 256 {
+                self.increment_y();
+            }
+            if self.dot == 257 {
+                self.copy_horizontal_bits();
+            }
+            if self.scanline == pre_render_scanline && (280..=304).contains(&self.dot) {
+                self.copy_vertical_bits();
+            }
+        }
+
+        self.dot += 1;
+
+        // with rendering enabled the NTSC PPU skips the last dot of the pre render scanline on odd frames
+        if self.scanline == pre_render_scanline && self.dot == DOTS_PER_SCANLINE - 1 && self.odd_frame && rendering
+            && self.region.skips_odd_frame_dot() {
+            self.dot += 1;
+        }
+
+        if self.dot >= DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline >= scanlines_per_frame {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+                self.frame_count += 1;
+            }
+        }
+    }
+
+    /*
+        Memory access
+     */
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => self.chr[addr as usize % self.chr.len()],
+            0x2000..=0x3EFF => self.vram[self.mirror_vram_address(addr)],
+            _ => self.read_palette(addr),
+        }
+    }
+
+    pub fn write_vram(&mut self, addr: u16, data: u8) {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => {
+                // character rom can not be written
+                if self.chr_is_ram {
+                    self.chr[addr as usize] = data;
+                }
+            },
+            0x2000..=0x3EFF => {
+                let index = self.mirror_vram_address(addr);
+                self.vram[index] = data;
+            },
+            _ => {
+                let index = PPU::mirror_palette_address(addr);
+                self.palette[index] = data & 0b0011_1111;
+            },
+        }
+    }
+
+    pub fn read_palette(&self, addr: u16) -> u8 {
+        self.palette[PPU::mirror_palette_address(addr)]
+    }
+
+    // the background colors of the sprite palettes ($3F10, $3F14, $3F18, $3F1C) mirror the ones of the background palettes
+    fn mirror_palette_address(addr: u16) -> usize {
+        let index = (addr & 0x001F) as usize;
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+            _ => index,
+        }
+    }
+
+    // the PPU has 2KB of vram for two nametables, the cartridge decides how the four logical nametables map onto them
+    fn mirror_vram_address(&self, addr: u16) -> usize {
+        let addr = (addr - NAMETABLE_START) & 0x0FFF;
+        let nametable = addr / 0x0400;
+        let offset = addr & 0x03FF;
+
+        let physical_nametable = match self.mirroring {
+            Mirroring::Vertical => nametable & 0b01,
+            Mirroring::Horizontal => nametable >> 1,
+            Mirroring::FourScreen => nametable,
+        };
+
+        (physical_nametable * 0x0400 + offset) as usize
+    }
+
+    fn increment_vram_address(&mut self) {
+        let increment = if self.ctrl.contains(ControlRegister::VramAddIncrement) { 32 } else { 1 };
+        self.v = self.v.wrapping_add(increment) & 0x7FFF;
+    }
+
+    /*
+        Scrolling, v is composed as: yyy NN YYYYY XXXXX
+        (fine y, nametable select, coarse y, coarse x)
+     */
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // coarse y can be set out of bounds, in that case it wraps without switching the nametable
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn copy_horizontal_bits(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_vertical_bits(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    /*
+        Rendering
+     */
+    fn render_scanline(&mut self) {
+        let y = self.scanline as usize;
+        let backdrop = self.read_palette(PALETTE_START);
+
+        // palette indices of the scanline, a value of 0 in the lower two bits marks a transparent pixel
+        let mut background = [0u8; 256];
+        let mut line = [backdrop; 256];
+
+        if self.mask.contains(MaskRegister::ShowBackground) {
+            self.render_background(&mut background);
+            for x in 0..256 {
+                if background[x] & 0b11 != 0 {
+                    line[x] = self.read_palette(PALETTE_START + background[x] as u16);
+                }
+            }
+        }
+
+        if self.mask.contains(MaskRegister::ShowSprites) {
+            self.render_sprites(&background, &mut line);
+        }
+
+        let grey_mask = if self.mask.contains(MaskRegister::Greyscale) { 0x30 } else { 0x3F };
+        let emphasis = ((self.mask.bits() >> 5) as u16) << 6;
+        for (x, color) in line.iter().enumerate() {
+            self.frame.set_pixel(x, y, (*color & grey_mask) as u16 | emphasis);
+        }
+    }
+
+    fn render_background(&self, background: &mut [u8; 256]) {
+        let fine_y = (self.v >> 12) & 0b111;
+        let pattern_base: u16 = if self.ctrl.contains(ControlRegister::BackgroundPatternAddress) { 0x1000 } else { 0 };
+        let show_left = self.mask.contains(MaskRegister::ShowBackgroundLeft);
+
+        let mut v = self.v;
+        // 33 tiles are fetched, since a fine x scroll shifts a part of the 33rd tile onto the screen
+        for tile in 0..33 {
+            let tile_index = self.read_vram(NAMETABLE_START | (v & 0x0FFF)) as u16;
+            let attribute = self.read_vram(0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+            let shift = ((v >> 4) & 0b100) | (v & 0b10);
+            let palette = (attribute >> shift) & 0b11;
+
+            let pattern_address = pattern_base + tile_index * 16 + fine_y;
+            let lo = self.read_vram(pattern_address);
+            let hi = self.read_vram(pattern_address + 8);
+
+            for bit in 0..8 {
+                let screen_x = tile * 8 + bit - self.x as i32;
+                if !(0..256).contains(&screen_x) || (screen_x < 8 && !show_left) {
+                    continue;
+                }
+                let color = ((lo >> (7 - bit)) & 1) | (((hi >> (7 - bit)) & 1) << 1);
+                if color != 0 {
+                    background[screen_x as usize] = (palette << 2) | color;
+                }
+            }
+
+            // increment coarse x, switching the horizontal nametable when wrapping around
+            if v & 0x001F == 31 {
+                v = (v & !0x001F) ^ 0x0400;
+            } else {
+                v += 1;
+            }
+        }
+    }
+
+    fn render_sprites(&mut self, background: &[u8; 256], line: &mut [u8; 256]) {
+        let sprite_height: i16 = if self.ctrl.contains(ControlRegister::SpriteSize) { 16 } else { 8 };
+        let show_left = self.mask.contains(MaskRegister::ShowSpritesLeft);
+        let show_background_left = self.mask.contains(MaskRegister::ShowBackgroundLeft);
+
+        // sprite evaluation, only the first 8 sprites of a scanline are rendered
+        let mut sprites = Vec::with_capacity(8);
+        for i in 0..64 {
+            // sprites are drawn one scanline below their y position
+            let row = self.scanline as i16 - (self.oam[i * 4] as i16 + 1);
+            if (0..sprite_height).contains(&row) {
+                if sprites.len() == 8 {
+                    self.status.insert(StatusRegister::SpriteOverflow);
+                    break;
+                }
+                sprites.push((i, row as u16));
+            }
+        }
+
+        // sprites with a lower index have priority, even if they are behind the background
+        let mut occupied = [false; 256];
+        for (i, row) in sprites {
+            let tile = self.oam[i * 4 + 1] as u16;
+            let attributes = self.oam[i * 4 + 2];
+            let sprite_x = self.oam[i * 4 + 3] as usize;
+
+            let flip_vertical = attributes & 0b1000_0000 > 0;
+            let flip_horizontal = attributes & 0b0100_0000 > 0;
+            let behind_background = attributes & 0b0010_0000 > 0;
+            let palette = 0x10 + ((attributes & 0b11) << 2);
+
+            let row = if flip_vertical { sprite_height as u16 - 1 - row } else { row };
+            let pattern_address = if sprite_height == 16 {
+                // 8x16 sprites select the pattern table with bit 0 of the tile index
+                let base = (tile & 0b1) * 0x1000;
+                base + (tile & 0xFE) * 16 + (row / 8) * 16 + row % 8
+            } else {
+                let base: u16 = if self.ctrl.contains(ControlRegister::SpritePatternAddress) { 0x1000 } else { 0 };
+                base + tile * 16 + row
+            };
+            let lo = self.read_vram(pattern_address);
+            let hi = self.read_vram(pattern_address + 8);
+
+            for bit in 0..8 {
+                let x = sprite_x + bit;
+                if x > 255 || occupied[x] || (x < 8 && !show_left) {
+                    continue;
+                }
+                let shift = if flip_horizontal { bit } else { 7 - bit };
+                let color = ((lo >> shift) & 1) | (((hi >> shift) & 1) << 1);
+                if color == 0 {
+                    continue;
+                }
+                occupied[x] = true;
+
+                let background_opaque = background[x] & 0b11 != 0;
+                if i == 0 && background_opaque && x != 255
+                    && (x >= 8 || show_background_left)
+                    && !self.status.contains(StatusRegister::SpriteZeroHit)
+                    && self.sprite_zero_hit_dot.is_none() {
+                    self.sprite_zero_hit_dot = Some(x as u16 + 1);
+                }
+
+                if !(behind_background && background_opaque) {
+                    line[x] = self.read_palette(PALETTE_START + (palette | color) as u16);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+/*
+Debugging views of the PPU memories, rendered into RGB24 buffers for a debugging window or PNG files:
+  pattern tables  both halves ($0000 and $1000) next to each other, drawn with a selectable palette
+  nametables      all four screens (512x480) with the current scroll window overlaid
+  palettes        the 32 entries of the palette RAM
+  sprites         the 64 sprites of the OAM, as images and as a list
+ */
+
+use std::io;
+
+use crate::font::{self, CHAR_WIDTH, LINE_HEIGHT};
+use crate::palette::SYSTEM_PALETTE;
+use crate::png;
+use crate::ppu::{ControlRegister, PPU};
+
+pub const PATTERN_TABLES_WIDTH: usize = 256;
+pub const PATTERN_TABLES_HEIGHT: usize = 128;
+pub const NAMETABLES_WIDTH: usize = 512;
+pub const NAMETABLES_HEIGHT: usize = 480;
+// the palettes are drawn as two rows (background and sprites) of 16 swatches
+const SWATCH_SIZE: usize = 16;
+pub const PALETTES_WIDTH: usize = 16 * SWATCH_SIZE;
+pub const PALETTES_HEIGHT: usize = 2 * SWATCH_SIZE;
+// the sprites are drawn in 8 rows of 8 cells, the cells are large enough for 8x16 sprites
+pub const SPRITES_WIDTH: usize = 8 * 8;
+pub const SPRITES_HEIGHT: usize = 8 * 16;
+// the 4 background and the 4 sprite palettes
+pub const PALETTE_COUNT: u8 = 8;
+
+const MARGIN: usize = 4;
+const SIDEBAR_X: usize = 2 * MARGIN + NAMETABLES_WIDTH;
+// the sprite list has two columns of 32 lines, every line shows the tile of the sprite next to its OAM bytes
+const SPRITE_LIST_COLUMN_WIDTH: usize = 8 + 2 + 18 * CHAR_WIDTH + 12;
+const PATTERN_TABLES_Y: usize = MARGIN + LINE_HEIGHT;
+const PALETTES_Y: usize = PATTERN_TABLES_Y + PATTERN_TABLES_HEIGHT + LINE_HEIGHT * 2;
+const SPRITE_LIST_Y: usize = PALETTES_Y + PALETTES_HEIGHT + LINE_HEIGHT * 2;
+pub const VIEW_WIDTH: usize = SIDEBAR_X + PATTERN_TABLES_WIDTH + MARGIN;
+pub const VIEW_HEIGHT: usize = SPRITE_LIST_Y + 32 * LINE_HEIGHT + MARGIN;
+
+const BACKGROUND_COLOR: (u8, u8, u8) = (0x10, 0x10, 0x18);
+const TEXT_COLOR: (u8, u8, u8) = (0xC0, 0xC0, 0xC0);
+const SCROLL_COLOR: (u8, u8, u8) = (0xFF, 0x50, 0x30);
+
+fn set_pixel(buffer: &mut [u8], width: usize, x: usize, y: usize, color: (u8, u8, u8)) {
+    let index = (y * width + x) * 3;
+    buffer[index] = color.0;
+    buffer[index + 1] = color.1;
+    buffer[index + 2] = color.2;
+}
+
+// the color of a palette RAM entry, palette is 0-7 and color 0-3
+fn palette_color(ppu: &PPU, palette: u8, color: u8) -> (u8, u8, u8) {
+    let index = ppu.read_palette(0x3F00 + ((palette as u16) << 2) + color as u16);
+    SYSTEM_PALETTE[(index & 0x3F) as usize]
+}
+
+// the 2 bit color of a pixel of the tile at the given pattern address
+fn tile_pixel(ppu: &PPU, pattern_address: u16, x: usize, y: usize) -> u8 {
+    let lo = ppu.read_vram(pattern_address + y as u16);
+    let hi = ppu.read_vram(pattern_address + y as u16 + 8);
+    let shift = 7 - x;
+    ((lo >> shift) & 1) | (((hi >> shift) & 1) << 1)
+}
+
+// both pattern tables, drawn with one of the 8 palettes
+pub fn render_pattern_tables(ppu: &PPU, palette: u8) -> Vec<u8> {
+    let mut buffer = vec![0; PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT * 3];
+    let colors: Vec<(u8, u8, u8)> = (0..4).map(|color| palette_color(ppu, palette % PALETTE_COUNT, color)).collect();
+
+    for table in 0..2 {
+        for tile in 0..256 {
+            let pattern_address = (table * 0x1000 + tile * 16) as u16;
+            let tile_x = table * 128 + (tile % 16) * 8;
+            let tile_y = (tile / 16) * 8;
+
+            for y in 0..8 {
+                for x in 0..8 {
+                    let color = tile_pixel(ppu, pattern_address, x, y);
+                    set_pixel(&mut buffer, PATTERN_TABLES_WIDTH, tile_x + x, tile_y + y, colors[color as usize]);
+                }
+            }
+        }
+    }
+
+    buffer
+}
+
+// the four nametables as they are mirrored, with the background pattern table selected in PPUCTRL
+pub fn render_nametables(ppu: &PPU) -> Vec<u8> {
+    let mut buffer = vec![0; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 3];
+    let pattern_base: u16 = if ppu.ctrl.contains(ControlRegister::BackgroundPatternAddress) { 0x1000 } else { 0 };
+
+    for nametable in 0..4u16 {
+        let base = 0x2000 + nametable * 0x0400;
+        let origin_x = (nametable as usize & 1) * 256;
+        let origin_y = (nametable as usize >> 1) * 240;
+
+        for row in 0..30u16 {
+            for column in 0..32u16 {
+                let tile = ppu.read_vram(base + row * 32 + column) as u16;
+                let attribute = ppu.read_vram(base + 0x03C0 + (row / 4) * 8 + column / 4);
+                let shift = ((row & 0b10) << 1) | (column & 0b10);
+                let palette = (attribute >> shift) & 0b11;
+
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let color = tile_pixel(ppu, pattern_base + tile * 16, x, y);
+                        // color 0 of every background palette is the backdrop color
+                        let rgb = palette_color(ppu, if color == 0 { 0 } else { palette }, color);
+                        let pixel_x = origin_x + column as usize * 8 + x;
+                        let pixel_y = origin_y + row as usize * 8 + y;
+                        set_pixel(&mut buffer, NAMETABLES_WIDTH, pixel_x, pixel_y, rgb);
+                    }
+                }
+            }
+        }
+    }
+
+    // the visible screen, which wraps around at the edges like the scrolling does
+    let (scroll_x, scroll_y) = ppu.scroll_position();
+    let (scroll_x, scroll_y) = (scroll_x as usize, scroll_y as usize);
+    for x in 0..256 {
+        let pixel_x = (scroll_x + x) % NAMETABLES_WIDTH;
+        set_pixel(&mut buffer, NAMETABLES_WIDTH, pixel_x, scroll_y % NAMETABLES_HEIGHT, SCROLL_COLOR);
+        set_pixel(&mut buffer, NAMETABLES_WIDTH, pixel_x, (scroll_y + 239) % NAMETABLES_HEIGHT, SCROLL_COLOR);
+    }
+    for y in 0..240 {
+        let pixel_y = (scroll_y + y) % NAMETABLES_HEIGHT;
+        set_pixel(&mut buffer, NAMETABLES_WIDTH, scroll_x % NAMETABLES_WIDTH, pixel_y, SCROLL_COLOR);
+        set_pixel(&mut buffer, NAMETABLES_WIDTH, (scroll_x + 255) % NAMETABLES_WIDTH, pixel_y, SCROLL_COLOR);
+    }
+
+    buffer
+}
+
+// the background palettes in the upper row, the sprite palettes in the lower one
+pub fn render_palettes(ppu: &PPU) -> Vec<u8> {
+    let mut buffer = vec![0; PALETTES_WIDTH * PALETTES_HEIGHT * 3];
+    for entry in 0..32u8 {
+        let x = (entry as usize % 16) * SWATCH_SIZE;
+        let y = (entry as usize / 16) * SWATCH_SIZE;
+        let color = palette_color(ppu, entry / 4, entry % 4);
+        font::fill_rect(&mut buffer, PALETTES_WIDTH, x, y, SWATCH_SIZE, SWATCH_SIZE, color);
+    }
+    buffer
+}
+
+fn sprite_height(ppu: &PPU) -> usize {
+    if ppu.ctrl.contains(ControlRegister::SpriteSize) { 16 } else { 8 }
+}
+
+// draws the sprite with its palette and flipping, transparent pixels are skipped
+fn draw_sprite(ppu: &PPU, sprite: usize, buffer: &mut [u8], width: usize, origin_x: usize, origin_y: usize, rows: usize) {
+    let tile = ppu.oam[sprite * 4 + 1] as u16;
+    let attributes = ppu.oam[sprite * 4 + 2];
+    let flip_vertical = attributes & 0b1000_0000 > 0;
+    let flip_horizontal = attributes & 0b0100_0000 > 0;
+    let height = sprite_height(ppu);
+
+    for y in 0..rows.min(height) {
+        let row = if flip_vertical { height - 1 - y } else { y };
+        let pattern_address = if height == 16 {
+            // 8x16 sprites select the pattern table with bit 0 of the tile index
+            (tile & 0b1) * 0x1000 + (tile & 0xFE) * 16 + (row as u16 / 8) * 16
+        } else {
+            let base: u16 = if ppu.ctrl.contains(ControlRegister::SpritePatternAddress) { 0x1000 } else { 0 };
+            base + tile * 16
+        };
+
+        for x in 0..8 {
+            let column = if flip_horizontal { 7 - x } else { x };
+            let color = tile_pixel(ppu, pattern_address, column, row % 8);
+            if color != 0 {
+                let rgb = palette_color(ppu, 4 + (attributes & 0b11), color);
+                set_pixel(buffer, width, origin_x + x, origin_y + y, rgb);
+            }
+        }
+    }
+}
+
+// the 64 sprites in OAM order, 8 per row
+pub fn render_sprites(ppu: &PPU) -> Vec<u8> {
+    let mut buffer = vec![0; SPRITES_WIDTH * SPRITES_HEIGHT * 3];
+    font::fill_rect(&mut buffer, SPRITES_WIDTH, 0, 0, SPRITES_WIDTH, SPRITES_HEIGHT, BACKGROUND_COLOR);
+    for sprite in 0..64 {
+        draw_sprite(ppu, sprite, &mut buffer, SPRITES_WIDTH, (sprite % 8) * 8, (sprite / 8) * 16, 16);
+    }
+    buffer
+}
+
+// one line per sprite: the index followed by the x and y position, the tile and the attributes, e.g. "00 X80 Y40 T12 A03"
+pub fn sprite_list(ppu: &PPU) -> Vec<String> {
+    (0..64)
+        .map(|sprite| {
+            let bytes = &ppu.oam[sprite * 4..sprite * 4 + 4];
+            format!("{:02X} X{:02X} Y{:02X} T{:02X} A{:02X}", sprite, bytes[3], bytes[0], bytes[1], bytes[2])
+        })
+        .collect()
+}
+
+// all views in one RGB24 buffer of VIEW_WIDTH x VIEW_HEIGHT pixels, for the debugging window
+pub fn render(ppu: &PPU, palette: u8) -> Vec<u8> {
+    let mut buffer = vec![0; VIEW_WIDTH * VIEW_HEIGHT * 3];
+    font::fill_rect(&mut buffer, VIEW_WIDTH, 0, 0, VIEW_WIDTH, VIEW_HEIGHT, BACKGROUND_COLOR);
+
+    let mut blit = |image: &[u8], x: usize, y: usize, width: usize| {
+        for (row, pixels) in image.chunks(width * 3).enumerate() {
+            let start = ((y + row) * VIEW_WIDTH + x) * 3;
+            buffer[start..start + pixels.len()].copy_from_slice(pixels);
+        }
+    };
+    blit(&render_nametables(ppu), MARGIN, MARGIN, NAMETABLES_WIDTH);
+    blit(&render_pattern_tables(ppu, palette), SIDEBAR_X, PATTERN_TABLES_Y, PATTERN_TABLES_WIDTH);
+    blit(&render_palettes(ppu), SIDEBAR_X, PALETTES_Y, PALETTES_WIDTH);
+
+    let (scroll_x, scroll_y) = ppu.scroll_position();
+    let headers = [
+        (PATTERN_TABLES_Y, format!("PATTERN TABLES  PALETTE {}", palette % PALETTE_COUNT)),
+        (PALETTES_Y, format!("PALETTES  SCROLL {}/{}", scroll_x, scroll_y)),
+        (SPRITE_LIST_Y, "SPRITES".to_string()),
+    ];
+    for (y, header) in headers {
+        font::draw_text(&mut buffer, VIEW_WIDTH, SIDEBAR_X, y - LINE_HEIGHT, &header, TEXT_COLOR);
+    }
+
+    for (sprite, line) in sprite_list(ppu).iter().enumerate() {
+        let x = SIDEBAR_X + (sprite / 32) * SPRITE_LIST_COLUMN_WIDTH;
+        let y = SPRITE_LIST_Y + (sprite % 32) * LINE_HEIGHT;
+        // the list only has room for the upper half of 8x16 sprites
+        draw_sprite(ppu, sprite, &mut buffer, VIEW_WIDTH, x, y, 8);
+        font::draw_text(&mut buffer, VIEW_WIDTH, x + 10, y, line, TEXT_COLOR);
+    }
+
+    buffer
+}
+
+// writes every view as PNG file for bug reports, e.g. "ppu_nametables.png" for the prefix "ppu",
+// the sprite list is written as text file next to them, returns the names of the written files
+pub fn export(ppu: &PPU, palette: u8, prefix: &str) -> io::Result<Vec<String>> {
+    let images = [
+        ("pattern_tables", PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT, render_pattern_tables(ppu, palette)),
+        ("nametables", NAMETABLES_WIDTH, NAMETABLES_HEIGHT, render_nametables(ppu)),
+        ("palettes", PALETTES_WIDTH, PALETTES_HEIGHT, render_palettes(ppu)),
+        ("sprites", SPRITES_WIDTH, SPRITES_HEIGHT, render_sprites(ppu)),
+    ];
+
+    let mut files = Vec::new();
+    for (name, width, height, pixels) in images {
+        let file = format!("{}_{}.png", prefix, name);
+        png::save_rgb(&file, width, height, &pixels)?;
+        files.push(file);
+    }
+
+    let file = format!("{}_sprites.txt", prefix);
+    std::fs::write(&file, sprite_list(ppu).join("\n") + "\n")?;
+    files.push(file);
+
+    Ok(files)
+}
@@ -0,0 +1,244 @@
+/*
+Records the frames and the audio samples into an uncompressed AVI file, see
+https://learn.microsoft.com/en-us/windows/win32/directshow/avi-riff-file-reference
+
+The video stream holds the image of every frame (with the overscan cut off) as 24 bit RGB bitmap, the audio stream
+the samples as 16 bit mono PCM, both are interleaved frame by frame. Nothing is compressed, so the recording is
+lossless and plays in any player, but it takes about 11MB per second. The AVI 1.0 format used here limits files
+to 2GB (about 3 minutes).
+ */
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+use crate::apu::SAMPLE_RATE;
+use crate::frame::Image;
+use crate::region::Region;
+
+const BYTES_PER_SAMPLE: u32 = 2;
+// AVI 1.0 files are limited to 2GB, the sizes in the headers are read as signed 32 bit numbers by many players
+pub const MAX_FILE_SIZE: u64 = 0x7FFF_FFFF;
+// the headers, written again with the final counts when the recording is finished
+const HEADER_SIZE: u64 = 324;
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIF_ISINTERLEAVED: u32 = 0x100;
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+pub struct AviRecorder<W: Write + Seek> {
+    writer: W,
+    // the exact frame rate of the region as rate / scale frames per second, e.g. 39375000 / 655171 for NTSC
+    frame_rate: u32,
+    frame_rate_scale: u32,
+    // the size of the recorded images, e.g. with the overscan cut off
+    width: usize,
+    height: usize,
+    frames: u32,
+    audio_bytes: u32,
+    // the id, the offset relative to the "movi" list and the size of every chunk, for the index
+    index: Vec<([u8; 4], u32, u32)>,
+    movi_size: u32,
+    size_limit: u64,
+}
+
+impl AviRecorder<BufWriter<File>> {
+    pub fn create(path: &str, region: Region, width: usize, height: usize) -> io::Result<Self> {
+        AviRecorder::new(BufWriter::new(File::create(path)?), region, width, height)
+    }
+}
+
+impl<W: Write + Seek> AviRecorder<W> {
+    pub fn new(writer: W, region: Region, width: usize, height: usize) -> io::Result<Self> {
+        let (frame_rate, frame_rate_scale) = region.frame_rate();
+        let mut recorder = AviRecorder {
+            writer,
+            frame_rate,
+            frame_rate_scale,
+            width,
+            height,
+            frames: 0,
+            audio_bytes: 0,
+            index: Vec::new(),
+            // the "movi" type
+            movi_size: 4,
+            size_limit: MAX_FILE_SIZE,
+        };
+        let header = recorder.header();
+        recorder.writer.write_all(&header)?;
+        Ok(recorder)
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    // frames which would make the file larger fail to record, the limit can only be lowered below MAX_FILE_SIZE
+    pub fn set_size_limit(&mut self, bytes: u64) {
+        self.size_limit = bytes.min(MAX_FILE_SIZE);
+    }
+
+    // the size of the finished file with the chunks recorded so far
+    fn file_size(&self) -> u64 {
+        HEADER_SIZE + (self.movi_size - 4) as u64 + 8 + self.index.len() as u64 * 16
+    }
+
+    // the rows of a bitmap are padded to 4 bytes
+    fn row_size(&self) -> u32 {
+        (self.width as u32 * 3 + 3) & !3
+    }
+
+    fn frame_size(&self) -> u32 {
+        self.row_size() * self.height as u32
+    }
+
+    // appends the image of a frame and the audio samples generated during it, see Video::render_cropped
+    pub fn record_frame(&mut self, image: &Image, samples: &[f32]) -> io::Result<()> {
+        if (image.width, image.height) != (self.width, self.height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "the image has {}x{} instead of {}x{} pixels", image.width, image.height, self.width, self.height)));
+        }
+
+        // bitmaps are stored bottom up in BGR order
+        let mut bitmap = Vec::with_capacity(self.frame_size() as usize);
+        for row in image.pixels.chunks(image.width * 3).rev() {
+            for pixel in row.chunks(3) {
+                bitmap.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            }
+            bitmap.resize(bitmap.len().next_multiple_of(4), 0);
+        }
+
+        let mut pcm = Vec::with_capacity(samples.len() * BYTES_PER_SAMPLE as usize);
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            pcm.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        // the frame is left out as a whole, the file stays valid and can be finished
+        let chunks_size = [bitmap.len(), pcm.len()].iter().map(|size| 8 + size.next_multiple_of(2) as u64 + 16).sum::<u64>();
+        if self.file_size() + chunks_size > self.size_limit {
+            return Err(io::Error::other(format!("the recording reached the size limit of {} bytes", self.size_limit)));
+        }
+
+        self.write_chunk(*b"00db", &bitmap)?;
+        self.frames += 1;
+        self.write_chunk(*b"01wb", &pcm)?;
+        self.audio_bytes += pcm.len() as u32;
+
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, id: [u8; 4], data: &[u8]) -> io::Result<()> {
+        self.index.push((id, self.movi_size, data.len() as u32));
+
+        self.writer.write_all(&id)?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(data)?;
+        // chunks are aligned to 16 bit
+        if data.len() % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+
+        self.movi_size += 8 + data.len() as u32 + data.len() as u32 % 2;
+        Ok(())
+    }
+
+    // writes the index and the final headers, returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut index = Vec::with_capacity(self.index.len() * 16);
+        for (id, offset, size) in &self.index {
+            index.extend_from_slice(id);
+            index.extend_from_slice(&AVIIF_KEYFRAME.to_le_bytes());
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&size.to_le_bytes());
+        }
+        self.writer.write_all(b"idx1")?;
+        self.writer.write_all(&(index.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&index)?;
+
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&self.header())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    // the RIFF header, the stream headers and the start of the "movi" list
+    fn header(&self) -> Vec<u8> {
+        let samples = self.audio_bytes / BYTES_PER_SAMPLE;
+        let index_size = 8 + self.index.len() as u32 * 16;
+        let riff_size = HEADER_SIZE as u32 - 8 + self.movi_size - 4 + index_size;
+        let audio_bytes_per_second = SAMPLE_RATE * BYTES_PER_SAMPLE;
+        let frame_size = self.frame_size();
+        let (width, height) = (self.width as u32, self.height as u32);
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        let u32s = |header: &mut Vec<u8>, values: &[u32]| {
+            for value in values {
+                header.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+
+        header.extend_from_slice(b"RIFF");
+        u32s(&mut header, &[riff_size]);
+        header.extend_from_slice(b"AVI LIST");
+        u32s(&mut header, &[4 + 64 + 124 + 100]);
+        header.extend_from_slice(b"hdrlavih");
+        u32s(&mut header, &[
+            56,
+            (1_000_000u64 * self.frame_rate_scale as u64 / self.frame_rate as u64) as u32,
+            frame_size * (self.frame_rate / self.frame_rate_scale + 1) + audio_bytes_per_second,
+            0,
+            AVIF_HASINDEX | AVIF_ISINTERLEAVED,
+            self.frames,
+            0,
+            2,
+            frame_size,
+            width,
+            height,
+            0, 0, 0, 0,
+        ]);
+
+        // the video stream
+        header.extend_from_slice(b"LIST");
+        u32s(&mut header, &[4 + 64 + 48]);
+        header.extend_from_slice(b"strlstrh");
+        u32s(&mut header, &[56]);
+        header.extend_from_slice(b"vidsDIB ");
+        u32s(&mut header, &[0, 0, 0, self.frame_rate_scale, self.frame_rate, 0, self.frames, frame_size, u32::MAX, 0]);
+        header.extend_from_slice(&[0, 0, 0, 0]);
+        header.extend_from_slice(&(width as u16).to_le_bytes());
+        header.extend_from_slice(&(height as u16).to_le_bytes());
+        header.extend_from_slice(b"strf");
+        // BITMAPINFOHEADER: 24 bit uncompressed
+        u32s(&mut header, &[40, 40, width, height]);
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&24u16.to_le_bytes());
+        u32s(&mut header, &[0, frame_size, 0, 0, 0, 0]);
+
+        // the audio stream
+        header.extend_from_slice(b"LIST");
+        u32s(&mut header, &[4 + 64 + 24]);
+        header.extend_from_slice(b"strlstrh");
+        u32s(&mut header, &[56]);
+        header.extend_from_slice(b"auds");
+        u32s(&mut header, &[0, 0, 0, 0, BYTES_PER_SAMPLE, audio_bytes_per_second, 0, samples,
+            audio_bytes_per_second / 10, u32::MAX, BYTES_PER_SAMPLE, 0, 0]);
+        header.extend_from_slice(b"strf");
+        // WAVEFORMAT: mono PCM
+        u32s(&mut header, &[16]);
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        u32s(&mut header, &[SAMPLE_RATE, audio_bytes_per_second]);
+        header.extend_from_slice(&(BYTES_PER_SAMPLE as u16).to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+
+        header.extend_from_slice(b"LIST");
+        u32s(&mut header, &[self.movi_size]);
+        header.extend_from_slice(b"movi");
+
+        debug_assert_eq!(header.len() as u64, HEADER_SIZE);
+        header
+    }
+}
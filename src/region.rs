@@ -0,0 +1,95 @@
+/*
+The timing of the console differs between the regions, see https://www.nesdev.org/wiki/Cycle_reference_chart
+                 NTSC            PAL             Dendy
+  master clock   21.477272 MHz   26.601712 MHz   26.601712 MHz
+  cpu clock      master / 12     master / 16     master / 15
+  PPU dots       3 per cycle     3.2 per cycle   3 per cycle
+  scanlines      262             312             312
+  vblank         241 - 260       241 - 310       291 - 310
+  frame rate     60.0988 Hz      50.0070 Hz      50.0070 Hz
+
+Dendy famiclones combine the PAL clock with the NTSC APU and a vertical blank of NTSC length.
+ */
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Ntsc => "NTSC",
+            Region::Pal => "PAL",
+            Region::Dendy => "Dendy",
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 236_250_000.0 / 11.0 / 12.0,
+            Region::Pal => 26_601_712.0 / 16.0,
+            Region::Dendy => 26_601_712.0 / 15.0,
+        }
+    }
+
+    // the PPU dots per 5 cpu cycles, PAL runs 3.2 dots per cycle
+    pub fn ppu_dots_per_five_cpu_cycles(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Dendy => 15,
+            Region::Pal => 16,
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // the scanline at which the vertical blank starts and the NMI is raised
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // only the NTSC PPU skips a dot of the pre render scanline on odd frames
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    // the exact frame rate as fraction (frames, seconds):
+    // NTSC has 341 * 262 - 0.5 dots at 236.25 MHz / 11 / 4, PAL and Dendy 341 * 312 dots at 26.601712 MHz / 5
+    pub fn frame_rate(&self) -> (u32, u32) {
+        match self {
+            Region::Ntsc => (39_375_000, 655_171),
+            Region::Pal | Region::Dendy => (3_325_214, 66_495),
+        }
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        let (frames, seconds) = self.frame_rate();
+        frames as f64 / seconds as f64
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!("unknown region {}, use ntsc, pal or dendy", value)),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+/*
+Software scaling of the presented image, so no GPU is needed:
+  Scale2x and Scale3x  the pixel art upscalers of AdvanceMAME, see https://www.scale2x.it/algorithm
+  xBR                  the 2x version of Hyllian's xBR level 1, which rounds diagonal edges by blending
+  hq2x                 after Maxim Stepin's hq2x: the neighbours which differ in YUV (with its thresholds) select
+                       the interpolation of every output pixel, by a few rules per corner instead of the 256 case
+                       table of the original
+The upscaled image can then be scaled up further by an integer factor, stretched to the 8:7 pixel aspect ratio
+of the NES and darkened every other line like the scanlines of a CRT.
+ */
+
+use std::str::FromStr;
+
+use crate::frame::Image;
+
+// NES pixels are 8/7 times wider than high on a TV
+pub const PIXEL_ASPECT_RATIO: f32 = 8.0 / 7.0;
+// the brightness of the dark lines between the scanlines
+const SCANLINE_BRIGHTNESS: u16 = 160;
+
+type Pixel = [u8; 3];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaler {
+    None,
+    Scale2x,
+    Scale3x,
+    Xbr,
+    Hq2x,
+}
+
+impl Scaler {
+    pub const ALL: [Scaler; 5] = [Scaler::None, Scaler::Scale2x, Scaler::Scale3x, Scaler::Xbr, Scaler::Hq2x];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scaler::None => "none",
+            Scaler::Scale2x => "scale2x",
+            Scaler::Scale3x => "scale3x",
+            Scaler::Xbr => "xbr",
+            Scaler::Hq2x => "hq2x",
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        match self {
+            Scaler::None => 1,
+            Scaler::Scale2x | Scaler::Xbr | Scaler::Hq2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+
+    pub fn next(&self) -> Scaler {
+        let index = Scaler::ALL.iter().position(|scaler| scaler == self).unwrap();
+        Scaler::ALL[(index + 1) % Scaler::ALL.len()]
+    }
+
+    pub fn apply(&self, image: &Image) -> Image {
+        match self {
+            Scaler::None => image.clone(),
+            Scaler::Scale2x => scale2x(image),
+            Scaler::Scale3x => scale3x(image),
+            Scaler::Xbr => xbr(image),
+            Scaler::Hq2x => hq2x(image),
+        }
+    }
+}
+
+impl FromStr for Scaler {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Scaler::ALL.iter()
+            .find(|scaler| scaler.name() == value.to_lowercase())
+            .copied()
+            .ok_or_else(|| format!("unknown scaler {}, use none, scale2x, scale3x, xbr or hq2x", value))
+    }
+}
+
+// the pixels outside of the image repeat the ones at its edges
+fn pixel(image: &Image, x: isize, y: isize) -> Pixel {
+    let x = x.clamp(0, image.width as isize - 1) as usize;
+    let y = y.clamp(0, image.height as isize - 1) as usize;
+    let index = (y * image.width + x) * 3;
+    [image.pixels[index], image.pixels[index + 1], image.pixels[index + 2]]
+}
+
+// runs the kernel for every pixel, it returns the factor x factor output pixels row by row
+fn upscale<F>(image: &Image, factor: usize, kernel: F) -> Image
+where
+    F: Fn(isize, isize) -> Vec<Pixel>,
+{
+    let width = image.width * factor;
+    let mut pixels = vec![0; width * image.height * factor * 3];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            for (i, output) in kernel(x as isize, y as isize).iter().enumerate() {
+                let index = ((y * factor + i / factor) * width + x * factor + i % factor) * 3;
+                pixels[index..index + 3].copy_from_slice(output);
+            }
+        }
+    }
+    Image::new(width, image.height * factor, pixels)
+}
+
+/*
+  A B C
+  D E F
+  G H I
+ */
+pub fn scale2x(image: &Image) -> Image {
+    upscale(image, 2, |x, y| {
+        let (b, d, e, f, h) = (pixel(image, x, y - 1), pixel(image, x - 1, y), pixel(image, x, y), pixel(image, x + 1, y), pixel(image, x, y + 1));
+        if b == h || d == f {
+            return vec![e; 4];
+        }
+        vec![
+            if d == b { d } else { e },
+            if b == f { f } else { e },
+            if d == h { d } else { e },
+            if h == f { f } else { e },
+        ]
+    })
+}
+
+pub fn scale3x(image: &Image) -> Image {
+    upscale(image, 3, |x, y| {
+        let at = |dx: isize, dy: isize| pixel(image, x + dx, y + dy);
+        let (a, b, c, d, e, f, g, h, i) = (at(-1, -1), at(0, -1), at(1, -1), at(-1, 0), at(0, 0), at(1, 0), at(-1, 1), at(0, 1), at(1, 1));
+        if b == h || d == f {
+            return vec![e; 9];
+        }
+        vec![
+            if d == b { d } else { e },
+            if (d == b && e != c) || (b == f && e != a) { b } else { e },
+            if b == f { f } else { e },
+            if (d == b && e != g) || (d == h && e != a) { d } else { e },
+            e,
+            if (b == f && e != i) || (h == f && e != c) { f } else { e },
+            if d == h { d } else { e },
+            if (d == h && e != i) || (h == f && e != g) { h } else { e },
+            if h == f { f } else { e },
+        ]
+    })
+}
+
+fn yuv_difference(a: Pixel, b: Pixel) -> [f32; 3] {
+    let [r, g, b] = [a[0] as f32 - b[0] as f32, a[1] as f32 - b[1] as f32, a[2] as f32 - b[2] as f32];
+    [0.299 * r + 0.587 * g + 0.114 * b, -0.169 * r - 0.331 * g + 0.5 * b, 0.5 * r - 0.419 * g - 0.081 * b]
+}
+
+// the weighted YUV distance of xBR, luma differences count the most
+fn distance(a: Pixel, b: Pixel) -> f32 {
+    let [y, u, v] = yuv_difference(a, b);
+    48.0 * y.abs() + 7.0 * u.abs() + 6.0 * v.abs()
+}
+
+// hq2x treats two colors as different when one of their YUV components differs by more than its threshold
+fn differs(a: Pixel, b: Pixel) -> bool {
+    let [y, u, v] = yuv_difference(a, b);
+    y.abs() > 48.0 || u.abs() > 7.0 || v.abs() > 6.0
+}
+
+// the weighted average of the pixels
+fn interpolate(weighted: &[(Pixel, u16)]) -> Pixel {
+    let total: u16 = weighted.iter().map(|(_, weight)| weight).sum();
+    let channel = |c: usize| (weighted.iter().map(|(pixel, weight)| pixel[c] as u16 * weight).sum::<u16>() / total) as u8;
+    [channel(0), channel(1), channel(2)]
+}
+
+fn blend(a: Pixel, b: Pixel) -> Pixel {
+    [((a[0] as u16 + b[0] as u16) / 2) as u8, ((a[1] as u16 + b[1] as u16) / 2) as u8, ((a[2] as u16 + b[2] as u16) / 2) as u8]
+}
+
+/*
+The neighbourhood of xBR for the bottom right output pixel, the other corners are mirrored:
+     A1 B1 C1
+  A0 A  B  C  C4
+  D0 D  E  F  F4
+  G0 G  H  I  I4
+     G5 H5 I5
+An edge along H - F is rounded off when the gradients across it are stronger than those along the other diagonal.
+ */
+pub fn xbr(image: &Image) -> Image {
+    upscale(image, 2, |x, y| {
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter().map(|&(sx, sy)| {
+            let at = |dx: isize, dy: isize| pixel(image, x + dx * sx, y + dy * sy);
+            let (e, b, c, d, f, g, h, i) = (at(0, 0), at(0, -1), at(1, -1), at(-1, 0), at(1, 0), at(-1, 1), at(0, 1), at(1, 1));
+            let (f4, i4, h5, i5) = (at(2, 0), at(2, 1), at(0, 2), at(1, 2));
+
+            let across = distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4.0 * distance(h, f);
+            let along = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4.0 * distance(e, i);
+            if across < along && e != f && e != h {
+                let closer = if distance(e, f) <= distance(e, h) { f } else { h };
+                blend(e, closer)
+            } else {
+                e
+            }
+        }).collect()
+    })
+}
+
+/*
+The neighbourhood of hq2x for the top left output pixel, the other corners are mirrored:
+  A B
+  D E F
+    H
+Only the neighbours which differ from E matter. When B or D is similar to E, the pixel blends in
+the similar ones. When both differ from E but are similar to each other, an edge crosses the corner:
+it is rounded off strongly at the corner of an area, half way where A continues E diagonally and
+only slightly when F or H differ as well, so thin lines and single pixels keep their shape.
+ */
+pub fn hq2x(image: &Image) -> Image {
+    upscale(image, 2, |x, y| {
+        [(1, 1), (-1, 1), (1, -1), (-1, -1)].iter().map(|&(sx, sy)| {
+            let at = |dx: isize, dy: isize| pixel(image, x + dx * sx, y + dy * sy);
+            let (e, a, b, d, f, h) = (at(0, 0), at(-1, -1), at(0, -1), at(-1, 0), at(1, 0), at(0, 1));
+            let (a_differs, b_differs, d_differs) = (differs(e, a), differs(e, b), differs(e, d));
+
+            match (b_differs, d_differs) {
+                (false, false) => interpolate(&[(e, 2), (b, 1), (d, 1)]),
+                (true, false) if a_differs => interpolate(&[(e, 3), (d, 1)]),
+                (true, false) => interpolate(&[(e, 2), (a, 1), (d, 1)]),
+                (false, true) if a_differs => interpolate(&[(e, 3), (b, 1)]),
+                (false, true) => interpolate(&[(e, 2), (a, 1), (b, 1)]),
+                (true, true) if differs(b, d) => {
+                    if a_differs { e } else { interpolate(&[(e, 3), (a, 1)]) }
+                },
+                (true, true) => match (a_differs, differs(e, f), differs(e, h)) {
+                    (true, false, false) => interpolate(&[(e, 2), (b, 3), (d, 3)]),
+                    (false, false, false) => interpolate(&[(e, 2), (b, 1), (d, 1)]),
+                    (true, true, true) => interpolate(&[(e, 14), (b, 1), (d, 1)]),
+                    _ => interpolate(&[(e, 6), (b, 1), (d, 1)]),
+                },
+            }
+        }).collect()
+    })
+}
+
+// stretches the image horizontally by the ratio, interpolating between the neighbouring pixels
+pub fn stretch(image: &Image, ratio: f32) -> Image {
+    let width = (image.width as f32 * ratio).round() as usize;
+    let mut pixels = Vec::with_capacity(width * image.height * 3);
+    for y in 0..image.height {
+        for x in 0..width {
+            let source = ((x as f32 + 0.5) / ratio - 0.5).max(0.0);
+            let left = pixel(image, source as isize, y as isize);
+            let right = pixel(image, source as isize + 1, y as isize);
+            let weight = source.fract();
+            for channel in 0..3 {
+                pixels.push((left[channel] as f32 * (1.0 - weight) + right[channel] as f32 * weight).round() as u8);
+            }
+        }
+    }
+    Image::new(width, image.height, pixels)
+}
+
+// darkens the last row of every group of rows which shows one scanline
+pub fn scanlines(image: &mut Image, rows_per_scanline: usize) {
+    if rows_per_scanline < 2 {
+        return;
+    }
+    let row_size = image.width * 3;
+    for (y, row) in image.pixels.chunks_mut(row_size).enumerate() {
+        if y % rows_per_scanline == rows_per_scanline - 1 {
+            for value in row {
+                *value = (*value as u16 * SCANLINE_BRIGHTNESS / 255) as u8;
+            }
+        }
+    }
+}
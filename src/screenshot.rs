@@ -0,0 +1,46 @@
+/*
+Screenshots of the frame as it is presented, named after the rom and the time they have been taken,
+e.g. "smb_20240131_235959_123.png". Only the frame buffer is used, so they work in headless runs as well.
+ */
+
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::frame::{Image, Overscan};
+
+// the UTC date and time as "YYYYMMDD_HHMMSS_mmm", see https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let days = (seconds / 86400) as i64;
+    let second_of_day = seconds % 86400;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}", year, month, day,
+        second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60, since_epoch.subsec_millis())
+}
+
+// the name of the rom file without its directory and extension followed by the timestamp
+pub fn file_name(rom_path: &str, time: SystemTime, extension: &str) -> String {
+    let rom_name = Path::new(rom_path)
+        .file_stem()
+        .map_or("rust-nes".to_string(), |stem| stem.to_string_lossy().to_string());
+    format!("{}_{}.{}", rom_name, format_timestamp(time), extension)
+}
+
+// saves the image of the frame as PNG file in the working directory and returns its name
+pub fn save(image: &Image, rom_path: &str, overscan: Overscan, scale: usize) -> io::Result<String> {
+    let file = file_name(rom_path, SystemTime::now(), "png");
+    image.crop(overscan).scale(scale).save_png(&file)?;
+    Ok(file)
+}
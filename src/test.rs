@@ -1,16 +1,42 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
+    use std::cell::RefCell;
     use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant, UNIX_EPOCH};
     use std::vec;
 
     use rand::Rng;
 
-    use crate::bus::Bus;
+    use crate::bus::{Bus, MemoryRegion};
     use crate::cpu::CPU;
     use crate::cpu::AddressingMode;
-    use crate::mem::Mem;
-    use crate::cartridge::create_test_cartridge;
-    use crate::trace::trace;
+    use crate::cpu::CpuError;
+    use crate::cpu::CpuVariant;
+    use crate::frame::{Frame, Image, Overscan};
+    use crate::golden::{self, GoldenResult};
+    use crate::mem::{FlatRam, Mem, RamPattern};
+    use crate::memory_viewer::{self, MemoryViewer};
+    use crate::movie::{self, Movie, MovieFrame};
+    use crate::cartridge::{create_test_cartridge, Mirroring};
+    use crate::joypad::JoypadButton;
+    use crate::json;
+    use crate::nes::Nes;
+    use crate::ntsc::{self, NtscFilter, NtscSettings};
+    use crate::opcodes::{CPU_OPS_CODES, OPCODES_MAP};
+    use crate::pacing::{self, FrameLimiter};
+    use crate::palette::{Palette, SYSTEM_PALETTE};
+    use crate::png;
+    use crate::ppu::PPU;
+    use crate::ppu_viewer;
+    use crate::recorder::AviRecorder;
+    use crate::region::Region;
+    use crate::scaler::{self, Scaler};
+    use crate::screenshot;
+    use crate::trace::{trace, trace_with_format, BusAccessKind, TraceFormat, Tracer};
+    use crate::video::Video;
 
     fn create_new_cpu() -> CPU {
         let cartridge = create_test_cartridge(false);
@@ -19,6 +45,13 @@ mod test {
         CPU::new(bus)
     }
 
+    fn create_new_nmos_cpu() -> CPU {
+        let cartridge = create_test_cartridge(false);
+        let bus = Bus::new(cartridge);
+
+        CPU::new_with_variant(bus, CpuVariant::Nmos6502)
+    }
+
     macro_rules! hashmap {
         ($( $key:expr => $value:expr ), * $(,)?) => {
             {
@@ -31,7 +64,7 @@ mod test {
         };
     }
 
-    fn check_zero_and_neg_flags(cpu: &CPU, expected_zero_flag: bool, expected_neg_flag: bool) {
+    fn check_zero_and_neg_flags<M: Mem>(cpu: &CPU<M>, expected_zero_flag: bool, expected_neg_flag: bool) {
         if expected_zero_flag {
             assert!(cpu.status & 0b0000_0010 == 0b10);
         } else {
@@ -45,7 +78,7 @@ mod test {
         }
     }
 
-    fn check_carry_flag(cpu: &CPU, expected_carry_flag: bool) {
+    fn check_carry_flag<M: Mem>(cpu: &CPU<M>, expected_carry_flag: bool) {
         if expected_carry_flag {
             assert!(cpu.status & 0b0000_0001 == 0b01);
         } else {
@@ -53,7 +86,7 @@ mod test {
         }
     }
 
-    fn check_decimal_flag(cpu: &CPU, expected_decimal_flag: bool) {
+    fn check_decimal_flag<M: Mem>(cpu: &CPU<M>, expected_decimal_flag: bool) {
         if expected_decimal_flag {
             assert!(cpu.status & 0b0000_1000 == 0b1000);
         } else {
@@ -61,7 +94,7 @@ mod test {
         }
     }
 
-    fn check_interrupt_disable_flag(cpu: &CPU, expected_interrupt_disable_flag: bool) {
+    fn check_interrupt_disable_flag<M: Mem>(cpu: &CPU<M>, expected_interrupt_disable_flag: bool) {
         if expected_interrupt_disable_flag {
             assert!(cpu.status & 0b0000_0100 == 0b100);
         } else {
@@ -69,7 +102,7 @@ mod test {
         }
     }
 
-    fn check_overflow_flag(cpu: &CPU, expected_overflow_flag: bool) {
+    fn check_overflow_flag<M: Mem>(cpu: &CPU<M>, expected_overflow_flag: bool) {
         if expected_overflow_flag {
             assert!(cpu.status & 0b0100_0000 == 0b0100_0000);
         } else {
@@ -91,7 +124,7 @@ mod test {
         bus.mem_write(104, 0x00);
 
         let mut cpu = CPU::new(bus);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x64;
         cpu.register_a = 1;
         cpu.register_x = 2;
@@ -99,21 +132,100 @@ mod test {
         let mut result: Vec<String> = vec![];
         cpu.run(|cpu, opcode| {
             result.push(trace(cpu, opcode).to_uppercase());
-        });
+        }).unwrap();
         assert_eq!(
-            "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD",
+            "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD PPU:  0, 21 CYC:7",
             result[0]
         );
         assert_eq!(
-            "0066  CA        DEX                             A:01 X:01 Y:03 P:24 SP:FD",
+            "0066  CA        DEX                             A:01 X:01 Y:03 P:24 SP:FD PPU:  0, 27 CYC:9",
             result[1]
         );
         assert_eq!(
-            "0067  88        DEY                             A:01 X:00 Y:03 P:26 SP:FD",
+            "0067  88        DEY                             A:01 X:00 Y:03 P:26 SP:FD PPU:  0, 33 CYC:11",
             result[2]
         );
     }
 
+    #[test]
+    fn test_format_trace_styles() {
+        let mut bus = Bus::new(create_test_cartridge(false));
+        bus.mem_write(100, 0xa2);
+        bus.mem_write(101, 0x01);
+        bus.mem_write(102, 0x00);
+
+        let mut cpu = CPU::new(bus);
+        cpu.power_on();
+        cpu.program_counter = 0x64;
+        let mut result: Vec<String> = vec![];
+        cpu.run(|cpu, opcode| {
+            result.push(trace_with_format(cpu, opcode, TraceFormat::Mesen));
+            result.push(trace_with_format(cpu, opcode, TraceFormat::Fceux));
+        }).unwrap();
+        assert_eq!(
+            "0064  A2 01    LDX #$01                         A:00 X:00 Y:00 S:FD P:nvUbdIzc V:0   H:21  Cycle:7",
+            result[0]
+        );
+        assert_eq!(
+            "c7          A:00 X:00 Y:00 S:FD P:nvUbdIzc  $0064:A2 01     LDX #$01",
+            result[1]
+        );
+
+        // buses without a PPU leave the PPU columns out
+        let mut ram = FlatRam::new();
+        ram.load(&[0xE8, 0x00], 0x0600);
+        let mut cpu = CPU::new(ram);
+        cpu.program_counter = 0x0600;
+        let mut result: Vec<String> = vec![];
+        cpu.run(|cpu, opcode| result.push(trace(cpu, opcode))).unwrap();
+        assert_eq!(
+            "0600  E8        INX                             A:00 X:00 Y:00 P:00 SP:00 CYC:0",
+            result[0]
+        );
+
+        assert_eq!("fceux".parse::<TraceFormat>(), Ok(TraceFormat::Fceux));
+        assert!("nintendulator".parse::<TraceFormat>().is_err());
+    }
+
+    #[test]
+    fn test_tracer() {
+        // INX at $0600 to $0607, followed by a BRK
+        let mut ram = FlatRam::new();
+        ram.load(&[0xE8; 8], 0x0600);
+
+        let mut tracer = Tracer::new(TraceFormat::Nestest);
+        tracer.set_ring_buffer_capacity(3);
+        tracer.set_start_trigger(Some(0x0602));
+        tracer.set_instruction_limit(Some(4));
+
+        let mut cpu = CPU::new(ram);
+        cpu.program_counter = 0x0600;
+        cpu.run(|cpu, opcode| tracer.trace(cpu, opcode)).unwrap();
+
+        // tracing starts at $0602 and stops after 4 instructions, the ring buffer keeps the last 3 of them
+        let addresses: Vec<&str> = tracer.ring_buffer().map(|line| &line[0..4]).collect();
+        assert_eq!(addresses, vec!["0603", "0604", "0605"]);
+
+        let mut dump = Vec::new();
+        tracer.dump_ring_buffer(&mut dump).unwrap();
+        assert_eq!(String::from_utf8(dump).unwrap().lines().count(), 3);
+
+        // the address range filter and disabling the tracer at runtime
+        tracer.set_start_trigger(None);
+        tracer.set_instruction_limit(None);
+        tracer.set_address_range(Some(0x0600..=0x0601));
+        cpu.program_counter = 0x0600;
+        cpu.run(|cpu, opcode| tracer.trace(cpu, opcode)).unwrap();
+        let addresses: Vec<&str> = tracer.ring_buffer().map(|line| &line[0..4]).collect();
+        assert_eq!(addresses, vec!["0605", "0600", "0601"]);
+
+        tracer.set_enabled(false);
+        cpu.program_counter = 0x0600;
+        cpu.run(|cpu, opcode| tracer.trace(cpu, opcode)).unwrap();
+        assert_eq!(tracer.ring_buffer().count(), 3);
+        assert_eq!(&tracer.ring_buffer().next().unwrap()[0..4], "0605");
+    }
+
     #[test]
    fn test_format_mem_access() {
        let mut bus = Bus::new(create_test_cartridge(true));
@@ -134,36 +246,1457 @@ mod test {
        bus.mem_write(0x400, 0xAA);
 
        let mut cpu = CPU::new(bus);
-       cpu.reset();
+       cpu.power_on();
        cpu.program_counter = 0x64;
        cpu.register_y = 0;
        cpu.register_x = 1;
        let mut result: Vec<String> = vec![];
        cpu.run(|cpu, opcode| {
             result.push(trace(cpu, opcode));
-       });
+       }).unwrap();
        assert_eq!(
-            "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:01 Y:00 P:24 SP:FD",
+            "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:01 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
             result[0]
        );
        assert_eq!(
-            "0066  01 32     ORA ($32,X) @ 33 = 0400 = AA    A:AA X:01 Y:00 P:A4 SP:FD",
+            "0066  01 32     ORA ($32,X) @ 33 = 0400 = AA    A:AA X:01 Y:00 P:A4 SP:FD PPU:  0, 36 CYC:12",
             result[1]
        );
        assert_eq!(
-            "0068  15 31     ORA $31,X @ 32 = 10             A:AA X:01 Y:00 P:A4 SP:FD",
+            "0068  15 31     ORA $31,X @ 32 = 10             A:AA X:01 Y:00 P:A4 SP:FD PPU:  0, 54 CYC:18",
             result[2]
        );
    }
 
+    // --------------------------------
+    //      generic 6502 programs
+    // --------------------------------
+
+    const KLAUS_DORMANN_FUNCTIONAL_TEST: &str = "test_roms/6502_functional_test.bin";
+    const KLAUS_DORMANN_FUNCTIONAL_TEST_SUCCESS: u16 = 0x3469;
+    const KLAUS_DORMANN_DECIMAL_TEST: &str = "test_roms/6502_decimal_test.bin";
+    const KLAUS_DORMANN_DECIMAL_TEST_ERROR: u16 = 0x000B;
+
+    // the test suites signal success or failure by jumping onto themselves
+    // returns the address of that trap or None if the program stopped at a BRK and stop_on_brk is set
+    fn run_until_trap(cpu: &mut CPU<FlatRam>, stop_on_brk: bool) -> Option<u16> {
+        loop {
+            let program_counter = cpu.program_counter;
+            if stop_on_brk && cpu.mem_read(program_counter) == 0x00 {
+                return None;
+            }
+
+            cpu.step(|_, _| {}).unwrap();
+
+            if cpu.program_counter == program_counter {
+                return Some(program_counter);
+            }
+        }
+    }
+
+    #[test]
+    fn test_program_counter_after_jumps_into_the_operand() {
+        // BNE with the offset $FF jumps back onto its operand byte
+        let mut ram = FlatRam::new();
+        ram.load(&[0xD0, 0xFF], 0x0200);
+        let mut cpu = CPU::new(ram);
+        cpu.program_counter = 0x0200;
+        cpu.step(|_, _| {}).unwrap();
+        assert_eq!(cpu.program_counter, 0x0201);
+
+        // the branch is not taken
+        cpu.program_counter = 0x0200;
+        cpu.status |= 0b0000_0010;
+        cpu.step(|_, _| {}).unwrap();
+        assert_eq!(cpu.program_counter, 0x0202);
+
+        // JMP $0201 jumps to its own operand
+        cpu.bus_mut().load(&[0x4C, 0x01, 0x02], 0x0200);
+        cpu.program_counter = 0x0200;
+        cpu.step(|_, _| {}).unwrap();
+        assert_eq!(cpu.program_counter, 0x0201);
+    }
+
+    #[test]
+    fn test_flat_ram_cpu() {
+        let mut ram = FlatRam::new();
+        // LDA #$42, STA $C000, BRK
+        ram.load(&[0xA9, 0x42, 0x8D, 0x00, 0xC0, 0x00], 0x8000);
+
+        let mut cpu = CPU::new(ram);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run(|_, _| {}).unwrap();
+
+        assert_eq!(cpu.bus().mem_peek(0xC000), 0x42);
+        assert_eq!(cpu.register_a, 0x42);
+    }
+
+    #[test]
+    fn test_step_brk() {
+        let mut ram = FlatRam::new();
+        ram.load(&[0x00, 0xFF], 0x0400);
+        ram.load(&[0x00, 0x05], 0xFFFE);
+
+        let mut cpu = CPU::new(ram);
+        cpu.power_on();
+        cpu.program_counter = 0x0400;
+        cpu.status = 0b0000_0001;
+        cpu.step(|_, _| {}).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0500);
+        assert_eq!(cpu.register_s, 0xFA);
+        assert_eq!(cpu.mem_read_u16(0x01FC), 0x0402);
+        assert_eq!(cpu.mem_read(0x01FB), 0b0011_0001);
+        check_interrupt_disable_flag(&cpu, true);
+    }
+
+    // builds an NROM image with a single 16KB program bank mapped to $C000 and the given interrupt vectors
+    fn create_test_rom(program: &[u8], nmi_vector: u16, reset_vector: u16) -> Vec<u8> {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0b0000_0001, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let mut program_rom = vec![0xEA; 0x4000];
+        program_rom[..program.len()].copy_from_slice(program);
+        program_rom[0x3FFA..0x3FFC].copy_from_slice(&nmi_vector.to_le_bytes());
+        program_rom[0x3FFC..0x3FFE].copy_from_slice(&reset_vector.to_le_bytes());
+        program_rom[0x3FFE..].copy_from_slice(&reset_vector.to_le_bytes());
+
+        rom.extend_from_slice(&program_rom);
+        rom.extend_from_slice(&[0u8; 0x2000]);
+        rom
+    }
+
+    #[test]
+    fn test_nes_nmi_frame() {
+        let program = [
+            0xA9, 0x80,         // LDA #$80
+            0x8D, 0x00, 0x20,   // STA $2000, enables the NMI
+            0x4C, 0x05, 0xC0,   // JMP $C005
+            0xE6, 0x10,         // NMI handler: INC $10
+            0x40,               // RTI
+        ];
+        let mut nes = Nes::new(&create_test_rom(&program, 0xC008, 0xC000)).unwrap();
+
+        nes.run_frame().unwrap();
+        nes.run_frame().unwrap();
+        nes.run_frame().unwrap();
+
+        // the frame ends with the start of the vertical blank, the NMI of the last frame is handled by the next instruction
+        assert_eq!(nes.cpu().mem_peek(0x0010), 2);
+        // a frame takes 29780.5 cpu cycles, which are about 734 samples at 44.1kHz
+        assert!((733..=735).contains(&nes.audio_samples().len()));
+    }
+
+    #[test]
+    fn test_memory_viewer() {
+        let program = [
+            0xA9, 0x42,         // LDA #$42
+            0x85, 0x13,         // STA $13
+            0x8D, 0x05, 0x60,   // STA $6005
+            0xE6, 0x14,         // INC $14
+            0x20, 0x0C, 0xC0,   // JSR $C00C
+            0x85, 0x15,         // STA $15
+            0x4C, 0x0C, 0xC0,   // JMP $C00C
+        ];
+        let mut nes = Nes::new(&create_test_rom(&program, 0xC000, 0xC000)).unwrap();
+        let mut viewer = MemoryViewer::new();
+
+        // without the write log nothing is collected
+        nes.run_frame().unwrap();
+        assert!(nes.written_addresses().is_empty());
+
+        nes.set_write_log_enabled(true);
+        nes.power_on();
+        nes.run_frame().unwrap();
+        viewer.record_writes(&nes);
+        viewer.end_frame();
+        // the dummy write of INC and the return address pushed by JSR are in the log
+        assert_eq!(nes.written_addresses()[..5], [0x0013, 0x6005, 0x0014, 0x0014, 0x01FD]);
+        assert!(viewer.highlight(MemoryRegion::CpuRam, 0x13) > 0);
+        assert!(viewer.highlight(MemoryRegion::ProgramRam, 0x05) > 0);
+        assert!(viewer.highlight(MemoryRegion::CpuRam, 0x1FC) > 0);
+        assert_eq!(viewer.highlight(MemoryRegion::CpuRam, 0x12), 0);
+        assert!(viewer.hex_dump(&nes).starts_with("0000: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00\n0010: 00 00 00 42 01 42"));
+
+        // the highlight fades out after a while
+        for _ in 0..100 {
+            viewer.end_frame();
+        }
+        assert_eq!(viewer.highlight(MemoryRegion::CpuRam, 0x13), 0);
+
+        // writing the same value again highlights the byte again
+        nes.run_frame().unwrap();
+        viewer.record_writes(&nes);
+        assert!(!nes.written_addresses().contains(&0x0013));
+        assert_eq!(viewer.highlight(MemoryRegion::CpuRam, 0x13), 0);
+        assert!(viewer.highlight(MemoryRegion::CpuRam, 0x15) > 0);
+
+        // editing writes a byte with the second nibble and moves on
+        viewer.move_cursor(0x13);
+        viewer.enter_nibble(&mut nes, 0x0A);
+        assert_eq!(nes.peek_memory(MemoryRegion::CpuRam, 0x13), 0x42);
+        viewer.enter_nibble(&mut nes, 0x05);
+        assert_eq!(nes.peek_memory(MemoryRegion::CpuRam, 0x13), 0xA5);
+        assert_eq!(viewer.cursor(), 0x14);
+
+        // the regions of the PPU are seen through their mirroring
+        nes.poke_memory(MemoryRegion::Palette, 0x10, 0x21);
+        assert_eq!(nes.peek_memory(MemoryRegion::Palette, 0x00), 0x21);
+        nes.poke_memory(MemoryRegion::Vram, 0x0005, 0x33);
+        assert_eq!(nes.peek_memory(MemoryRegion::Vram, 0x0805), 0x33);
+
+        viewer.next_region();
+        assert_eq!(viewer.region, MemoryRegion::ProgramRam);
+        assert_eq!(viewer.render(&nes, true).len(), memory_viewer::VIEW_WIDTH * memory_viewer::VIEW_HEIGHT * 3);
+    }
+
+    #[test]
+    fn test_ppu_viewer() {
+        // a cartridge without character rom has character ram, which can be written
+        let mut ppu = PPU::new(vec![], Mirroring::Horizontal);
+        let rgb = |image: &[u8], width: usize, x: usize, y: usize| {
+            let index = (y * width + x) * 3;
+            (image[index], image[index + 1], image[index + 2])
+        };
+
+        // the first row of tile 1 starts with a pixel of color 1 and one of color 3
+        ppu.write_vram(0x0010, 0b1100_0000);
+        ppu.write_vram(0x0018, 0b0100_0000);
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F01, 0x01);
+        ppu.write_vram(0x3F03, 0x30);
+        ppu.write_vram(0x3F15, 0x16);
+        ppu.write_vram(0x3F17, 0x2A);
+
+        let patterns = ppu_viewer::render_pattern_tables(&ppu, 0);
+        assert_eq!(rgb(&patterns, ppu_viewer::PATTERN_TABLES_WIDTH, 0, 0), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(rgb(&patterns, ppu_viewer::PATTERN_TABLES_WIDTH, 8, 0), SYSTEM_PALETTE[0x01]);
+        assert_eq!(rgb(&patterns, ppu_viewer::PATTERN_TABLES_WIDTH, 9, 0), SYSTEM_PALETTE[0x30]);
+
+        // the horizontal mirroring shows the tile in the two upper nametables only
+        ppu.write_vram(0x2000 + 32 + 2, 0x01);
+        let nametables = ppu_viewer::render_nametables(&ppu);
+        assert_eq!(rgb(&nametables, ppu_viewer::NAMETABLES_WIDTH, 16, 8), SYSTEM_PALETTE[0x01]);
+        assert_eq!(rgb(&nametables, ppu_viewer::NAMETABLES_WIDTH, 256 + 16, 8), SYSTEM_PALETTE[0x01]);
+        assert_eq!(rgb(&nametables, ppu_viewer::NAMETABLES_WIDTH, 16, 240 + 8), SYSTEM_PALETTE[0x0F]);
+
+        // the scroll window starts in the second nametable and wraps around to the first one
+        ppu.write_register(0x2005, 0x10);
+        ppu.write_register(0x2005, 0x20);
+        ppu.write_register(0x2000, 0x01);
+        assert_eq!(ppu.scroll_position(), (256 + 16, 32));
+        let nametables = ppu_viewer::render_nametables(&ppu);
+        assert_eq!(rgb(&nametables, ppu_viewer::NAMETABLES_WIDTH, 256 + 16, 32), (0xFF, 0x50, 0x30));
+        assert_eq!(rgb(&nametables, ppu_viewer::NAMETABLES_WIDTH, 15, 32 + 239), (0xFF, 0x50, 0x30));
+        assert_eq!(rgb(&nametables, ppu_viewer::NAMETABLES_WIDTH, 16, 8), SYSTEM_PALETTE[0x01]);
+
+        let palettes = ppu_viewer::render_palettes(&ppu);
+        assert_eq!(rgb(&palettes, ppu_viewer::PALETTES_WIDTH, 16, 0), SYSTEM_PALETTE[0x01]);
+        assert_eq!(rgb(&palettes, ppu_viewer::PALETTES_WIDTH, 5 * 16, 16), SYSTEM_PALETTE[0x16]);
+
+        // a horizontally flipped sprite with the second sprite palette
+        ppu.oam[0..4].copy_from_slice(&[0x10, 0x01, 0b0100_0001, 0x20]);
+        assert_eq!(ppu_viewer::sprite_list(&ppu)[0], "00 X20 Y10 T01 A41");
+        assert_eq!(ppu_viewer::sprite_list(&ppu)[63], "3F X00 Y00 T00 A00");
+        let sprites = ppu_viewer::render_sprites(&ppu);
+        assert_eq!(rgb(&sprites, ppu_viewer::SPRITES_WIDTH, 7, 0), SYSTEM_PALETTE[0x16]);
+        assert_eq!(rgb(&sprites, ppu_viewer::SPRITES_WIDTH, 6, 0), SYSTEM_PALETTE[0x2A]);
+        assert_eq!(rgb(&sprites, ppu_viewer::SPRITES_WIDTH, 0, 0), rgb(&sprites, ppu_viewer::SPRITES_WIDTH, 0, 8));
+
+        assert_eq!(ppu_viewer::render(&ppu, 5).len(), ppu_viewer::VIEW_WIDTH * ppu_viewer::VIEW_HEIGHT * 3);
+    }
+
+    #[test]
+    fn test_png_encoder() {
+        assert_eq!(png::crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(png::adler32(b"Wikipedia"), 0x11E6_0398);
+
+        let pixels: Vec<u8> = (0..2 * 3 * 3).collect();
+        let encoded = png::encode_rgb(2, 3, &pixels).unwrap();
+        assert_eq!(encoded[0..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(encoded[12..16], *b"IHDR");
+        assert_eq!(encoded[16..29], [0, 0, 0, 2, 0, 0, 0, 3, 8, 2, 0, 0, 0]);
+        assert_eq!(encoded[encoded.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+        // the image data is a single stored deflate block, every row starts with filter type 0
+        let idat = &encoded[33..encoded.len() - 12];
+        let length = u32::from_be_bytes(idat[0..4].try_into().unwrap()) as usize;
+        assert_eq!(idat[4..8], *b"IDAT");
+        let zlib = &idat[8..8 + length];
+        assert_eq!(zlib[0..3], [0x78, 0x01, 0x01]);
+        let raw = &zlib[7..zlib.len() - 4];
+        assert_eq!(raw, [0, 0, 1, 2, 3, 4, 5, 0, 6, 7, 8, 9, 10, 11, 0, 12, 13, 14, 15, 16, 17]);
+        assert_eq!(zlib[zlib.len() - 4..], png::adler32(raw).to_be_bytes());
+        assert_eq!(idat[8 + length..], png::crc32(&idat[4..8 + length]).to_be_bytes());
+
+        // images without pixels or with the wrong number of bytes are rejected
+        assert!(png::encode_rgb(0, 3, &[]).is_err());
+        assert!(png::encode_rgb(2, 0, &[]).is_err());
+        assert!(png::encode_rgb(2, 3, &pixels[1..]).is_err());
+    }
+
+    #[test]
+    fn test_screenshot() {
+        let mut frame = Frame::new();
+        frame.set_pixel(0, 8, 0x01);
+        frame.set_pixel(255, 231, 0x30);
+
+        let rgb = frame.to_rgb_cropped(&Palette::default(), Overscan::NONE, 1);
+        assert_eq!(rgb, frame.to_rgb(&Palette::default()));
+
+        // the first visible pixel of the cropped frame is the one of scanline 8, scaled up to 2x2 pixels
+        let overscan = Overscan::NTSC;
+        assert_eq!((overscan.width(), overscan.height()), (256, 224));
+        let rgb = frame.to_rgb_cropped(&Palette::default(), overscan, 2);
+        assert_eq!(rgb.len(), 512 * 448 * 3);
+        let (r, g, b) = SYSTEM_PALETTE[0x01];
+        assert_eq!(rgb[0..6], [r, g, b, r, g, b]);
+        assert_eq!(rgb[512 * 3..512 * 3 + 3], [r, g, b]);
+        let (r, g, b) = SYSTEM_PALETTE[0x30];
+        assert_eq!(rgb[rgb.len() - 3..], [r, g, b]);
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        assert_eq!(screenshot::format_timestamp(time), "20231114_221320_123");
+        assert_eq!(screenshot::format_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_164_800)), "20240229_000000_000");
+        assert_eq!(screenshot::file_name("roms/smb.nes", time, "png"), "smb_20231114_221320_123.png");
+    }
+
+    #[test]
+    fn test_avi_recorder() {
+        let mut frame = Frame::new();
+        frame.set_pixel(0, 239, 0x16);
+        let image = frame.to_image(&Palette::default());
+        let mut recorder = AviRecorder::new(Cursor::new(Vec::new()), Region::Ntsc, 256, 240).unwrap();
+        recorder.record_frame(&image, &[0.5, -1.0, 2.0]).unwrap();
+        recorder.record_frame(&image, &[0.0]).unwrap();
+        assert_eq!(recorder.frames(), 2);
+        let avi = recorder.finish().unwrap().into_inner();
+
+        let u32_at = |offset: usize| u32::from_le_bytes(avi[offset..offset + 4].try_into().unwrap());
+        assert_eq!(avi[0..4], *b"RIFF");
+        assert_eq!(u32_at(4) as usize, avi.len() - 8);
+        assert_eq!(avi[8..12], *b"AVI ");
+        // the total frames of the main header, the length of the video and the audio stream
+        assert_eq!(u32_at(48), 2);
+        assert_eq!(u32_at(140), 2);
+        assert_eq!(u32_at(264), 4);
+
+        // the bitmap of the first frame starts with the last row in BGR order
+        assert_eq!(avi[312..316], *b"LIST");
+        assert_eq!(avi[320..324], *b"movi");
+        assert_eq!(avi[324..328], *b"00db");
+        let (r, g, b) = SYSTEM_PALETTE[0x16];
+        assert_eq!(avi[332..335], [b, g, r]);
+
+        let audio = 332 + 256 * 240 * 3;
+        assert_eq!(avi[audio..audio + 4], *b"01wb");
+        assert_eq!(u32_at(audio + 4), 6);
+        assert_eq!(avi[audio + 8..audio + 14], [0xFF, 0x3F, 0x01, 0x80, 0xFF, 0x7F]);
+
+        // one index entry per chunk
+        let index = avi.len() - 4 * 16 - 8;
+        assert_eq!(avi[index..index + 4], *b"idx1");
+        assert_eq!(u32_at(index + 4), 4 * 16);
+
+        // frames beyond the size limit fail, the frames recorded up to it make a complete file
+        let mut recorder = AviRecorder::new(Cursor::new(Vec::new()), Region::Ntsc, 256, 240).unwrap();
+        let limit = 324 + 2 * (8 + 256 * 240 * 3 + 8 + 6) + 8 + 4 * 16;
+        recorder.set_size_limit(limit as u64);
+        recorder.record_frame(&image, &[0.0; 3]).unwrap();
+        recorder.record_frame(&image, &[0.0; 3]).unwrap();
+        assert!(recorder.record_frame(&image, &[0.0; 3]).is_err());
+        assert_eq!(recorder.frames(), 2);
+        let avi = recorder.finish().unwrap().into_inner();
+        assert_eq!(avi.len(), limit);
+        assert_eq!(u32::from_le_bytes(avi[4..8].try_into().unwrap()) as usize, limit - 8);
+    }
+
+    #[test]
+    fn test_movie() {
+        assert_eq!(movie::md5(b""), [0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04, 0xe9, 0x80, 0x09, 0x98, 0xec, 0xf8, 0x42, 0x7e]);
+        assert_eq!(movie::md5(b"The quick brown fox jumps over the lazy dog")[0..4], [0x9e, 0x10, 0x7d, 0x9d]);
+        assert_eq!(movie::base64(b"Man"), "TWFu");
+        assert_eq!(movie::base64(b"Ma"), "TWE=");
+        assert_eq!(movie::base64(b"M"), "TQ==");
+
+        // reads the first controller every frame and collects the pressed buttons in $11
+        let program = [
+            0xA9, 0x01, 0x8D, 0x16, 0x40,   // LDA #$01, STA $4016
+            0xA9, 0x00, 0x8D, 0x16, 0x40,   // LDA #$00, STA $4016
+            0xA2, 0x08,                     // LDX #$08
+            0xAD, 0x16, 0x40,               // LDA $4016
+            0x4A,                           // LSR A
+            0x26, 0x10,                     // ROL $10
+            0xCA,                           // DEX
+            0xD0, 0xF7,                     // BNE $C00C
+            0xA5, 0x10,                     // LDA $10
+            0x05, 0x11,                     // ORA $11
+            0x85, 0x11,                     // STA $11
+            0x4C, 0x00, 0xC0,               // JMP $C000
+        ];
+        let rom = create_test_rom(&program, 0xC000, 0xC000);
+
+        let mut recorded = Movie::new("test", &rom, Region::Ntsc);
+        recorded.record_frame(MovieFrame::default());
+        recorded.record_frame(MovieFrame { commands: 0, buttons: [JoypadButton::A | JoypadButton::Up, JoypadButton::empty()] });
+        recorded.record_frame(MovieFrame { commands: movie::COMMAND_HARD_RESET, buttons: [JoypadButton::empty(), JoypadButton::B] });
+        let fm2 = recorded.to_fm2();
+        assert!(fm2.starts_with("version 3\n"));
+        assert!(fm2.ends_with("|0|........|........||\n|0|...U...A|........||\n|2|........|......B.||\n"));
+
+        let movie = Movie::parse(&fm2).unwrap();
+        assert_eq!(movie, recorded);
+        assert!(movie.matches_rom(&rom));
+        assert!(!movie.matches_rom(&create_test_rom(&[0xEA], 0xC000, 0xC000)));
+
+        // the A button ends up in bit 7, Up in bit 3, the hard reset clears the ram again
+        let mut nes = Nes::new(&rom).unwrap();
+        let mut collected = Vec::new();
+        for frame in &movie.frames {
+            frame.apply(&mut nes);
+            nes.run_frame().unwrap();
+            collected.push(nes.peek_memory(MemoryRegion::CpuRam, 0x11));
+        }
+        assert_eq!(collected, [0x00, 0x88, 0x00]);
+
+        assert!(Movie::parse("version 3\nsavestate base64:AAAA\n").is_err());
+        assert!(Movie::parse("version 3\nbinary 1\n").is_err());
+        assert!(Movie::parse("version 3\nbinary 0\n").is_ok());
+        assert!(Movie::parse("version 3\n|0|..x|\n").is_err());
+        // ports without a controller have an empty field
+        let movie = Movie::parse("version 3\nport1 0\n|1|R.......|||\n").unwrap();
+        assert_eq!(movie.frames, [MovieFrame { commands: movie::COMMAND_SOFT_RESET, buttons: [JoypadButton::Right, JoypadButton::empty()] }]);
+    }
+
+    #[test]
+    fn test_nes_program_rom_size() {
+        let rom = create_test_rom(&[0x4C, 0x00, 0xC0], 0xC000, 0xC000);
+        let (header, program_rom) = rom.split_at(16);
+        let program_rom = &program_rom[..0x4000];
+
+        for banks in [0u8, 3] {
+            let mut invalid_rom = header.to_vec();
+            invalid_rom[4] = banks;
+            invalid_rom.extend(std::iter::repeat_n(program_rom, banks as usize).flatten());
+            invalid_rom.extend_from_slice(&[0u8; 0x2000]);
+            assert!(Nes::new(&invalid_rom).is_err(), "{} banks of program rom", banks);
+            assert!(Nes::new(&rom).unwrap().load_rom(&invalid_rom).is_err());
+        }
+
+        let mut rom_32k = header.to_vec();
+        rom_32k[4] = 2;
+        rom_32k.extend_from_slice(program_rom);
+        rom_32k.extend_from_slice(program_rom);
+        rom_32k.extend_from_slice(&[0u8; 0x2000]);
+        Nes::new(&rom_32k).unwrap().run_frame().unwrap();
+    }
+
+    #[test]
+    fn test_regions() {
+        assert_eq!("PAL".parse::<Region>(), Ok(Region::Pal));
+        assert!("secam".parse::<Region>().is_err());
+
+        // flags 9 of iNES, byte 12 of NES 2.0, garbage in the unused bytes makes flags 9 unreliable
+        let mut rom = create_test_rom(&[0x4C, 0x00, 0xC0], 0xC000, 0xC000);
+        assert_eq!(Nes::new(&rom).unwrap().region(), Region::Ntsc);
+        rom[9] = 1;
+        assert_eq!(Nes::new(&rom).unwrap().region(), Region::Pal);
+        rom[11..16].copy_from_slice(b"Dude!");
+        assert_eq!(Nes::new(&rom).unwrap().region(), Region::Ntsc);
+        rom[7] = 0b0000_1000;
+        rom[12] = 3;
+        assert_eq!(Nes::new(&rom).unwrap().region(), Region::Dendy);
+
+        // the cpu cycles per frame: 341 * 262 dots and 341 * 312 dots at 3 or 3.2 dots per cycle,
+        // the frame ends with the start of the vertical blank
+        for (region, cycles_per_frame, vblank_scanline) in [(Region::Ntsc, 29780.7, 241), (Region::Pal, 33247.5, 241), (Region::Dendy, 35464.0, 291)] {
+            let mut nes = Nes::new(&rom).unwrap();
+            nes.set_region(region);
+            assert_eq!(nes.region(), region);
+            nes.run_frame().unwrap();
+            let start = nes.cpu().cycles;
+            for _ in 0..10 {
+                nes.run_frame().unwrap();
+            }
+            let cycles = (nes.cpu().cycles - start) as f64 / 10.0;
+            assert!((cycles - cycles_per_frame).abs() < 1.0, "{} runs {} cycles per frame", region.name(), cycles);
+            assert_eq!(nes.ppu().scanline, vblank_scanline);
+        }
+
+        // a PAL frame lasts about 20ms, which are about 882 samples at 44.1kHz
+        let mut nes = Nes::new(&rom).unwrap();
+        nes.set_region(Region::Pal);
+        nes.run_frame().unwrap();
+        nes.run_frame().unwrap();
+        assert!((880..=884).contains(&nes.audio_samples().len()));
+
+        // the microseconds per frame in the AVI header
+        let avi = AviRecorder::new(Cursor::new(Vec::new()), Region::Pal, 256, 240).unwrap().finish().unwrap().into_inner();
+        assert_eq!(u32::from_le_bytes(avi[32..36].try_into().unwrap()), 19997);
+
+        let movie = Movie::new("test", &rom, Region::Pal);
+        assert!(movie.to_fm2().contains("palFlag 1\n"));
+        let movie = Movie::parse(&movie.to_fm2()).unwrap();
+        assert_eq!(movie.region(Region::Ntsc), Region::Pal);
+        assert_eq!(Movie::parse("version 3\npalFlag 0\n").unwrap().region(Region::Pal), Region::Ntsc);
+    }
+
+    #[test]
+    fn test_palette() {
+        let palette = Palette::default();
+        assert_eq!(palette.color(0x16), SYSTEM_PALETTE[0x16]);
+        // red emphasis keeps the red channel and darkens the others, all three darken every channel
+        let (r, g, b) = SYSTEM_PALETTE[0x30];
+        assert_eq!(palette.color(0x30 | 0b001 << 6), (r, (g as f32 * 0.746) as u8, (b as f32 * 0.746) as u8));
+        assert_eq!(palette.color(0x30 | 0b111 << 6), ((r as f32 * 0.746 * 0.746) as u8, (g as f32 * 0.746 * 0.746) as u8, (b as f32 * 0.746 * 0.746) as u8));
+
+        // .pal files with 64 colors get the emphasis variants derived, the ones with 512 colors are used as they are
+        let pal: Vec<u8> = SYSTEM_PALETTE.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+        assert_eq!(Palette::parse(&pal), Ok(palette.clone()));
+        let full: Vec<u8> = (0..512 * 3).map(|value| value as u8).collect();
+        assert_eq!(Palette::parse(&full).unwrap().color(0x1FF), (0xFD, 0xFE, 0xFF));
+        assert!(Palette::parse(&pal[..189]).is_err());
+        assert!(Palette::select("missing.pal").is_err());
+
+        // the palette decoded from the video signal: grey, black and white columns, red hues for $x6
+        let ntsc = Palette::ntsc();
+        let (r, g, b) = ntsc.color(0x00);
+        assert!(r == g && g == b && r > 64);
+        assert_eq!(ntsc.color(0x0F), (0, 0, 0));
+        assert_eq!(ntsc.color(0x20), (255, 255, 255));
+        let (r, g, b) = ntsc.color(0x16);
+        assert!(r > g && r > b);
+        let (r, g, b) = ntsc.color(0x20 | 0b100 << 6);
+        assert!(b > r && b > g);
+
+        // PPUMASK greyscale keeps the brightness of the backdrop, the emphasis bits end up in the frame
+        let program = [
+            0xA9, 0x3F, 0x8D, 0x06, 0x20,   // LDA #$3F, STA $2006
+            0xA9, 0x00, 0x8D, 0x06, 0x20,   // LDA #$00, STA $2006
+            0xA9, 0x16, 0x8D, 0x07, 0x20,   // LDA #$16, STA $2007
+            0xA9, 0x21, 0x8D, 0x01, 0x20,   // LDA #$21, STA $2001
+            0x4C, 0x14, 0xC0,               // JMP $C014
+        ];
+        let mut nes = Nes::new(&create_test_rom(&program, 0xC000, 0xC000)).unwrap();
+        nes.run_frame().unwrap();
+        nes.run_frame().unwrap();
+        assert_eq!(nes.frame_buffer().pixel(0, 0), 0x10 | 0b001 << 6);
+    }
+
+    #[test]
+    fn test_ntsc_filter() {
+        assert_eq!("sharpness=0.5,hue=-0.1".parse(), Ok(NtscSettings { sharpness: 0.5, hue: -0.1, ..NtscSettings::default() }));
+        assert!("blur=1".parse::<NtscSettings>().is_err());
+        assert!("hue=2".parse::<NtscSettings>().is_err());
+
+        let mut frame = Frame::new();
+        frame.pixels.fill(0x16);
+        let clean = NtscFilter::new(NtscSettings { artifacts: 0.0, ..NtscSettings::default() });
+        let image = clean.render(&frame, 0);
+        assert_eq!((image.width, image.height), (ntsc::NTSC_WIDTH, ntsc::NTSC_HEIGHT));
+        // every scanline is output twice
+        assert_eq!(image.pixels[0..ntsc::NTSC_WIDTH * 3], image.pixels[ntsc::NTSC_WIDTH * 3..ntsc::NTSC_WIDTH * 6]);
+
+        // without artifacts a flat area has the color of the decoded palette, with them the chroma leaks into the luma
+        let color = |image: &Image, x: usize, y: usize| {
+            let index = (y * image.width + x) * 3;
+            (image.pixels[index], image.pixels[index + 1], image.pixels[index + 2])
+        };
+        let (r, g, b) = Palette::ntsc().color(0x16);
+        for x in [100, 101, 102, 300] {
+            let (actual_r, actual_g, actual_b) = color(&image, x, 100);
+            assert!(actual_r.abs_diff(r) <= 1 && actual_g.abs_diff(g) <= 1 && actual_b.abs_diff(b) <= 1);
+        }
+        let artifacts = NtscFilter::new(NtscSettings { artifacts: 1.0, ..NtscSettings::default() }).render(&frame, 0);
+        assert!((100..110).any(|x| color(&artifacts, x, 100) != color(&artifacts, 100, 100)));
+
+        // half a turn of the hue makes red cyan
+        let rotated = NtscFilter::new(NtscSettings { hue: 1.0, artifacts: 0.0, ..NtscSettings::default() }).render(&frame, 0);
+        let (r, _, b) = color(&rotated, 300, 100);
+        assert!(b > r);
+
+        // the overscan is cropped in scanlines of the frame
+        let cropped = image.crop(Overscan::NTSC);
+        assert_eq!((cropped.width, cropped.height), (ntsc::NTSC_WIDTH, ntsc::NTSC_HEIGHT - 32));
+        let video = Video::default();
+        assert_eq!(video.render(&frame, 0), frame.to_image(&Palette::default()));
+    }
+
+    #[test]
+    fn test_scalers() {
+        let black = [0u8, 0, 0];
+        let white = [255u8, 255, 255];
+        // a white diagonal from the top right to the bottom left
+        let pixels: Vec<u8> = (0..9).flat_map(|i| if i % 3 + i / 3 == 2 { white } else { black }).collect();
+        let image = Image::new(3, 3, pixels);
+        let at = |image: &Image, x: usize, y: usize| {
+            let index = (y * image.width + x) * 3;
+            [image.pixels[index], image.pixels[index + 1], image.pixels[index + 2]]
+        };
+
+        // Scale2x fills in the steps of the diagonal, the nearest neighbour scale keeps them
+        let scaled = scaler::scale2x(&image);
+        assert_eq!((scaled.width, scaled.height), (6, 6));
+        assert_eq!(at(&scaled, 4, 2), white);
+        assert_eq!(at(&scaled, 5, 3), black);
+        assert_eq!(at(&image.scale(2), 4, 2), black);
+        let scaled = scaler::scale3x(&image);
+        assert_eq!((scaled.width, scaled.height), (9, 9));
+        assert_eq!(at(&scaled, 6, 3), white);
+        assert_eq!(at(&scaled, 8, 5), black);
+
+        // xBR blends the pixels next to the diagonal
+        let scaled = scaler::xbr(&image);
+        assert_eq!((scaled.width, scaled.height), (6, 6));
+        assert_eq!(at(&scaled, 3, 1), [127, 127, 127]);
+        assert_eq!(at(&scaled, 0, 0), black);
+
+        // hq2x fills the steps of the diagonal half way and keeps the thin line mostly white
+        let scaled = scaler::hq2x(&image);
+        assert_eq!((scaled.width, scaled.height), (6, 6));
+        assert_eq!(at(&scaled, 3, 1), [127, 127, 127]);
+        assert_eq!(at(&scaled, 2, 2), [223, 223, 223]);
+        assert_eq!(at(&scaled, 0, 0), black);
+        // colors within the YUV thresholds are treated as the same, a single pixel only blends slightly
+        let mut dot = Image::new(3, 3, vec![100; 3 * 3 * 3]);
+        dot.pixels[12..15].copy_from_slice(&[120, 120, 120]);
+        assert_eq!(at(&scaler::hq2x(&dot), 2, 2), [110, 110, 110]);
+        dot.pixels[12..15].copy_from_slice(&white);
+        assert_eq!(at(&scaler::hq2x(&dot), 2, 2), [235, 235, 235]);
+
+        // a flat image stays flat
+        let flat = Image::new(4, 4, vec![90; 4 * 4 * 3]);
+        for scaler in Scaler::ALL {
+            let scaled = scaler.apply(&flat);
+            assert_eq!((scaled.width, scaled.height), (4 * scaler.factor(), 4 * scaler.factor()));
+            assert!(scaled.pixels.iter().all(|value| *value == 90));
+        }
+
+        let stretched = scaler::stretch(&Frame::new().to_image(&Palette::default()), scaler::PIXEL_ASPECT_RATIO);
+        assert_eq!((stretched.width, stretched.height), (293, 240));
+        let mut lines = flat.scale(2);
+        scaler::scanlines(&mut lines, 2);
+        assert_eq!(at(&lines, 0, 0), [90, 90, 90]);
+        assert_eq!(at(&lines, 0, 1), [56, 56, 56]);
+
+        assert_eq!("XBR".parse(), Ok(Scaler::Xbr));
+        assert_eq!("hq2x".parse(), Ok(Scaler::Hq2x));
+        assert!("hq3x".parse::<Scaler>().is_err());
+        assert_eq!(Scaler::Xbr.next(), Scaler::Hq2x);
+        assert_eq!(Scaler::Hq2x.next(), Scaler::None);
+
+        let video = Video { scaler: Scaler::Scale2x, scale: 2, aspect_correction: true, scanlines: true, ..Video::default() };
+        let image = video.present(&Frame::new(), 0);
+        assert_eq!((image.width, image.height), (1170, 960));
+    }
+
+    #[test]
+    fn test_overscan() {
+        assert_eq!("8,8,0,0".parse(), Ok(Overscan::NTSC));
+        assert_eq!("none".parse(), Ok(Overscan::NONE));
+        assert!("8,8".parse::<Overscan>().is_err());
+        assert!("8,8,a,0".parse::<Overscan>().is_err());
+        assert!("120,120,0,0".parse::<Overscan>().is_err());
+        assert!(Overscan::new(0, 240, 0, 0).is_err());
+        assert!(Overscan::new(0, 0, 255, 1).is_err());
+        // a single scanline is left, the window still gets an image
+        let overscan = Overscan::new(0, 239, 0, 0).unwrap();
+        assert_eq!((overscan.width(), overscan.height()), (256, 1));
+        let video = Video { overscan, scale: 2, scanlines: true, ..Video::default() };
+        let image = video.present(&Frame::new(), 0);
+        assert_eq!((image.width, image.height), (512, 2));
+        assert_eq!(Overscan::for_region(Region::Ntsc), Overscan::NTSC);
+        assert_eq!(Overscan::for_region(Region::Pal), Overscan::PAL);
+
+        // the PAL overscan leaves 252x239 pixels, the rows of the bitmap need no padding
+        let mut frame = Frame::new();
+        frame.set_pixel(2, 239, 0x16);
+        let image = frame.to_image(&Palette::default());
+        let mut recorder = AviRecorder::new(Cursor::new(Vec::new()), Region::Pal, 252, 239).unwrap();
+        recorder.record_frame(&image.crop(Overscan::PAL), &[]).unwrap();
+        // images of another size are rejected
+        assert!(recorder.record_frame(&image, &[]).is_err());
+        let avi = recorder.finish().unwrap().into_inner();
+        let u32_at = |offset: usize| u32::from_le_bytes(avi[offset..offset + 4].try_into().unwrap());
+        assert_eq!((u32_at(64), u32_at(68)), (252, 239));
+        assert_eq!(u32_at(328), 252 * 3 * 239);
+        let (r, g, b) = SYSTEM_PALETTE[0x16];
+        assert_eq!(avi[332..335], [b, g, r]);
+
+        // odd widths are padded to 4 bytes
+        let mut recorder = AviRecorder::new(Cursor::new(Vec::new()), Region::Ntsc, 255, 240).unwrap();
+        recorder.record_frame(&image.crop("0,0,0,1".parse().unwrap()), &[]).unwrap();
+        let avi = recorder.finish().unwrap().into_inner();
+        assert_eq!(u32::from_le_bytes(avi[328..332].try_into().unwrap()), 768 * 240);
+
+        // the window crops before scaling, the NTSC filter keeps its width
+        let video = Video { overscan: Overscan::NTSC, scale: 2, scanlines: true, ..Video::default() };
+        let image = video.present(&frame, 0);
+        assert_eq!((image.width, image.height), (512, 448));
+        let video = Video { overscan: Overscan::PAL, ntsc: Some(NtscFilter::new(NtscSettings::default())), ..Video::default() };
+        let image = video.present(&frame, 0);
+        assert_eq!((image.width, image.height), (ntsc::NTSC_WIDTH * 252 / 256, ntsc::NTSC_HEIGHT - 2));
+
+        // the recordings get the filtered image with the overscan cut off
+        let image = video.render_cropped(&frame, 0);
+        assert_eq!((image.width, image.height), video.cropped_size());
+        let (width, height) = video.cropped_size();
+        let mut recorder = AviRecorder::new(Cursor::new(Vec::new()), Region::Pal, width, height).unwrap();
+        recorder.record_frame(&image, &[]).unwrap();
+        let video = Video { overscan: Overscan::NTSC, ..Video::default() };
+        assert_eq!(video.cropped_size(), (256, 224));
+    }
+
+    #[test]
+    fn test_frame_limiter() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(50.0, start);
+        assert_eq!(limiter.frame_duration(), Duration::from_millis(20));
+
+        // a frame which took 5ms waits 15ms, the waits do not drift when the frames take longer
+        assert_eq!(limiter.end_frame(start + Duration::from_millis(5)), Duration::from_millis(15));
+        assert_eq!(limiter.end_frame(start + Duration::from_millis(30)), Duration::from_millis(10));
+        // far behind, the pacing continues from now
+        assert_eq!(limiter.end_frame(start + Duration::from_millis(200)), Duration::ZERO);
+        assert_eq!(limiter.end_frame(start + Duration::from_millis(205)), Duration::from_millis(15));
+
+        limiter.set_speed(4.0, start).unwrap();
+        assert_eq!(limiter.frame_duration(), Duration::from_millis(5));
+        assert_eq!(limiter.end_frame(start + Duration::from_millis(1)), Duration::from_millis(4));
+        // only every fourth frame is shown while fast-forwarding
+        let presented = (0..8).filter(|frame| limiter.should_present(start + Duration::from_millis(frame * 5))).count();
+        assert_eq!(presented, 2);
+
+        limiter.set_speed(pacing::UNCAPPED, start).unwrap();
+        assert_eq!(limiter.end_frame(start + Duration::from_millis(1)), Duration::ZERO);
+        assert_eq!(limiter.end_frame(start + Duration::from_millis(1)), Duration::ZERO);
+
+        limiter.set_speed(0.5, start).unwrap();
+        assert_eq!(limiter.end_frame(start), Duration::from_millis(40));
+        assert!((0..4).all(|frame| limiter.should_present(start + Duration::from_millis(frame))));
+        assert_eq!(limiter.native_frame_duration(), Duration::from_millis(20));
+
+        // a speed just below the minimum would overflow the frame duration
+        assert!(limiter.set_speed(1e-300, start).is_err());
+        assert!(limiter.set_speed(pacing::MIN_SPEED / 2.0, start).is_err());
+        limiter.set_speed(pacing::MIN_SPEED, start).unwrap();
+        assert_eq!(limiter.frame_duration(), Duration::from_secs(2));
+
+        // 0 and speeds which are not finite run uncapped, negative speeds are rejected
+        for speed in [0.0, f64::NAN, f64::INFINITY] {
+            limiter.set_speed(speed, start).unwrap();
+            assert_eq!(limiter.speed(), pacing::UNCAPPED);
+            assert_eq!(limiter.frame_duration(), Duration::ZERO);
+        }
+        assert!(limiter.set_speed(-1.0, start).is_err());
+        assert!(limiter.set_speed(f64::NEG_INFINITY, start).is_err());
+        assert_eq!(limiter.speed(), pacing::UNCAPPED);
+    }
+
+    #[test]
+    fn test_golden_images() {
+        let pixels: Vec<u8> = (0..4 * 2 * 3).map(|value| value * 7).collect();
+        let mut encoded = png::encode_rgb(4, 2, &pixels).unwrap();
+        assert_eq!(png::decode_rgb(&encoded), Ok((4, 2, pixels)));
+        // the first block header of the image data, compressed blocks can not be decoded
+        encoded[43] |= 0b010;
+        assert!(png::decode_rgb(&encoded).is_err());
+        // an IHDR chunk of 5 instead of 13 bytes followed by the valid chunks
+        let mut short_header = encoded[0..8].to_vec();
+        short_header.extend_from_slice(&[0, 0, 0, 5, b'I', b'H', b'D', b'R', 0, 0, 0, 4, 0, 0, 0, 0, 0]);
+        short_header.extend_from_slice(&encoded[33..]);
+        assert!(png::decode_rgb(&short_header).is_err());
+
+        // sets the backdrop color to $16 while A is pressed, $0F otherwise
+        let mut program = vec![
+            0xA9, 0x01, 0x8D, 0x16, 0x40,   // LDA #$01, STA $4016
+            0xA9, 0x00, 0x8D, 0x16, 0x40,   // LDA #$00, STA $4016
+            0xAD, 0x16, 0x40,               // LDA $4016
+            0x29, 0x01,                     // AND #$01
+            0xAA,                           // TAX
+            0xBD, 0x30, 0xC0,               // LDA $C030,X
+            0xA0, 0x3F, 0x8C, 0x06, 0x20,   // LDY #$3F, STY $2006
+            0xA0, 0x00, 0x8C, 0x06, 0x20,   // LDY #$00, STY $2006
+            0x8D, 0x07, 0x20,               // STA $2007
+            0x4C, 0x00, 0xC0,               // JMP $C000
+        ];
+        program.resize(0x30, 0xEA);
+        program.extend_from_slice(&[0x0F, 0x16]);
+        let mut nes = Nes::new(&create_test_rom(&program, 0xC000, 0xC000)).unwrap();
+
+        let directory = std::env::temp_dir().join(format!("rust_nes_golden_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = golden::golden_path(directory.to_str().unwrap(), "backdrop", 2);
+        assert!(path.ends_with("backdrop_2.png"));
+
+        nes.run_frame().unwrap();
+        nes.run_frame().unwrap();
+        assert!(golden::check_frame(nes.frame_buffer(), &path, false).is_err());
+        assert_eq!(golden::check_frame(nes.frame_buffer(), &path, true), Ok(GoldenResult::Updated));
+        assert_eq!(golden::check_frame(nes.frame_buffer(), &path, false), Ok(GoldenResult::Match));
+        let hash = nes.frame_buffer().hash();
+
+        nes.set_input(0, JoypadButton::A);
+        // the NES has no third controller port, the input is ignored
+        nes.set_input(2, JoypadButton::B);
+        nes.run_frame().unwrap();
+        nes.run_frame().unwrap();
+        assert_ne!(nes.frame_buffer().hash(), hash);
+        let result = golden::check_frame(nes.frame_buffer(), &path, false).unwrap();
+        let GoldenResult::Mismatch { differing_pixels, actual_path, diff_path } = result else {
+            panic!("the frame should not match the golden image");
+        };
+        assert_eq!(differing_pixels, 256 * 240);
+        assert!(actual_path.ends_with("backdrop_2.actual.png"));
+        let (_, _, diff) = png::decode_rgb(&std::fs::read(&diff_path).unwrap()).unwrap();
+        assert_eq!(diff[0..3], [0xFF, 0x00, 0x00]);
+        let (_, _, actual) = png::decode_rgb(&std::fs::read(&actual_path).unwrap()).unwrap();
+        assert_eq!(actual, nes.frame_buffer().to_rgb(&Palette::default()));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_golden_movie() {
+        // the committed golden images of tests/golden, rewrite them with
+        // "headless tests/golden/sprite_scroll.nes --movie tests/golden/sprite_scroll.fm2 --golden tests/golden --golden-frames 30,90,120 --update-golden"
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+        let rom = std::fs::read(format!("{}/sprite_scroll.nes", directory)).unwrap();
+        let movie = Movie::load(&format!("{}/sprite_scroll.fm2", directory)).unwrap();
+        assert!(movie.matches_rom(&rom));
+        assert_eq!(movie.frames.len(), 120);
+
+        let mut nes = Nes::new(&rom).unwrap();
+        for (frame, input) in movie.frames.iter().enumerate() {
+            input.apply(&mut nes);
+            nes.run_frame().unwrap();
+
+            let frame_number = frame as u64 + 1;
+            if [30, 90, 120].contains(&frame_number) {
+                let path = golden::golden_path(directory, "sprite_scroll", frame_number);
+                assert_eq!(golden::check_frame(nes.frame_buffer(), &path, false), Ok(GoldenResult::Match), "frame {}", frame_number);
+            }
+        }
+    }
+
+    #[test]
+    fn test_nes_rejects_unsupported_mapper() {
+        let mut rom = create_test_rom(&[], 0xC000, 0xC000);
+        rom[6] |= 0b0001_0000;
+
+        assert!(Nes::new(&rom).is_err());
+    }
+
+    #[test]
+    fn test_power_on_and_reset() {
+        let mut ram = FlatRam::new();
+        ram.load(&[0x00, 0x80], 0xFFFC);
+
+        let mut cpu = CPU::new(ram);
+        cpu.power_on();
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(cpu.register_s, 0xFD);
+        assert_eq!(cpu.status, 0b0010_0100);
+        assert_eq!(cpu.cycles, 7);
+
+        // a reset keeps the registers and the memory but moves the stack pointer
+        cpu.register_a = 0x12;
+        cpu.register_x = 0x34;
+        cpu.status = 0b0000_0001;
+        cpu.mem_write(0x01FD, 0x56);
+        cpu.reset();
+        assert_eq!(cpu.register_a, 0x12);
+        assert_eq!(cpu.register_x, 0x34);
+        assert_eq!(cpu.register_s, 0xFA);
+        assert_eq!(cpu.status, 0b0000_0101);
+        assert_eq!(cpu.mem_read(0x01FD), 0x56);
+        assert_eq!(cpu.cycles, 14);
+
+        cpu.start_address = Some(0xC000);
+        cpu.power_on();
+        assert_eq!(cpu.program_counter, 0xC000);
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.register_s, 0xFD);
+    }
+
+    #[test]
+    fn test_ram_pattern() {
+        let mut nes = Nes::new(&create_test_rom(&[], 0xC000, 0xC000)).unwrap();
+        assert_eq!(nes.cpu().mem_peek(0x0000), 0x00);
+
+        nes.set_ram_pattern(RamPattern::Ones);
+        nes.power_on();
+        assert!((0..0x0800).all(|addr| nes.cpu().mem_peek(addr) == 0xFF));
+        assert_eq!(nes.cpu().mem_peek(0x6000), 0xFF);
+
+        // a reset does not touch the ram
+        nes.cpu_mut().mem_write(0x0010, 0x42);
+        nes.reset();
+        assert_eq!(nes.cpu().mem_peek(0x0010), 0x42);
+
+        assert_eq!("random".parse(), Ok(RamPattern::Random));
+        assert!("garbage".parse::<RamPattern>().is_err());
+    }
+
+    #[test]
+    fn test_ppu_registers() {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+
+        // write two bytes to the nametable at $2400, reads of $2007 are delayed by one read
+        cpu.mem_write(0x2006, 0x24);
+        cpu.mem_write(0x2006, 0x00);
+        cpu.mem_write(0x2007, 0x11);
+        cpu.mem_write(0x2007, 0x22);
+
+        cpu.mem_write(0x2006, 0x24);
+        cpu.mem_write(0x2006, 0x00);
+        cpu.mem_read(0x2007);
+        assert_eq!(cpu.mem_read(0x2007), 0x11);
+        assert_eq!(cpu.mem_read(0x2007), 0x22);
+
+        // the PPU registers are mirrored every 8 bytes
+        cpu.mem_write(0x3FFE, 0x3F);
+        cpu.mem_write(0x3FFE, 0x10);
+        cpu.mem_write(0x3FFF, 0x2C);
+
+        // the palette is read without delay and $3F10 mirrors $3F00
+        cpu.mem_write(0x2006, 0x3F);
+        cpu.mem_write(0x2006, 0x00);
+        assert_eq!(cpu.mem_read(0x2007), 0x2C);
+
+        // increment by 32 steps through the nametable column by column
+        cpu.mem_write(0x2000, 0b0000_0100);
+        cpu.mem_write(0x2006, 0x20);
+        cpu.mem_write(0x2006, 0x00);
+        cpu.mem_write(0x2007, 0x33);
+        cpu.mem_write(0x2007, 0x44);
+        assert_eq!(cpu.bus().ppu.read_vram(0x2000), 0x33);
+        assert_eq!(cpu.bus().ppu.read_vram(0x2020), 0x44);
+
+        // the cartridge uses horizontal mirroring, so $2800 mirrors $2C00
+        cpu.mem_write(0x2000, 0);
+        cpu.mem_write(0x2006, 0x28);
+        cpu.mem_write(0x2006, 0x05);
+        cpu.mem_write(0x2007, 0x55);
+        assert_eq!(cpu.bus().ppu.read_vram(0x2C05), 0x55);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+        cpu.bus_mut().joypad1.buttons = JoypadButton::A;
+
+        // unmapped reads return the last byte on the data bus, which is the high byte of the operand
+        cpu.interpret_without_reset(vec![0xAD, 0x00, 0x50, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.register_a, 0x50);
+        cpu.interpret_without_reset(vec![0xAD, 0x00, 0x40, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.register_a, 0x40);
+
+        // the controllers only drive bit 0, the upper 3 bits are open bus
+        cpu.interpret_without_reset(vec![0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.register_a, 0x41);
+
+        // bit 5 of $4015 is open bus, the last byte on the bus is the $40 of the operand
+        cpu.interpret_without_reset(vec![0xAD, 0x15, 0x40, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.register_a & 0b0010_0000, 0);
+
+        // LDA $3FF5,X crosses a page, the dummy read of $3F15 puts the $FF of the PPU latch on the bus
+        cpu.interpret_without_reset(vec![0xA9, 0xFF, 0x8D, 0x03, 0x20, 0xA2, 0x20, 0xBD, 0xF5, 0x3F, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.register_a & 0b0010_0000, 0b0010_0000);
+    }
+
+    #[test]
+    fn test_ppu_io_latch() {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+
+        // write only registers return the last value written to any PPU register
+        cpu.mem_write(0x2003, 0x3B);
+        assert_eq!(cpu.mem_read(0x2000), 0x3B);
+        // $2002 only drives the upper 3 bits
+        assert_eq!(cpu.mem_read(0x2002) & 0b0001_1111, 0x1B);
+        assert_eq!(cpu.mem_read(0x2005), 0x1B);
+
+        // the latch decays after about 600ms
+        for _ in 0..40 * 262 {
+            cpu.bus_mut().ppu.tick(341);
+        }
+        assert_eq!(cpu.mem_read(0x2000), 0x00);
+    }
+
+    #[test]
+    fn test_ppu_vblank() {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+        for _ in 0..241 {
+            cpu.bus_mut().ppu.tick(341);
+        }
+        cpu.bus_mut().ppu.tick(2);
+
+        assert_eq!(cpu.mem_read(0x2002) & 0b1000_0000, 0b1000_0000);
+        // reading the status clears the vblank flag
+        assert_eq!(cpu.mem_read(0x2002) & 0b1000_0000, 0);
+    }
+
+    #[test]
+    fn test_joypad() {
+        let mut cpu = create_new_cpu();
+        cpu.bus_mut().joypad1.buttons = JoypadButton::A | JoypadButton::Start | JoypadButton::Right;
+
+        cpu.mem_write(0x4016, 1);
+        // while strobing the state of A is returned
+        assert_eq!(cpu.mem_read(0x4016) & 1, 1);
+        assert_eq!(cpu.mem_read(0x4016) & 1, 1);
+        cpu.mem_write(0x4016, 0);
+
+        let buttons: Vec<u8> = (0..8).map(|_| cpu.mem_read(0x4016) & 1).collect();
+        assert_eq!(buttons, vec![1, 0, 0, 1, 0, 0, 0, 1]);
+        // after 8 reads official controllers return 1
+        assert_eq!(cpu.mem_read(0x4016) & 1, 1);
+        assert_eq!(cpu.mem_read(0x4017) & 1, 0);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut cpu = create_new_cpu();
+        for i in 0..=0xFF {
+            cpu.mem_write(0x0200 + i, i as u8);
+        }
+        cpu.power_on();
+        cpu.mem_write(0x2003, 0x00);
+
+        // LDA #$02, STA $4014, BRK
+        let cycles_before = cpu.cycles;
+        cpu.interpret_without_reset(vec![0xA9, 0x02, 0x8D, 0x14, 0x40, 0x00], 0x0600).unwrap();
+
+        for i in 0..=0xFF {
+            assert_eq!(cpu.bus().ppu.oam[i], i as u8);
+        }
+        // 2 + 4 cycles for the instructions, 513 or 514 for the transfer
+        assert!(cpu.cycles - cycles_before == 519 || cpu.cycles - cycles_before == 520);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+        assert_eq!(cpu.cycles, 7);
+
+        // LDA $06F0,X without and with a page crossing
+        cpu.register_x = 0x01;
+        cpu.interpret_without_reset(vec![0xBD, 0xF0, 0x06, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.cycles, 11);
+        cpu.register_x = 0x20;
+        cpu.interpret_without_reset(vec![0xBD, 0xF0, 0x06, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.cycles, 16);
+
+        // STA $06F0,X always takes 5 cycles
+        cpu.interpret_without_reset(vec![0x9D, 0xF0, 0x06, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.cycles, 21);
+
+        // BNE not taken, taken and taken across a page
+        cpu.register_x = 0x00;
+        cpu.interpret_without_reset(vec![0xA2, 0x00, 0xD0, 0x00, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.cycles, 25);
+        cpu.interpret_without_reset(vec![0xA2, 0x01, 0xD0, 0x00, 0x00], 0x0600).unwrap();
+        assert_eq!(cpu.cycles, 30);
+        cpu.interpret_without_reset(vec![0xA2, 0x01, 0xD0, 0x7F], 0x06F0).unwrap();
+        assert_eq!(cpu.cycles, 36);
+    }
+
+    #[test]
+    fn test_apu_status() {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+
+        // the length counter is only loaded for enabled channels
+        cpu.mem_write(0x4003, 0b0000_1000);
+        assert_eq!(cpu.mem_read(0x4015) & 0b0000_0001, 0);
+
+        cpu.mem_write(0x4015, 0b0000_0001);
+        cpu.mem_write(0x4003, 0b0000_1000);
+        assert_eq!(cpu.mem_read(0x4015) & 0b0000_0001, 1);
+
+        // the frame counter raises an irq after a full sequence in 4 step mode, reading $4015 acknowledges it
+        cpu.mem_write(0x4017, 0);
+        cpu.bus_mut().tick(29830);
+        assert!(cpu.bus().poll_irq());
+        assert_eq!(cpu.mem_read(0x4015) & 0b0100_0000, 0b0100_0000);
+        assert!(!cpu.bus().poll_irq());
+
+        // disabling the channel clears its length counter
+        cpu.mem_write(0x4015, 0);
+        assert_eq!(cpu.mem_read(0x4015) & 0b0000_0001, 0);
+    }
+
+    // the binaries of https://github.com/Klaus2m5/6502_65C02_functional_tests are not part of the
+    // repository, place them into test_roms/ and run the suites with --ignored
+    #[test]
+    #[ignore = "requires the binaries of the test suite in test_roms/"]
+    fn test_klaus_dormann_functional() {
+        let binary = std::fs::read(KLAUS_DORMANN_FUNCTIONAL_TEST)
+            .unwrap_or_else(|error| panic!("{} could not be read: {}", KLAUS_DORMANN_FUNCTIONAL_TEST, error));
+
+        let mut ram = FlatRam::new();
+        ram.load(&binary, 0x0000);
+
+        let mut cpu = CPU::new_with_variant(ram, CpuVariant::Nmos6502);
+        cpu.program_counter = 0x0400;
+
+        let trap = run_until_trap(&mut cpu, false);
+        assert_eq!(trap, Some(KLAUS_DORMANN_FUNCTIONAL_TEST_SUCCESS), "functional test trapped at {:04x?}", trap);
+    }
+
+    #[test]
+    #[ignore = "requires the binaries of the test suite in test_roms/"]
+    fn test_klaus_dormann_decimal() {
+        let binary = std::fs::read(KLAUS_DORMANN_DECIMAL_TEST)
+            .unwrap_or_else(|error| panic!("{} could not be read: {}", KLAUS_DORMANN_DECIMAL_TEST, error));
+
+        let mut ram = FlatRam::new();
+        ram.load(&binary, 0x0200);
+
+        let mut cpu = CPU::new_with_variant(ram, CpuVariant::Nmos6502);
+        cpu.program_counter = 0x0200;
+
+        run_until_trap(&mut cpu, true);
+        assert_eq!(cpu.mem_read(KLAUS_DORMANN_DECIMAL_TEST_ERROR), 0, "decimal test reported an error");
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum BusAccess {
+        Read(u16, u8),
+        Write(u16, u8),
+    }
+
+    // a flat ram which records every access of the cpu
+    #[derive(Default)]
+    struct LoggingRam {
+        ram: FlatRam,
+        accesses: Vec<BusAccess>,
+    }
+
+    impl Mem for LoggingRam {
+        fn mem_read(&mut self, addr: u16) -> u8 {
+            let value = self.ram.mem_read(addr);
+            self.accesses.push(BusAccess::Read(addr, value));
+            value
+        }
+
+        fn mem_peek(&self, addr: u16) -> u8 {
+            self.ram.mem_peek(addr)
+        }
+
+        fn mem_write(&mut self, addr: u16, data: u8) {
+            self.ram.mem_write(addr, data);
+            self.accesses.push(BusAccess::Write(addr, data));
+        }
+    }
+
+    // executes a single instruction at $0200 and returns the bus accesses it made
+    fn run_logged(program: &[u8], zero_page: &[u8], setup: impl Fn(&mut CPU<LoggingRam>)) -> Vec<BusAccess> {
+        let mut ram = LoggingRam::default();
+        ram.ram.load(zero_page, 0x0000);
+        ram.ram.load(program, 0x0200);
+
+        let mut cpu = CPU::new(ram);
+        cpu.program_counter = 0x0200;
+        cpu.register_s = 0xFD;
+        setup(&mut cpu);
+
+        cpu.step(|_, _| {}).unwrap();
+        assert_eq!(cpu.cycles, cpu.bus().accesses.len() as u64);
+
+        std::mem::take(&mut cpu.bus_mut().accesses)
+    }
+
+    #[test]
+    fn test_bus_accesses_indexed() {
+        use BusAccess::{Read, Write};
+
+        // LDA $1200,X only reads the target when no page is crossed
+        let accesses = run_logged(&[0xBD, 0x00, 0x12], &[], |cpu| cpu.register_x = 0x01);
+        assert_eq!(accesses, vec![Read(0x0200, 0xBD), Read(0x0201, 0x00), Read(0x0202, 0x12), Read(0x1201, 0x00)]);
+
+        // LDA $12F0,X reads the address without the carry first
+        let accesses = run_logged(&[0xBD, 0xF0, 0x12], &[], |cpu| cpu.register_x = 0x20);
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0xBD), Read(0x0201, 0xF0), Read(0x0202, 0x12), Read(0x1210, 0x00), Read(0x1310, 0x00),
+        ]);
+
+        // STA $1200,X always does the dummy read
+        let accesses = run_logged(&[0x9D, 0x00, 0x12], &[], |cpu| { cpu.register_x = 0x01; cpu.register_a = 0x42; });
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0x9D), Read(0x0201, 0x00), Read(0x0202, 0x12), Read(0x1201, 0x00), Write(0x1201, 0x42),
+        ]);
+
+        // LDA $F0,X reads the zero page base address while adding the index and wraps around
+        let accesses = run_logged(&[0xB5, 0xF0], &[], |cpu| cpu.register_x = 0x20);
+        assert_eq!(accesses, vec![Read(0x0200, 0xB5), Read(0x0201, 0xF0), Read(0x00F0, 0x00), Read(0x0010, 0x00)]);
+
+        // LDA ($10,X) reads the pointer before indexing it
+        let accesses = run_logged(&[0xA1, 0x10], &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x34, 0x12], |cpu| cpu.register_x = 0x02);
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0xA1), Read(0x0201, 0x10), Read(0x0010, 0x00), Read(0x0012, 0x34), Read(0x0013, 0x12), Read(0x1234, 0x00),
+        ]);
+
+        // LDA ($00),Y with a page crossing
+        let accesses = run_logged(&[0xB1, 0x00], &[0xF0, 0x12], |cpu| cpu.register_y = 0x20);
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0xB1), Read(0x0201, 0x00), Read(0x0000, 0xF0), Read(0x0001, 0x12), Read(0x1210, 0x00), Read(0x1310, 0x00),
+        ]);
+    }
+
+    #[test]
+    fn test_bus_accesses_read_modify_write() {
+        use BusAccess::{Read, Write};
+
+        // INC $10 writes the unmodified value back before the result
+        let accesses = run_logged(&[0xE6, 0x10], &[0; 0x11], |_| {});
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0xE6), Read(0x0201, 0x10), Read(0x0010, 0x00), Write(0x0010, 0x00), Write(0x0010, 0x01),
+        ]);
+
+        // ASL $1200,X
+        let accesses = run_logged(&[0x1E, 0x00, 0x12], &[], |cpu| cpu.register_x = 0x01);
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0x1E), Read(0x0201, 0x00), Read(0x0202, 0x12), Read(0x1201, 0x00),
+            Read(0x1201, 0x00), Write(0x1201, 0x00), Write(0x1201, 0x00),
+        ]);
+
+        // the accumulator version reads the next byte instead
+        let accesses = run_logged(&[0x0A, 0x55], &[], |_| {});
+        assert_eq!(accesses, vec![Read(0x0200, 0x0A), Read(0x0201, 0x55)]);
+    }
+
+    #[test]
+    fn test_bus_accesses_stack_and_branches() {
+        use BusAccess::{Read, Write};
+
+        // PLA reads the stack before incrementing the stack pointer
+        let accesses = run_logged(&[0x68], &[], |_| {});
+        assert_eq!(accesses, vec![Read(0x0200, 0x68), Read(0x0201, 0x00), Read(0x01FD, 0x00), Read(0x01FE, 0x00)]);
+
+        // JSR $1234 pushes the return address between reading the low and the high byte
+        let accesses = run_logged(&[0x20, 0x34, 0x12], &[], |_| {});
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0x20), Read(0x0201, 0x34), Read(0x01FD, 0x00), Write(0x01FD, 0x02), Write(0x01FC, 0x02), Read(0x0202, 0x12),
+        ]);
+
+        // RTS reads the pulled address while incrementing it
+        let accesses = run_logged(&[0x60], &[], |cpu| cpu.bus_mut().ram.load(&[0x02, 0x03], 0x01FE));
+        assert_eq!(accesses, vec![
+            Read(0x0200, 0x60), Read(0x0201, 0x00), Read(0x01FD, 0x00), Read(0x01FE, 0x02), Read(0x01FF, 0x03), Read(0x0302, 0x00),
+        ]);
+
+        // a taken BNE to the previous page reads the next opcode and the target without the page fixup
+        let accesses = run_logged(&[0xD0, 0xFC, 0xEA], &[], |_| {});
+        assert_eq!(accesses, vec![Read(0x0200, 0xD0), Read(0x0201, 0xFC), Read(0x0202, 0xEA), Read(0x02FE, 0x00)]);
+    }
+
+    #[test]
+    fn test_bus_trace() {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+
+        let accesses = Rc::new(RefCell::new(Vec::new()));
+        let recorder = accesses.clone();
+        cpu.set_bus_trace(Some(Box::new(move |access| recorder.borrow_mut().push(*access))));
+
+        // INC $10 followed by an OAM DMA of page 2
+        cpu.register_a = 0x02;
+        cpu.interpret_without_reset(vec![0xE6, 0x10, 0x8D, 0x14, 0x40, 0x00], 0x0600).unwrap();
+
+        let accesses = accesses.borrow();
+        let kinds: Vec<(BusAccessKind, bool)> = accesses.iter().take(5).map(|access| (access.kind, access.write)).collect();
+        assert_eq!(kinds, vec![
+            (BusAccessKind::OpcodeFetch, false),
+            (BusAccessKind::Operand, false),
+            (BusAccessKind::Operand, false),
+            (BusAccessKind::Dummy, true),
+            (BusAccessKind::Operand, true),
+        ]);
+        assert_eq!(accesses[0].cycle, 7);
+        assert_eq!(accesses[4].cycle, 11);
+        assert_eq!(format!("{}", accesses[4]), "     11 W 0010 01 operand");
+
+        assert_eq!(accesses.iter().filter(|access| access.kind == BusAccessKind::Dma).count(), 512);
+        assert!(accesses.iter().filter(|access| access.kind == BusAccessKind::Dma && access.write).all(|access| access.address == 0x2004));
+    }
+
+    #[test]
+    fn test_bus_accesses_match_opcode_cycles() {
+        const BRANCHES: [u8; 8] = [0x10, 0x30, 0x50, 0x70, 0x90, 0xB0, 0xD0, 0xF0];
+
+        for opcode in CPU_OPS_CODES.iter() {
+            let mut ram = LoggingRam::default();
+            ram.ram.load(&[opcode.code, 0x10, 0x02], 0x0200);
+
+            let mut cpu = CPU::new(ram);
+            cpu.program_counter = 0x0200;
+            cpu.register_s = 0xFD;
+            if cpu.step(|_, _| {}).is_err() {
+                continue;
+            }
+
+            // no page is crossed, the branches with a cleared flag are taken and take one additional cycle
+            let accesses = cpu.bus().accesses.len() as u64;
+            let taken = BRANCHES.contains(&opcode.code) && opcode.code & 0b0010_0000 == 0;
+            assert_eq!(accesses, opcode.cycles as u64 + taken as u64, "OpCode {:02x} {}", opcode.code, opcode.name);
+        }
+    }
+
+    // the per opcode json files (00.json .. ff.json) of https://github.com/SingleStepTests/65x02 are not part
+    // of the repository, place the 6502/v1 directory into test_roms/ and run them with --ignored
+    const SINGLE_STEP_TESTS: &str = "test_roms/6502/v1";
+    // the JAM opcodes halt the cpu and the results of these unstable opcodes depend on the individual chip,
+    // their results are reported but not required to match
+    const SINGLE_STEP_UNSTABLE_OPCODES: [u8; 19] = [
+        0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+        0x8B, 0xAB, 0x93, 0x9F, 0x9B, 0x9C, 0x9E,
+    ];
+
+    fn json_number(value: &json::Value, key: &str) -> u64 {
+        value.get(key).and_then(json::Value::as_u64).unwrap_or_else(|| panic!("{} is missing", key))
+    }
+
+    // returns the address and the value of the [address, value] pairs of a ram listing
+    fn json_ram(state: &json::Value) -> Vec<(u16, u8)> {
+        state.get("ram").and_then(json::Value::as_array).unwrap().iter().map(|entry| {
+            let entry = entry.as_array().unwrap();
+            (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8)
+        }).collect()
+    }
+
+    // runs a single test vector, returns a description of the first difference
+    fn run_single_step_test(test: &json::Value) -> Result<(), String> {
+        let initial = test.get("initial").unwrap();
+        let expected = test.get("final").unwrap();
+
+        let mut ram = LoggingRam::default();
+        for (address, value) in json_ram(initial) {
+            ram.ram.mem_write(address, value);
+        }
+
+        let mut cpu = CPU::new_with_variant(ram, CpuVariant::Nmos6502);
+        cpu.program_counter = json_number(initial, "pc") as u16;
+        cpu.register_s = json_number(initial, "s") as u8;
+        cpu.register_a = json_number(initial, "a") as u8;
+        cpu.register_x = json_number(initial, "x") as u8;
+        cpu.register_y = json_number(initial, "y") as u8;
+        cpu.status = json_number(initial, "p") as u8;
+
+        cpu.step(|_, _| {}).map_err(|error| error.to_string())?;
+
+        // the B flag and bit 5 only exist on the stack
+        let registers = [
+            ("pc", cpu.program_counter as u64, json_number(expected, "pc")),
+            ("s", cpu.register_s as u64, json_number(expected, "s")),
+            ("a", cpu.register_a as u64, json_number(expected, "a")),
+            ("x", cpu.register_x as u64, json_number(expected, "x")),
+            ("y", cpu.register_y as u64, json_number(expected, "y")),
+            ("p", (cpu.status | 0b0011_0000) as u64, json_number(expected, "p") | 0b0011_0000),
+        ];
+        for (name, value, expected_value) in registers {
+            if value != expected_value {
+                return Err(format!("{} is {:02x}, expected {:02x}", name, value, expected_value));
+            }
+        }
+
+        for (address, expected_value) in json_ram(expected) {
+            let value = cpu.mem_peek(address);
+            if value != expected_value {
+                return Err(format!("ram {:04x} is {:02x}, expected {:02x}", address, value, expected_value));
+            }
+        }
+
+        let expected_accesses: Vec<BusAccess> = test.get("cycles").and_then(json::Value::as_array).unwrap().iter().map(|cycle| {
+            let cycle = cycle.as_array().unwrap();
+            let address = cycle[0].as_u64().unwrap() as u16;
+            let value = cycle[1].as_u64().unwrap() as u8;
+            match cycle[2].as_str() {
+                Some("write") => BusAccess::Write(address, value),
+                _ => BusAccess::Read(address, value),
+            }
+        }).collect();
+        if cpu.bus().accesses != expected_accesses {
+            return Err(format!("bus accesses {:x?}, expected {:x?}", cpu.bus().accesses, expected_accesses));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "requires the json test vectors in test_roms/6502/v1"]
+    fn test_single_step_tests() {
+        let mut failed_opcodes = Vec::new();
+        for code in 0..=0xFFu8 {
+            let path = format!("{}/{:02x}.json", SINGLE_STEP_TESTS, code);
+            let content = std::fs::read_to_string(&path).unwrap_or_else(|error| panic!("{} could not be read: {}", path, error));
+
+            let tests = json::parse(&content).unwrap();
+            let tests = tests.as_array().unwrap();
+
+            let mut passed = 0;
+            let mut first_failure = None;
+            for test in tests {
+                match run_single_step_test(test) {
+                    Ok(()) => passed += 1,
+                    Err(error) => {
+                        if first_failure.is_none() {
+                            first_failure = Some(format!("{}: {}", test.get("name").and_then(json::Value::as_str).unwrap_or("?"), error));
+                        }
+                    },
+                }
+            }
+
+            println!("{:02x} {:>4}: {}/{} passed", code, OPCODES_MAP[&code].name, passed, tests.len());
+            if let Some(failure) = first_failure {
+                println!("    first failure {}", failure);
+                if !SINGLE_STEP_UNSTABLE_OPCODES.contains(&code) {
+                    failed_opcodes.push(code);
+                }
+            }
+        }
+
+        assert!(failed_opcodes.is_empty(), "opcodes {:02x?} failed", failed_opcodes);
+    }
+
+    #[test]
+    fn test_json_parser() {
+        let value = json::parse(r#"{ "name": "a9 \"x\"", "cycles": [[512, 169, "read"]], "ok": true, "none": null, "n": -1.5e1 }"#).unwrap();
+
+        assert_eq!(value.get("name").and_then(json::Value::as_str), Some("a9 \"x\""));
+        let cycle = value.get("cycles").and_then(json::Value::as_array).unwrap()[0].as_array().unwrap();
+        assert_eq!(cycle[0].as_u64(), Some(512));
+        assert_eq!(cycle[2].as_str(), Some("read"));
+        assert_eq!(value.get("ok"), Some(&json::Value::Bool(true)));
+        assert_eq!(value.get("none"), Some(&json::Value::Null));
+        assert_eq!(value.get("n"), Some(&json::Value::Number(-15.0)));
+        assert_eq!(value.get("n").unwrap().as_u64(), None);
+
+        assert!(json::parse("[1, 2").is_err());
+        assert!(json::parse("{} x").is_err());
+    }
+
     // --------------------------------
     //      opcode tests are below
     // --------------------------------
 
+    #[test]
+    fn test_all_opcodes_present() {
+        assert_eq!(CPU_OPS_CODES.len(), 256);
+        for code in 0..=0xFF {
+            assert!(OPCODES_MAP.contains_key(&code), "OpCode {:02x} is missing", code);
+        }
+    }
+
     #[test]
     fn test_sta_and_lda_from_memory() {
         let mut cpu = create_new_cpu();
-        cpu.interpret(vec![0xa9, 0x07, 0x8D, 0x00, 0x00, 0xa9, 0x02, 0xAE, 0x00, 0x00, 0x00]);
+        cpu.interpret(vec![0xa9, 0x07, 0x8D, 0x00, 0x00, 0xa9, 0x02, 0xAE, 0x00, 0x00, 0x00]).unwrap();
 
         assert_eq!(cpu.register_a, 0x02);
         assert_eq!(cpu.register_x, 0x07);
@@ -173,7 +1706,7 @@ mod test {
     fn test_inx_overflow() {
         let mut cpu = create_new_cpu();
         cpu.register_x = 0xff;
-        cpu.interpret(vec![0xa9, 0xff, 0xaa, 0xe8, 0xe8, 0x00]);
+        cpu.interpret(vec![0xa9, 0xff, 0xaa, 0xe8, 0xe8, 0x00]).unwrap();
         
         assert_eq!(cpu.register_x, 1);
     }
@@ -264,12 +1797,12 @@ mod test {
     macro_rules! opcode_test_case {
         ( $s:expr, $( $opcode:expr, $addressing_mode:expr, $value:expr, $setup:expr, $assert_stmt:stmt, $xydeviation:expr), * $(,)?) => {
             $(
-                $s.reset();
+                $s.power_on();
                 $setup;
 
                 let instructions = build_instructions($opcode, $addressing_mode, $value, $xydeviation);
 
-                $s.interpret_without_reset(instructions, 0x00);
+                $s.interpret_without_reset(instructions, 0x00).unwrap();
 
                 $assert_stmt
             )*
@@ -280,7 +1813,7 @@ mod test {
     #[test]
     fn test_and () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x29, &AddressingMode::Immediate, 0b0101_0101, cpu.register_a = 0b0101, assert_eq!(cpu.register_a, 0b0101), 0,
@@ -328,11 +1861,11 @@ mod test {
         macro_rules! adc_test_cases {
             ( $( $base:expr, $instructions:expr, $result:expr, $additional_setup:block), * $(,)?) => {
                 $(
-                    cpu.reset();
+                    cpu.power_on();
                     cpu.register_a = $base;
                     $additional_setup
 
-                    cpu.interpret_without_reset($instructions, 0x00);
+                    cpu.interpret_without_reset($instructions, 0x00).unwrap();
 
                     assert_eq!(cpu.register_a, $result);
                 )*
@@ -361,10 +1894,65 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_alr () {
+        let mut cpu = create_new_cpu();
+        opcode_test_case!{
+            cpu,
+            0x4B, &AddressingMode::Immediate, 0b0000_0111, cpu.register_a = 0b0000_0101, {
+                assert_eq!(cpu.register_a, 0b0000_0010);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, true);
+            }, 0,
+            0x4B, &AddressingMode::Immediate, 0b1000_0000, cpu.register_a = 0b1111_1111, {
+                assert_eq!(cpu.register_a, 0b0100_0000);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, false);
+            }, 0,
+            0x4B, &AddressingMode::Immediate, 0b0000_0001, cpu.register_a = 0b0000_0001, {
+                assert_eq!(cpu.register_a, 0b0000_0000);
+                check_zero_and_neg_flags(&cpu, true, false);
+                check_carry_flag(&cpu, true);
+            }, 0,
+        }
+    }
+
+    #[test]
+    fn test_arr () {
+        let mut cpu = create_new_cpu();
+        opcode_test_case!{
+            cpu,
+            0x6B, &AddressingMode::Immediate, 0xFF, {cpu.register_a = 0xFF; cpu.status = 0b0000_0001;}, {
+                assert_eq!(cpu.register_a, 0xFF);
+                check_zero_and_neg_flags(&cpu, false, true);
+                check_carry_flag(&cpu, true);
+                check_overflow_flag(&cpu, false);
+            }, 0,
+            0x6B, &AddressingMode::Immediate, 0x80, cpu.register_a = 0xFF, {
+                assert_eq!(cpu.register_a, 0x40);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, true);
+                check_overflow_flag(&cpu, true);
+            }, 0,
+            0x6B, &AddressingMode::Immediate, 0x60, cpu.register_a = 0xFF, {
+                assert_eq!(cpu.register_a, 0x30);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, false);
+                check_overflow_flag(&cpu, true);
+            }, 0,
+            0x6B, &AddressingMode::Immediate, 0x01, cpu.register_a = 0x01, {
+                assert_eq!(cpu.register_a, 0x00);
+                check_zero_and_neg_flags(&cpu, true, false);
+                check_carry_flag(&cpu, false);
+                check_overflow_flag(&cpu, false);
+            }, 0,
+        }
+    }
+
     #[test]
     fn test_asl () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x0A, &AddressingMode::NoneAddressing, 0b0000_0101, cpu.register_a = 0b0000_0101, {
@@ -395,18 +1983,42 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_axs () {
+        let mut cpu = create_new_cpu();
+        opcode_test_case!{
+            cpu,
+            0xCB, &AddressingMode::Immediate, 0x01, {cpu.register_a = 0x0F; cpu.register_x = 0xF3;}, {
+                assert_eq!(cpu.register_x, 0x02);
+                assert_eq!(cpu.register_a, 0x0F);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, true);
+            }, 0,
+            0xCB, &AddressingMode::Immediate, 0x04, {cpu.register_a = 0x0F; cpu.register_x = 0xF3;}, {
+                assert_eq!(cpu.register_x, 0xFF);
+                check_zero_and_neg_flags(&cpu, false, true);
+                check_carry_flag(&cpu, false);
+            }, 0,
+            0xCB, &AddressingMode::Immediate, 0x03, {cpu.register_a = 0x0F; cpu.register_x = 0xF3;}, {
+                assert_eq!(cpu.register_x, 0x00);
+                check_zero_and_neg_flags(&cpu, true, false);
+                check_carry_flag(&cpu, true);
+            }, 0,
+        }
+    }
+
     #[test]
     fn test_bcs () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0xB0, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0xB0, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
 
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b0000_0001;
-        cpu.interpret_without_reset(vec![0xB0, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0xB0, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
@@ -415,15 +2027,15 @@ mod test {
     #[test]
     fn test_bcc () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0x90, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0x90, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
 
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b0000_0001;
-        cpu.interpret_without_reset(vec![0x90, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0x90, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
@@ -432,23 +2044,23 @@ mod test {
     #[test]
     fn test_beq () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0xF0, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0xF0, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
 
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b0000_0010;
-        cpu.interpret_without_reset(vec![0xF0, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0xF0, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
 
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b0000_0010;
         // inx resets the zero flag, therefore the second branch needs to be an BNE
-        cpu.interpret_without_reset(vec![0xF0, 0x01, 0x00, 0xE8, 0xD0, 0xFC, 0xE8, 0xE8, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0xF0, 0x01, 0x00, 0xE8, 0xD0, 0xFC, 0xE8, 0xE8, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x01);
@@ -477,15 +2089,15 @@ mod test {
     #[test]
     fn test_bmi () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b1000_0000;
-        cpu.interpret_without_reset(vec![0x30, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0x30, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
 
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0x30, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0x30, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
@@ -494,21 +2106,21 @@ mod test {
     #[test]
     fn test_bne () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0xD0, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0xD0, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
 
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b0000_0010;
-        cpu.interpret_without_reset(vec![0xD0, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0xD0, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
 
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0xD0, 0x01, 0x00, 0xE8, 0xD0, 0xFC, 0xE8, 0xE8, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0xD0, 0x01, 0x00, 0xE8, 0xD0, 0xFC, 0xE8, 0xE8, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x01);
@@ -517,15 +2129,15 @@ mod test {
     #[test]
     fn test_bpl () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0x10, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0x10, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
 
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b1000_0000;
-        cpu.interpret_without_reset(vec![0x10, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0x10, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
@@ -534,15 +2146,15 @@ mod test {
     #[test]
     fn test_bvc () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0x50, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0x50, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
 
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b0100_0000;
-        cpu.interpret_without_reset(vec![0x50, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0x50, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
@@ -551,15 +2163,15 @@ mod test {
     #[test]
     fn test_bvs () {
         let mut cpu = create_new_cpu();
-        cpu.reset();
+        cpu.power_on();
         cpu.status = 0b0100_0000;
-        cpu.interpret_without_reset(vec![0x70, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.interpret_without_reset(vec![0x70, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x05);
         assert_eq!(cpu.register_x, 0x01);
 
-        cpu.reset();
-        cpu.interpret_without_reset(vec![0x70, 0x01, 0x00, 0xE8, 0x00], 0x00);
+        cpu.power_on();
+        cpu.interpret_without_reset(vec![0x70, 0x01, 0x00, 0xE8, 0x00], 0x00).unwrap();
 
         assert_eq!(cpu.program_counter, 0x03);
         assert_eq!(cpu.register_x, 0x00);
@@ -607,7 +2219,7 @@ mod test {
     #[test]
     fn test_cmp () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             // without carry set
@@ -650,7 +2262,7 @@ mod test {
     fn test_cpu_ram_mirroring() {
         let mut  cpu = create_new_cpu();
 
-        cpu.interpret(vec![0xEA, 0xEA, 0xEA, 0xEA, 0x00]);
+        cpu.interpret(vec![0xEA, 0xEA, 0xEA, 0xEA, 0x00]).unwrap();
         assert_eq!(cpu.program_counter, 0x0605);
         assert_eq!(cpu.register_s, 0xFD);
         assert_eq!(cpu.register_a, 0x00);
@@ -720,7 +2332,7 @@ mod test {
     #[test]
     fn test_dcp () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0xCF, &AddressingMode::Absolute, 0x43, {}, assert_eq!(cpu.last_mem_write_value, 0x42), 0,
@@ -750,10 +2362,75 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_decimal_mode () {
+        // the 2A03 ignores the decimal flag
+        let mut cpu = create_new_cpu();
+        opcode_test_case!{
+            cpu,
+            0x69, &AddressingMode::Immediate, 0x01, {cpu.register_a = 0x09; cpu.status = 0b0000_1000;}, {
+                assert_eq!(cpu.register_a, 0x0A);
+                check_carry_flag(&cpu, false);
+            }, 0,
+            0xE9, &AddressingMode::Immediate, 0x01, {cpu.register_a = 0x10; cpu.status = 0b0000_1001;}, {
+                assert_eq!(cpu.register_a, 0x0F);
+                check_carry_flag(&cpu, true);
+            }, 0,
+        }
+
+        let mut cpu = create_new_nmos_cpu();
+        opcode_test_case!{
+            cpu,
+            0x69, &AddressingMode::Immediate, 0x27, {cpu.register_a = 0x15; cpu.status = 0b0000_1000;}, {
+                assert_eq!(cpu.register_a, 0x42);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, false);
+            }, 0,
+            0x69, &AddressingMode::Immediate, 0x58, {cpu.register_a = 0x46; cpu.status = 0b0000_1001;}, {
+                assert_eq!(cpu.register_a, 0x05);
+                check_carry_flag(&cpu, true);
+            }, 0,
+            // the zero flag is taken from the binary result on the NMOS 6502
+            0x69, &AddressingMode::Immediate, 0x01, {cpu.register_a = 0x99; cpu.status = 0b0000_1000;}, {
+                assert_eq!(cpu.register_a, 0x00);
+                check_zero_and_neg_flags(&cpu, false, true);
+                check_carry_flag(&cpu, true);
+            }, 0,
+            0x69, &AddressingMode::Immediate, 0x79, {cpu.register_a = 0x79; cpu.status = 0b0000_1000;}, {
+                assert_eq!(cpu.register_a, 0x58);
+                check_carry_flag(&cpu, true);
+                check_overflow_flag(&cpu, true);
+            }, 0,
+            0xE9, &AddressingMode::Immediate, 0x15, {cpu.register_a = 0x42; cpu.status = 0b0000_1001;}, {
+                assert_eq!(cpu.register_a, 0x27);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, true);
+            }, 0,
+            0xE9, &AddressingMode::Immediate, 0x01, {cpu.register_a = 0x00; cpu.status = 0b0000_1001;}, {
+                assert_eq!(cpu.register_a, 0x99);
+                check_zero_and_neg_flags(&cpu, false, true);
+                check_carry_flag(&cpu, false);
+            }, 0,
+            0xE9, &AddressingMode::Immediate, 0x21, {cpu.register_a = 0x32; cpu.status = 0b0000_1000;}, {
+                assert_eq!(cpu.register_a, 0x10);
+                check_carry_flag(&cpu, true);
+            }, 0,
+            0x6B, &AddressingMode::Immediate, 0xFF, {cpu.register_a = 0xFF; cpu.status = 0b0000_1000;}, {
+                assert_eq!(cpu.register_a, 0xD5);
+                check_zero_and_neg_flags(&cpu, false, false);
+                check_carry_flag(&cpu, true);
+            }, 0,
+            // without the decimal flag the NMOS 6502 calculates binary
+            0x69, &AddressingMode::Immediate, 0x01, {cpu.register_a = 0x09; cpu.status = 0b0000_0000;}, {
+                assert_eq!(cpu.register_a, 0x0A);
+            }, 0,
+        }
+    }
+
     #[test]
     fn test_dec () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0xC6, &AddressingMode::ZeroPage, 0x23, {}, assert_eq!(cpu.last_mem_write_value, 0x22), 0,
@@ -809,7 +2486,7 @@ mod test {
     #[test]
     fn test_eor () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x49, &AddressingMode::Immediate,   0b0101_0101, cpu.register_a = 0b0000_0101, assert_eq!(cpu.register_a, 0b0101_0000), 0,
@@ -871,7 +2548,7 @@ mod test {
     #[test]
     fn test_inc () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 9 as u8;
+        let xydeviation = 9_u8;
         opcode_test_case!{
             cpu,
             0xE6, &AddressingMode::ZeroPage, 0x23, {}, {
@@ -896,7 +2573,7 @@ mod test {
     #[test]
     fn test_isb () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0xEF, &AddressingMode::Absolute, 0x23, cpu.register_a = 0x25, {
@@ -945,20 +2622,67 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_jam () {
+        let mut cpu = create_new_cpu();
+        for opcode in [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2] {
+            cpu.power_on();
+            let result = cpu.interpret_without_reset(vec![0xE8, opcode, 0xE8, 0x00], 0x00);
+
+            assert_eq!(result, Err(CpuError::Jammed { opcode, address: 0x01 }));
+            assert!(cpu.jammed);
+            assert_eq!(cpu.program_counter, 0x01);
+            assert_eq!(cpu.register_x, 0x01);
+
+            // a jammed cpu does not execute anything until it is reset
+            assert_eq!(cpu.run(|_, _| {}), Err(CpuError::Jammed { opcode, address: 0x01 }));
+            assert_eq!(cpu.register_x, 0x01);
+        }
+
+        cpu.reset();
+        assert!(!cpu.jammed);
+
+        // a jam opcode at the end of the address space, the program counter has already wrapped to $0000
+        let mut ram = FlatRam::new();
+        ram.load(&[0x02], 0xFFFF);
+        let mut cpu = CPU::new(ram);
+        cpu.program_counter = 0xFFFF;
+        assert_eq!(cpu.step(|_, _| {}), Err(CpuError::Jammed { opcode: 0x02, address: 0xFFFF }));
+        assert_eq!(cpu.program_counter, 0xFFFF);
+        assert!(cpu.jammed);
+    }
+
+    #[test]
+    fn test_unknown_opcode () {
+        // every opcode is decoded, so UnknownOpcode is only returned for an incomplete opcode table
+        for code in 0..=0xFFu8 {
+            assert!(OPCODES_MAP.contains_key(&code), "opcode {:02x} is missing", code);
+        }
+
+        assert_eq!(
+            CpuError::UnknownOpcode { opcode: 0x02, address: 0xFFFF }.to_string(),
+            "OpCode 02 at ffff is not recognized"
+        );
+        assert_eq!(
+            CpuError::Jammed { opcode: 0x02, address: 0xFFFF }.to_string(),
+            "CPU jammed by opcode 02 at ffff"
+        );
+    }
+
     #[test]
     fn test_jmp () {
         let mut cpu = create_new_cpu();
 
-        cpu.interpret(vec![0x4C, 0x02, 0x11, 0x00, 0x00]);
+        cpu.interpret(vec![0x4C, 0x02, 0x11, 0x00, 0x00]).unwrap();
         assert_eq!(cpu.program_counter, 0x1103);
-        cpu.interpret(vec![0x6C, 0x03, 0x06, 0x02, 0x11, 0x00, 0x00]); // default base address is (time of writing 0x0600)
+        cpu.interpret(vec![0x6C, 0x03, 0x06, 0x02, 0x11, 0x00, 0x00]).unwrap(); // default base address is (time of writing 0x0600)
         assert_eq!(cpu.program_counter, 0x1103);
     }
 
     #[test]
     fn test_jsr () {
         let mut cpu = create_new_cpu();
-        cpu.interpret(vec![0x20, 0x02, 0x11, 0x00, 0x00]);
+        cpu.interpret(vec![0x20, 0x02, 0x11, 0x00, 0x00]).unwrap();
         assert_eq!(cpu.program_counter, 0x1103);
         assert_eq!(cpu.register_s, 0xFB);
         assert_eq!(cpu.last_mem_write_address, 0x01FC); // check if it pushed something to the stack
@@ -975,59 +2699,59 @@ mod test {
 
                 if opcodes.contains_key(&AddressingMode::Immediate) {
                     // immediate mode loading of values [1, 127]
-                    cpu.interpret(vec![opcodes[&AddressingMode::Immediate], 0x07, 0x00]);
+                    cpu.interpret(vec![opcodes[&AddressingMode::Immediate], 0x07, 0x00]).unwrap();
                     assert_eq!(cpu.$cpu_target_register, 0x07);
                     check_zero_and_neg_flags(&cpu, false, false);
 
                     // immediate mode value 0, for a valid test we need to pollute the register first
-                    cpu.interpret(vec![opcodes[&AddressingMode::Immediate], 0x07, opcodes[&AddressingMode::Immediate], 0x00, 0x00]);
+                    cpu.interpret(vec![opcodes[&AddressingMode::Immediate], 0x07, opcodes[&AddressingMode::Immediate], 0x00, 0x00]).unwrap();
                     assert_eq!(cpu.$cpu_target_register, 0x00);
                     check_zero_and_neg_flags(&cpu, true, false);
 
                     // immediate mode negative values [128, 255]
-                    cpu.interpret(vec![opcodes[&AddressingMode::Immediate], 0x97, 0x00]);
+                    cpu.interpret(vec![opcodes[&AddressingMode::Immediate], 0x97, 0x00]).unwrap();
                     assert_eq!(cpu.$cpu_target_register, 0x97);
                     check_zero_and_neg_flags(&cpu, false, true);
                 }
 
                 // absolute mode loading of values [1, 127]
-                cpu.interpret(vec![opcodes[&AddressingMode::Absolute], 0x06, 0x06, 0x00, 0x05, 0x08, 0x09, 0x0A]);
+                cpu.interpret(vec![opcodes[&AddressingMode::Absolute], 0x06, 0x06, 0x00, 0x05, 0x08, 0x09, 0x0A]).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x09);
                 check_zero_and_neg_flags(&cpu, false, false);
 
                 // absolute mode value 0
-                cpu.interpret(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::Absolute], 0x08, 0x06, 0x00, 0x05, 0x08, 0x00, 0x0A]);
+                cpu.interpret(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::Absolute], 0x08, 0x06, 0x00, 0x05, 0x08, 0x00, 0x0A]).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x00);
                 check_zero_and_neg_flags(&cpu, true, false);
 
                 // absolute mode negative values [128, 255]
-                cpu.interpret(vec![opcodes[&AddressingMode::Absolute], 0x06, 0x06, 0x00, 0x00, 0x00, 0x97]);
+                cpu.interpret(vec![opcodes[&AddressingMode::Absolute], 0x06, 0x06, 0x00, 0x00, 0x00, 0x97]).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x97);
                 check_zero_and_neg_flags(&cpu, false, true);
 
                 macro_rules! test_absolute_addressing_xy {
                     ($addressing_mode:ident, $addressing_register:ident) => {
                         // absolute x/y mode loading of values [1, 127]
-                        cpu.reset();
+                        cpu.power_on();
                         cpu.$addressing_register = 0x01;
                         
-                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x06, 0x06, 0x00, 0x05, 0x08, 0x09, 0x0A, 0x0B], 0x0600);
+                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x06, 0x06, 0x00, 0x05, 0x08, 0x09, 0x0A, 0x0B], 0x0600).unwrap();
                         assert_eq!(cpu.$cpu_target_register, 0x0A);
                         check_zero_and_neg_flags(&cpu, false, false);
             
                         // absolute x/y mode value 0
-                        cpu.reset();
+                        cpu.power_on();
                         cpu.$addressing_register = 0x01;
             
-                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::$addressing_mode], 0x08, 0x06, 0x00, 0x05, 0x08, 0x09, 0x00, 0x0B], 0x0600);
+                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::$addressing_mode], 0x08, 0x06, 0x00, 0x05, 0x08, 0x09, 0x00, 0x0B], 0x0600).unwrap();
                         assert_eq!(cpu.$cpu_target_register, 0x00);
                         check_zero_and_neg_flags(&cpu, true, false);
             
                         // absolute x/y mode negative values [128, 255]
-                        cpu.reset();
+                        cpu.power_on();
                         cpu.$addressing_register = 0x01;
             
-                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x06, 0x06, 0x00, 0x00, 0x08, 0x03, 0x99, 0x02], 0x0600);
+                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x06, 0x06, 0x00, 0x00, 0x08, 0x03, 0x99, 0x02], 0x0600).unwrap();
                         assert_eq!(cpu.$cpu_target_register, 0x99);
                         check_zero_and_neg_flags(&cpu, false, true);
                     };
@@ -1046,52 +2770,52 @@ mod test {
                 }
 
                 // zero page
-                cpu.reset();
-                cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x05, 0x00, 0x05, 0x08, 0x09, 0x0A, 0x0B], 0x00);
+                cpu.power_on();
+                cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x05, 0x00, 0x05, 0x08, 0x09, 0x0A, 0x0B], 0x00).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x09);
                 check_zero_and_neg_flags(&cpu, false, false);
 
-                cpu.reset();
-                cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::ZeroPage], 0x08, 0x00, 0x05, 0x08, 0x09, 0x00, 0x0B, 0x0C], 0x00);
+                cpu.power_on();
+                cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::ZeroPage], 0x08, 0x00, 0x05, 0x08, 0x09, 0x00, 0x0B, 0x0C], 0x00).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x00);
                 check_zero_and_neg_flags(&cpu, true, false);
 
-                cpu.reset();
-                cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x05, 0x00, 0x00, 0x08, 0x99, 0x03, 0x02], 0x00);
+                cpu.power_on();
+                cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x05, 0x00, 0x00, 0x08, 0x99, 0x03, 0x02], 0x00).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x99);
                 check_zero_and_neg_flags(&cpu, false, true);
 
                 macro_rules! test_zero_page_addressing_xy {
                     ($addressing_mode:ident, $addressing_register:ident) => {
                         // absolute x/y mode loading of values [1, 127]
-                        cpu.reset();
+                        cpu.power_on();
                         cpu.$addressing_register = 0x01;
                         
-                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x05, 0x00, 0x05, 0x08, 0x09, 0x0A, 0x0B], 0x00);
+                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x05, 0x00, 0x05, 0x08, 0x09, 0x0A, 0x0B], 0x00).unwrap();
                         assert_eq!(cpu.$cpu_target_register, 0x0A);
                         check_zero_and_neg_flags(&cpu, false, false);
             
                         // absolute x/y mode value 0
-                        cpu.reset();
+                        cpu.power_on();
                         cpu.$addressing_register = 0x01;
             
-                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::$addressing_mode], 0x08, 0x00, 0x05, 0x08, 0x09, 0x0B, 0x00, 0x0C], 0x00);
+                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::ZeroPage], 0x01, opcodes[&AddressingMode::$addressing_mode], 0x08, 0x00, 0x05, 0x08, 0x09, 0x0B, 0x00, 0x0C], 0x00).unwrap();
                         assert_eq!(cpu.$cpu_target_register, 0x00);
                         check_zero_and_neg_flags(&cpu, true, false);
             
                         // absolute x/y mode negative values [128, 255]
-                        cpu.reset();
+                        cpu.power_on();
                         cpu.$addressing_register = 0x01;
             
-                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x05, 0x00, 0x00, 0x08, 0x03, 0x99, 0x02], 0x00);
+                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x05, 0x00, 0x00, 0x08, 0x03, 0x99, 0x02], 0x00).unwrap();
                         assert_eq!(cpu.$cpu_target_register, 0x99);
                         check_zero_and_neg_flags(&cpu, false, true);
 
                         // absolute x/y mode page wrap
-                        cpu.reset();
+                        cpu.power_on();
                         cpu.$addressing_register = 0xFE;
             
-                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x06, 0x00, 0x00, 0x08, 0x03, 0x99, 0x02], 0x00);
+                        cpu.interpret_without_reset(vec![opcodes[&AddressingMode::$addressing_mode], 0x06, 0x00, 0x00, 0x08, 0x03, 0x99, 0x02], 0x00).unwrap();
                         assert_eq!(cpu.$cpu_target_register, 0x08);
                         check_zero_and_neg_flags(&cpu, false, false);
                     };
@@ -1116,12 +2840,12 @@ mod test {
         test_lax_register_a,
         register_a,
         hashmap! {
-            AddressingMode::ZeroPage => 0xA7 as u8,
-            AddressingMode::ZeroPageY => 0xB7 as u8,
-            AddressingMode::Absolute => 0xAF as u8,
-            AddressingMode::AbsoluteY => 0xBF as u8,
-            AddressingMode::IndirectX => 0xA3 as u8,
-            AddressingMode::IndirectY => 0xB3 as u8,
+            AddressingMode::ZeroPage => 0xA7_u8,
+            AddressingMode::ZeroPageY => 0xB7_u8,
+            AddressingMode::Absolute => 0xAF_u8,
+            AddressingMode::AbsoluteY => 0xBF_u8,
+            AddressingMode::IndirectX => 0xA3_u8,
+            AddressingMode::IndirectY => 0xB3_u8,
         }
     );
 
@@ -1129,11 +2853,11 @@ mod test {
         test_ldx,
         register_x,
         hashmap!{
-            AddressingMode::Immediate => 0xA2 as u8,
-            AddressingMode::ZeroPage => 0xA6 as u8,
-            AddressingMode::ZeroPageY => 0xB6 as u8,
-            AddressingMode::Absolute => 0xAE as u8,
-            AddressingMode::AbsoluteY => 0xBE as u8,
+            AddressingMode::Immediate => 0xA2_u8,
+            AddressingMode::ZeroPage => 0xA6_u8,
+            AddressingMode::ZeroPageY => 0xB6_u8,
+            AddressingMode::Absolute => 0xAE_u8,
+            AddressingMode::AbsoluteY => 0xBE_u8,
         }
     );
 
@@ -1141,11 +2865,11 @@ mod test {
         test_ldy,
         register_y,
         hashmap!{
-            AddressingMode::Immediate => 0xA0 as u8,
-            AddressingMode::ZeroPage => 0xA4 as u8,
-            AddressingMode::ZeroPageX => 0xB4 as u8,
-            AddressingMode::Absolute => 0xAC as u8,
-            AddressingMode::AbsoluteX => 0xBC as u8,
+            AddressingMode::Immediate => 0xA0_u8,
+            AddressingMode::ZeroPage => 0xA4_u8,
+            AddressingMode::ZeroPageX => 0xB4_u8,
+            AddressingMode::Absolute => 0xAC_u8,
+            AddressingMode::AbsoluteX => 0xBC_u8,
         }
     );
 
@@ -1153,19 +2877,41 @@ mod test {
         test_lda,
         register_a,
         hashmap!{
-            AddressingMode::Immediate => 0xA9 as u8,
-            AddressingMode::ZeroPage => 0xA5 as u8,
-            AddressingMode::ZeroPageX => 0xB5 as u8,
-            AddressingMode::Absolute => 0xAD as u8,
-            AddressingMode::AbsoluteX => 0xBD as u8,
-            AddressingMode::AbsoluteY => 0xB9 as u8,
+            AddressingMode::Immediate => 0xA9_u8,
+            AddressingMode::ZeroPage => 0xA5_u8,
+            AddressingMode::ZeroPageX => 0xB5_u8,
+            AddressingMode::Absolute => 0xAD_u8,
+            AddressingMode::AbsoluteX => 0xBD_u8,
+            AddressingMode::AbsoluteY => 0xB9_u8,
         }
     );
 
+    #[test]
+    fn test_las () {
+        let mut cpu = create_new_cpu();
+        let xydeviation = 7_u8;
+        opcode_test_case!{
+            cpu,
+            0xBB, &AddressingMode::AbsoluteY, 0b1010_1010, cpu.register_y = xydeviation, {
+                // the stack pointer is 0xFD after the reset
+                assert_eq!(cpu.register_a, 0b1010_1000);
+                assert_eq!(cpu.register_x, 0b1010_1000);
+                assert_eq!(cpu.register_s, 0b1010_1000);
+                check_zero_and_neg_flags(&cpu, false, true);
+            }, xydeviation,
+            0xBB, &AddressingMode::AbsoluteY, 0b0000_0010, cpu.register_y = xydeviation, {
+                assert_eq!(cpu.register_a, 0x00);
+                assert_eq!(cpu.register_x, 0x00);
+                assert_eq!(cpu.register_s, 0x00);
+                check_zero_and_neg_flags(&cpu, true, false);
+            }, xydeviation,
+        }
+    }
+
     #[test]
     fn test_lsr () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 5 as u8;
+        let xydeviation = 5_u8;
         opcode_test_case!{
             cpu,
             // without carry set
@@ -1207,6 +2953,29 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_lxa () {
+        let mut cpu = create_new_cpu();
+        opcode_test_case!{
+            cpu,
+            0xAB, &AddressingMode::Immediate, 0xFF, cpu.register_a = 0x01, {
+                assert_eq!(cpu.register_a, 0xEF);
+                assert_eq!(cpu.register_x, 0xEF);
+                check_zero_and_neg_flags(&cpu, false, true);
+            }, 0,
+            0xAB, &AddressingMode::Immediate, 0x35, {cpu.register_a = 0x00; cpu.magic_constant = 0xFF;}, {
+                assert_eq!(cpu.register_a, 0x35);
+                assert_eq!(cpu.register_x, 0x35);
+                check_zero_and_neg_flags(&cpu, false, false);
+            }, 0,
+            0xAB, &AddressingMode::Immediate, 0x35, {cpu.register_a = 0x00; cpu.magic_constant = 0x00;}, {
+                assert_eq!(cpu.register_a, 0x00);
+                assert_eq!(cpu.register_x, 0x00);
+                check_zero_and_neg_flags(&cpu, true, false);
+            }, 0,
+        }
+    }
+
     #[test]
     fn test_nop() {
         let mut  cpu = create_new_cpu();
@@ -1215,7 +2984,7 @@ mod test {
         let top_opcodes: Vec<u8> = vec![0x0C, 0x1C, 0x3C, 0x5C, 0x7C, 0xDC, 0xFC];
 
         for opcode in nop_opcodes {
-            cpu.interpret(vec![opcode, opcode, opcode, opcode, 0x00]);
+            cpu.interpret(vec![opcode, opcode, opcode, opcode, 0x00]).unwrap();
             assert_eq!(cpu.program_counter, 0x0605);
             assert_eq!(cpu.register_s, 0xFD);
             assert_eq!(cpu.register_a, 0x00);
@@ -1224,7 +2993,7 @@ mod test {
         }
 
         for opcode in dop_opcodes {
-            cpu.interpret(vec![opcode, 0x00, opcode, 0xA9, opcode, 0xA9, opcode, 0x01, 0x00]);
+            cpu.interpret(vec![opcode, 0x00, opcode, 0xA9, opcode, 0xA9, opcode, 0x01, 0x00]).unwrap();
             assert_eq!(cpu.program_counter, 0x0609);
             assert_eq!(cpu.register_s, 0xFD);
             assert_eq!(cpu.register_a, 0x00);
@@ -1233,7 +3002,7 @@ mod test {
         }
 
         for opcode in top_opcodes {
-            cpu.interpret(vec![opcode, 0x00, 0x00, opcode, 0xA9, 0x00, opcode, 0x00, 0xA9, opcode, 0x01, 0x00, 0x00]);
+            cpu.interpret(vec![opcode, 0x00, 0x00, opcode, 0xA9, 0x00, opcode, 0x00, 0xA9, opcode, 0x01, 0x00, 0x00]).unwrap();
             assert_eq!(cpu.program_counter, 0x060D);
             assert_eq!(cpu.register_s, 0xFD);
             assert_eq!(cpu.register_a, 0x00);
@@ -1245,7 +3014,7 @@ mod test {
     #[test]
     fn test_ora () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x09, &AddressingMode::Immediate,   0b0101_0101, cpu.register_a = 0b0000_0101, assert_eq!(cpu.register_a, 0b0101_0101), 0,
@@ -1296,7 +3065,7 @@ mod test {
     #[test]
     fn test_rla () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             // without carry set
@@ -1345,7 +3114,7 @@ mod test {
     #[test]
     fn test_rol () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             // without carry set
@@ -1396,7 +3165,7 @@ mod test {
     #[test]
     fn test_ror () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             // without carry set
@@ -1449,7 +3218,7 @@ mod test {
         let mut cpu = create_new_cpu();
 
         // we push 0x0000 as return address to the stack and the status of 0x00
-        cpu.interpret(vec![0xA9, 0x00, 0x48, 0xA9, 0x00, 0x48, 0xA9, 0x00, 0x48, 0x40]);
+        cpu.interpret(vec![0xA9, 0x00, 0x48, 0xA9, 0x00, 0x48, 0xA9, 0x00, 0x48, 0x40]).unwrap();
         assert_eq!(cpu.program_counter, 0x0001);
         assert_eq!(cpu.register_s, 0xFD);
         assert_eq!(cpu.status, 0b0010_0000);
@@ -1459,7 +3228,7 @@ mod test {
     fn test_rts () {
         let mut cpu = create_new_cpu();
 
-        cpu.interpret(vec![0x20, 0x07, 0x06, 0x00, 0x02, 0x02, 0x02, 0x60, 0x02, 0x02]);
+        cpu.interpret(vec![0x20, 0x07, 0x06, 0x00, 0x02, 0x02, 0x02, 0x60, 0x02, 0x02]).unwrap();
         assert_eq!(cpu.program_counter, 0x0604);
         assert_eq!(cpu.register_s, 0xFD);
     }
@@ -1467,7 +3236,7 @@ mod test {
     #[test]
     fn test_sax () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x87, &AddressingMode::ZeroPage, 0x77, cpu.register_x = 0x42, {
@@ -1501,7 +3270,7 @@ mod test {
     #[test]
     fn test_sbc () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0xE9, &AddressingMode::Immediate, 0x23, cpu.register_a = 0x25, {
@@ -1550,10 +3319,66 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_sha_shx_shy () {
+        let mut cpu = create_new_cpu();
+        let xydeviation = 7_u8;
+        // the base addresses used by build_instructions are in the zero page, therefore the stored
+        // value is and'ed with 0x00 + 1
+        opcode_test_case!{
+            cpu,
+            0x9F, &AddressingMode::AbsoluteY, 0x77, {cpu.register_a = 0x0F; cpu.register_x = 0xF3; cpu.register_y = xydeviation;}, {
+                assert_eq!(cpu.last_mem_write_value, 0x01);
+            }, xydeviation,
+            0x93, &AddressingMode::IndirectY, 0x77, {cpu.register_a = 0x0E; cpu.register_x = 0xF3; cpu.register_y = xydeviation;}, {
+                assert_eq!(cpu.last_mem_write_value, 0x00);
+            }, xydeviation,
+            0x9E, &AddressingMode::AbsoluteY, 0x77, {cpu.register_x = 0xF3; cpu.register_y = xydeviation;}, {
+                assert_eq!(cpu.last_mem_write_value, 0x01);
+            }, xydeviation,
+            0x9C, &AddressingMode::AbsoluteX, 0x77, {cpu.register_y = 0xF3; cpu.register_x = xydeviation;}, {
+                assert_eq!(cpu.last_mem_write_value, 0x01);
+            }, xydeviation,
+        }
+
+        // without page crossing the value is stored at the indexed address
+        cpu.power_on();
+        cpu.register_x = 0xFF;
+        cpu.register_y = 0x05;
+        cpu.interpret_without_reset(vec![0x9E, 0xF0, 0x02, 0x00], 0x00).unwrap();
+        assert_eq!(cpu.last_mem_write_address, 0x02F5);
+        assert_eq!(cpu.last_mem_write_value, 0x03);
+
+        // with page crossing the high byte of the address is replaced by the stored value
+        cpu.power_on();
+        cpu.register_x = 0x01;
+        cpu.register_y = 0x20;
+        cpu.interpret_without_reset(vec![0x9E, 0xF0, 0x02, 0x00], 0x00).unwrap();
+        assert_eq!(cpu.last_mem_write_address, 0x0110);
+        assert_eq!(cpu.last_mem_write_value, 0x01);
+
+        cpu.power_on();
+        cpu.register_y = 0xFF;
+        cpu.register_x = 0x20;
+        cpu.interpret_without_reset(vec![0x9C, 0xF0, 0x02, 0x00], 0x00).unwrap();
+        assert_eq!(cpu.last_mem_write_address, 0x0310);
+        assert_eq!(cpu.last_mem_write_value, 0x03);
+
+        cpu.power_on();
+        cpu.mem_write(0x10, 0xF0);
+        cpu.mem_write(0x11, 0x02);
+        cpu.register_a = 0x07;
+        cpu.register_x = 0x05;
+        cpu.register_y = 0x20;
+        cpu.interpret_without_reset(vec![0x93, 0x10, 0x00], 0x00).unwrap();
+        assert_eq!(cpu.last_mem_write_address, 0x0110);
+        assert_eq!(cpu.last_mem_write_value, 0x01);
+    }
+
     #[test]
     fn test_slo () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x07, &AddressingMode::ZeroPage,  0b0001_0101, cpu.register_a = 0b0101_0000, assert_eq!(cpu.register_a, 0b0111_1010), 0,
@@ -1581,7 +3406,7 @@ mod test {
     #[test]
     fn test_sre () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 5 as u8;
+        let xydeviation = 5_u8;
         opcode_test_case!{
             cpu,
             // without carry set
@@ -1613,13 +3438,13 @@ mod test {
             }, 0,
             0x5F, &AddressingMode::AbsoluteX,   0b0000_0000, {cpu.register_a = 0b0000_0000; cpu.register_x = xydeviation;}, {
                 assert_eq!(cpu.last_mem_write_value, 0b0000_0000);
-                assert_eq!(cpu.register_a, 0b0000_0000 ^ 0b0000_0000);
+                assert_eq!(cpu.register_a, 0b0000_0000);
                 check_zero_and_neg_flags(&cpu, true, false);
                 check_carry_flag(&cpu, false);
             }, xydeviation,
             0x4F, &AddressingMode::Absolute, 0b0000_0001, {cpu.register_a = 0b0000_0001; cpu.status = 0b0000_0001;}, {
                 assert_eq!(cpu.last_mem_write_value, 0b0000_0000);
-                assert_eq!(cpu.register_a, 0b0000_0000 ^ 0b0000_0001);
+                assert_eq!(cpu.register_a, 0b0000_0001);
                 check_zero_and_neg_flags(&cpu, false, false);
                 check_carry_flag(&cpu, true);
             }, 0,
@@ -1629,7 +3454,7 @@ mod test {
     #[test]
     fn test_sta () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x85, &AddressingMode::ZeroPage,  0x77, cpu.register_a = 0x42, {
@@ -1659,7 +3484,7 @@ mod test {
     #[test]
     fn test_stx_and_sty () {
         let mut cpu = create_new_cpu();
-        let xydeviation = 7 as u8;
+        let xydeviation = 7_u8;
         opcode_test_case!{
             cpu,
             0x86, &AddressingMode::ZeroPage,  0x77, cpu.register_x = 0x42, {
@@ -1683,6 +3508,28 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_tas () {
+        let mut cpu = create_new_cpu();
+        cpu.power_on();
+        cpu.register_a = 0xF3;
+        cpu.register_x = 0x3F;
+        cpu.register_y = 0x10;
+        cpu.interpret_without_reset(vec![0x9B, 0x00, 0x02, 0x00], 0x00).unwrap();
+        assert_eq!(cpu.register_s, 0x33);
+        assert_eq!(cpu.last_mem_write_address, 0x0210);
+        assert_eq!(cpu.last_mem_write_value, 0x03);
+
+        cpu.power_on();
+        cpu.register_a = 0xFF;
+        cpu.register_x = 0xFF;
+        cpu.register_y = 0x20;
+        cpu.interpret_without_reset(vec![0x9B, 0xF0, 0x00, 0x00], 0x00).unwrap();
+        assert_eq!(cpu.register_s, 0xFF);
+        assert_eq!(cpu.last_mem_write_address, 0x0110);
+        assert_eq!(cpu.last_mem_write_value, 0x01);
+    }
+
     // transfer between register opcodes follow
     macro_rules! generate_transfer_test {
         ($test_name:ident, $cpu_source_register:ident, $cpu_target_register:ident, $opcode:expr, $status_affected:expr) => {
@@ -1690,21 +3537,21 @@ mod test {
             fn $test_name() {
                 let mut cpu = create_new_cpu();
                 cpu.$cpu_source_register = 0x13;
-                cpu.interpret_without_reset(vec![$opcode, 0x00], 0x00);
+                cpu.interpret_without_reset(vec![$opcode, 0x00], 0x00).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x13);
                 if $status_affected {
                     check_zero_and_neg_flags(&cpu, false, false);
                 }
 
                 cpu.$cpu_source_register = 0x00;
-                cpu.interpret_without_reset(vec![$opcode, 0x00], 0x00);
+                cpu.interpret_without_reset(vec![$opcode, 0x00], 0x00).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x00);
                 if $status_affected {
                     check_zero_and_neg_flags(&cpu, true, false);
                 }
 
                 cpu.$cpu_source_register = 0x92;
-                cpu.interpret_without_reset(vec![$opcode, 0x00], 0x00);
+                cpu.interpret_without_reset(vec![$opcode, 0x00], 0x00).unwrap();
                 assert_eq!(cpu.$cpu_target_register, 0x92);
                 if $status_affected {
                     check_zero_and_neg_flags(&cpu, false, true);
@@ -1736,4 +3583,25 @@ mod test {
     generate_transfer_test!(
         test_tya, register_y, register_a, 0x98, true
     );
-}
\ No newline at end of file
+
+    #[test]
+    fn test_xaa () {
+        let mut cpu = create_new_cpu();
+        opcode_test_case!{
+            cpu,
+            0x8B, &AddressingMode::Immediate, 0xFF, {cpu.register_a = 0x01; cpu.register_x = 0x0F;}, {
+                assert_eq!(cpu.register_a, 0x0F);
+                assert_eq!(cpu.register_x, 0x0F);
+                check_zero_and_neg_flags(&cpu, false, false);
+            }, 0,
+            0x8B, &AddressingMode::Immediate, 0xF0, {cpu.register_a = 0x01; cpu.register_x = 0xFF;}, {
+                assert_eq!(cpu.register_a, 0xE0);
+                check_zero_and_neg_flags(&cpu, false, true);
+            }, 0,
+            0x8B, &AddressingMode::Immediate, 0xFF, {cpu.register_a = 0x00; cpu.register_x = 0x11; cpu.magic_constant = 0x00;}, {
+                assert_eq!(cpu.register_a, 0x00);
+                check_zero_and_neg_flags(&cpu, true, false);
+            }, 0,
+        }
+    }
+}
@@ -1,26 +1,26 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
 use crate::cpu::{AddressingMode, CPU};
 use crate::mem::Mem;
 use crate::opcodes::OpCode;
 
-fn parse_addressing_information(cpu: &CPU, opcode: &&OpCode) -> String {
+fn parse_addressing_information<M: Mem>(cpu: &CPU<M>, opcode: &&OpCode) -> String {
     // since we are inside the trace callback, the program_counter still points to the opcode of the instruction and therefore we have to 
     // be careful when using it, inside the cpu.rs logic we are usually already one step further, reading the operand of the opcode
     let result = match opcode.len {
         1 => {
             match opcode.code {
-                0x0a | 0x2a | 0x4a | 0x6a => format!("A  "),
+                0x0a | 0x2a | 0x4a | 0x6a => "A  ".to_string(),
                 _ => "".to_string()
             }
         },
         2 => {
-            let operand_value = cpu.mem_read(cpu.program_counter + 1);
-            let (real_address, stored_value) = match opcode.mode {
-                AddressingMode::Immediate | AddressingMode::NoneAddressing => (0, 0),
-                _ => {
-                    let address = cpu.get_absolute_address(&opcode.mode, cpu.program_counter + 1);
-                    (address, cpu.mem_read(address))
-                }
-            };
+            let operand_value = cpu.mem_peek(cpu.program_counter + 1);
             match opcode.mode {
                 // assuming these are the branching opcodes
                 AddressingMode::NoneAddressing => {
@@ -42,7 +42,7 @@ fn parse_addressing_information(cpu: &CPU, opcode: &&OpCode) -> String {
             }
         }
         3 => {
-            let operand_value: u16 = cpu.mem_read_u16(cpu.program_counter + 1);
+            let operand_value: u16 = cpu.mem_peek_u16(cpu.program_counter + 1);
             match opcode.mode {
                 AddressingMode::Absolute => format!("${:04x}", operand_value),
                 AddressingMode::AbsoluteX => format!("${:04x},X", operand_value),
@@ -57,16 +57,16 @@ fn parse_addressing_information(cpu: &CPU, opcode: &&OpCode) -> String {
     result
 }
 
-fn parse_detailed_addressing_information(cpu: &CPU, opcode: &&OpCode) -> String {
+fn parse_detailed_addressing_information<M: Mem>(cpu: &CPU<M>, opcode: &&OpCode) -> String {
     let result = match opcode.len {
         1 => "".to_string(),
         2 => {
-            let operand_value = cpu.mem_read(cpu.program_counter + 1);
+            let operand_value = cpu.mem_peek(cpu.program_counter + 1);
             let (real_address, stored_value) = match opcode.mode {
                 AddressingMode::Immediate | AddressingMode::NoneAddressing => (0, 0),
                 _ => {
                     let address = cpu.get_absolute_address(&opcode.mode, cpu.program_counter + 1);
-                    (address, cpu.mem_read(address))
+                    (address, cpu.mem_peek(address))
                 }
             };
             match opcode.mode {
@@ -76,8 +76,8 @@ fn parse_detailed_addressing_information(cpu: &CPU, opcode: &&OpCode) -> String
                     format!("@ {:02x} = {:04x} = {:02x}", operand_value.wrapping_add(cpu.register_x), real_address, stored_value)
                 },
                 AddressingMode::IndirectY => {
-                    let lo = cpu.mem_read(operand_value as u16);
-                    let hi = cpu.mem_read(operand_value.wrapping_add(1) as u16);
+                    let lo = cpu.mem_peek(operand_value as u16);
+                    let hi = cpu.mem_peek(operand_value.wrapping_add(1) as u16);
                     let indirect_address = (lo as u16) + ((hi as u16) << 8);
                     format!("= {:04x} @ {:04x} = {:02x}", indirect_address, real_address, stored_value)
                 },
@@ -89,7 +89,7 @@ fn parse_detailed_addressing_information(cpu: &CPU, opcode: &&OpCode) -> String
                 AddressingMode::Immediate | AddressingMode::NoneAddressing => (0, 0),
                 _ => {
                     let address = cpu.get_absolute_address(&opcode.mode, cpu.program_counter + 1);
-                    (address, cpu.mem_read(address))
+                    (address, cpu.mem_peek(address))
                 }
             };
             match opcode.code {
@@ -108,7 +108,7 @@ fn parse_detailed_addressing_information(cpu: &CPU, opcode: &&OpCode) -> String
     result
 }
 
-fn parse_register_stati(cpu: &CPU) -> String{
+fn parse_register_stati<M: Mem>(cpu: &CPU<M>) -> String{
     format!("A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x}",
         cpu.register_a,
         cpu.register_x,
@@ -118,10 +118,46 @@ fn parse_register_stati(cpu: &CPU) -> String{
     )
 }
 
-pub fn trace(cpu: &mut CPU, opcode: &&OpCode) -> String {
+// the flags as letters, upper case ones are set, e.g. "nvUbdIzc"
+fn parse_status_flags(status: u8) -> String {
+    "NVUBDIZC".chars().enumerate().map(|(i, flag)| {
+        if status & (0b1000_0000 >> i) != 0 { flag } else { flag.to_ascii_lowercase() }
+    }).collect()
+}
+
+// the layout of the trace lines, they match the logs of other emulators so traces can be diffed against them
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TraceFormat {
+    // the layout of nestest.log (Nintendulator)
+    Nestest,
+    // the default layout of the Mesen trace logger
+    Mesen,
+    // the default layout of the FCEUX trace logger with the cycle count enabled
+    Fceux,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nestest" => Ok(TraceFormat::Nestest),
+            "mesen" => Ok(TraceFormat::Mesen),
+            "fceux" => Ok(TraceFormat::Fceux),
+            _ => Err(format!("unknown trace format {}, expected nestest, mesen or fceux", s)),
+        }
+    }
+}
+
+pub fn trace<M: Mem>(cpu: &mut CPU<M>, opcode: &&OpCode) -> String {
+    trace_with_format(cpu, opcode, TraceFormat::Nestest)
+}
+
+// the PPU columns are left out in case the bus has no PPU
+pub fn trace_with_format<M: Mem>(cpu: &CPU<M>, opcode: &&OpCode, format: TraceFormat) -> String {
     let mut full_instruction = Vec::new();
     for i in 0 .. opcode.len as u16 {
-        full_instruction.push(cpu.mem_read(cpu.program_counter + i));
+        full_instruction.push(cpu.mem_peek(cpu.program_counter + i));
     }
 
     let instruction_str = full_instruction
@@ -132,9 +168,223 @@ pub fn trace(cpu: &mut CPU, opcode: &&OpCode) -> String {
     
     let addressing_string = parse_addressing_information(cpu, opcode);
     let addressing_details = parse_detailed_addressing_information(cpu, opcode);
+    let disassembly = format!("{: >4} {} {}", opcode.name, addressing_string, addressing_details).to_uppercase();
+
+    let ppu_position = cpu.bus().ppu_position();
+
+    match format {
+        TraceFormat::Nestest => {
+            let register_stati = parse_register_stati(cpu);
+
+            let part_one = format!("{:04x}  {:8} {}", cpu.program_counter, instruction_str, disassembly);
+            let mut line = format!("{:47} {}", part_one, register_stati);
+            if let Some((scanline, dot)) = ppu_position {
+                line += &format!(" PPU:{:>3},{:>3}", scanline, dot);
+            }
+            format!("{} CYC:{}", line, cpu.cycles).to_uppercase()
+        },
+        TraceFormat::Mesen => {
+            let mut line = format!("{:04X}  {:8} {:32} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}",
+                cpu.program_counter, instruction_str.to_uppercase(), disassembly.trim(),
+                cpu.register_a, cpu.register_x, cpu.register_y, cpu.register_s, parse_status_flags(cpu.status));
+            if let Some((scanline, dot)) = ppu_position {
+                line += &format!(" V:{:<3} H:{:<3}", scanline, dot);
+            }
+            format!("{} Cycle:{}", line, cpu.cycles)
+        },
+        TraceFormat::Fceux => {
+            format!("c{:<10} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}  ${:04X}:{:8}  {}",
+                cpu.cycles, cpu.register_a, cpu.register_x, cpu.register_y, cpu.register_s,
+                parse_status_flags(cpu.status), cpu.program_counter, instruction_str.to_uppercase(), disassembly.trim())
+        },
+    }
+}
+
+// collects trace lines of the executed instructions, pass its trace function as callback to the cpu,
+// lines are only formatted for instructions which pass the filters
+pub struct Tracer {
+    pub format: TraceFormat,
+    enabled: bool,
+    // only instructions inside the range are traced
+    address_range: Option<RangeInclusive<u16>>,
+    // tracing starts once the program counter reaches the trigger address
+    start_trigger: Option<u16>,
+    triggered: bool,
+    // tracing stops after the given number of instructions
+    instruction_limit: Option<u64>,
+    traced_instructions: u64,
+    output: Option<Box<dyn Write>>,
+    // the last lines are kept in memory, e.g. to dump them after a crash
+    ring_buffer: VecDeque<String>,
+    ring_buffer_capacity: usize,
+}
+
+impl Tracer {
+    pub fn new(format: TraceFormat) -> Self {
+        Tracer {
+            format,
+            enabled: true,
+            address_range: None,
+            start_trigger: None,
+            triggered: false,
+            instruction_limit: None,
+            traced_instructions: 0,
+            output: None,
+            ring_buffer: VecDeque::new(),
+            ring_buffer_capacity: 0,
+        }
+    }
 
-    let register_stati = parse_register_stati(cpu);
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_address_range(&mut self, address_range: Option<RangeInclusive<u16>>) {
+        self.address_range = address_range;
+    }
+
+    // setting a trigger (or None) restarts the instruction count
+    pub fn set_start_trigger(&mut self, start_trigger: Option<u16>) {
+        self.start_trigger = start_trigger;
+        self.triggered = false;
+        self.traced_instructions = 0;
+    }
+
+    pub fn set_instruction_limit(&mut self, instruction_limit: Option<u64>) {
+        self.instruction_limit = instruction_limit;
+    }
+
+    pub fn set_output(&mut self, output: Option<Box<dyn Write>>) {
+        self.output = output;
+    }
 
-    let part_one = format!("{:04x}  {:8} {: >4} {} {}", cpu.program_counter, instruction_str, opcode.name, addressing_string, addressing_details);
-    format!("{:47} {}", part_one, register_stati).to_uppercase()
-}
\ No newline at end of file
+    // writes the lines to a buffered file, call flush before the program exits
+    pub fn set_output_file(&mut self, path: &str) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.output = Some(Box::new(BufWriter::new(file)));
+        Ok(())
+    }
+
+    // a capacity of 0 disables the ring buffer
+    pub fn set_ring_buffer_capacity(&mut self, capacity: usize) {
+        self.ring_buffer_capacity = capacity;
+        while self.ring_buffer.len() > capacity {
+            self.ring_buffer.pop_front();
+        }
+    }
+
+    pub fn ring_buffer(&self) -> impl Iterator<Item = &String> {
+        self.ring_buffer.iter()
+    }
+
+    pub fn dump_ring_buffer<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for line in &self.ring_buffer {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Some(output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn trace<M: Mem>(&mut self, cpu: &CPU<M>, opcode: &&OpCode) {
+        if !self.enabled || (self.output.is_none() && self.ring_buffer_capacity == 0) {
+            return;
+        }
+
+        if !self.triggered {
+            match self.start_trigger {
+                Some(address) if address != cpu.program_counter => return,
+                _ => self.triggered = true,
+            }
+        }
+
+        if let Some(address_range) = &self.address_range {
+            if !address_range.contains(&cpu.program_counter) {
+                return;
+            }
+        }
+
+        if let Some(limit) = self.instruction_limit {
+            if self.traced_instructions >= limit {
+                return;
+            }
+        }
+        self.traced_instructions += 1;
+
+        let line = trace_with_format(cpu, opcode, self.format);
+
+        // a failing output (e.g. a full disk) stops the file output instead of the emulation
+        if let Some(output) = &mut self.output {
+            if writeln!(output, "{}", line).is_err() {
+                self.output = None;
+            }
+        }
+
+        if self.ring_buffer_capacity > 0 {
+            if self.ring_buffer.len() == self.ring_buffer_capacity {
+                self.ring_buffer.pop_front();
+            }
+            self.ring_buffer.push_back(line);
+        }
+    }
+}
+
+// the purpose of a bus access of the cpu
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BusAccessKind {
+    OpcodeFetch,
+    // operand bytes, pointers and the data the instruction operates on
+    Operand,
+    // accesses whose value is discarded, like the reads of indexed addressing before the page fixup
+    // and the write of the unmodified value by read-modify-write instructions
+    Dummy,
+    Stack,
+    // the interrupt and reset vectors
+    Vector,
+    Dma,
+}
+
+// a single bus access, recorded by the cpu while it happens
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BusAccess {
+    // the cpu cycle the access happened in
+    pub cycle: u64,
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+    pub kind: BusAccessKind,
+}
+
+// receives every bus access of the cpu, see CPU::set_bus_trace
+pub type BusTrace = Box<dyn FnMut(&BusAccess)>;
+
+impl fmt::Display for BusAccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BusAccessKind::OpcodeFetch => "fetch",
+            BusAccessKind::Operand => "operand",
+            BusAccessKind::Dummy => "dummy",
+            BusAccessKind::Stack => "stack",
+            BusAccessKind::Vector => "vector",
+            BusAccessKind::Dma => "dma",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// one line per access, e.g. "      7 R C000 4C fetch"
+impl fmt::Display for BusAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.write { "W" } else { "R" };
+        write!(f, "{:>7} {} {:04X} {:02X} {}", self.cycle, direction, self.address, self.value, self.kind)
+    }
+}
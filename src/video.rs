@@ -0,0 +1,73 @@
+/*
+How the frames are presented: the palette turns the pixels into colors, unless the NTSC filter decodes them
+from the composite video signal. The window, the screenshots and the recordings use the same colors,
+all of them with the overscan cut off. The window additionally scales the image in software:
+an upscaler, an integer scale, the 8:7 pixel aspect ratio and scanlines.
+ */
+
+use crate::frame::{Frame, Image, Overscan, HEIGHT, WIDTH};
+use crate::ntsc::{NtscFilter, NTSC_HEIGHT, NTSC_WIDTH};
+use crate::palette::Palette;
+use crate::scaler::{self, Scaler, PIXEL_ASPECT_RATIO};
+
+pub struct Video {
+    pub palette: Palette,
+    // the NTSC filter has its own colors, the palette is not used with it
+    pub ntsc: Option<NtscFilter>,
+    pub overscan: Overscan,
+    pub scaler: Scaler,
+    // the integer scale applied after the upscaler
+    pub scale: usize,
+    pub aspect_correction: bool,
+    pub scanlines: bool,
+}
+
+impl Default for Video {
+    fn default() -> Self {
+        Video {
+            palette: Palette::default(),
+            ntsc: None,
+            overscan: Overscan::NONE,
+            scaler: Scaler::None,
+            scale: 1,
+            aspect_correction: false,
+            scanlines: false,
+        }
+    }
+}
+
+impl Video {
+    // the frame number selects the phase of the NTSC color subcarrier
+    pub fn render(&self, frame: &Frame, frame_number: u64) -> Image {
+        match &self.ntsc {
+            Some(filter) => filter.render(frame, NtscFilter::burst_phase(frame_number)),
+            None => frame.to_image(&self.palette),
+        }
+    }
+
+    // the rendered image with the overscan cut off, as it is recorded
+    pub fn render_cropped(&self, frame: &Frame, frame_number: u64) -> Image {
+        self.render(frame, frame_number).crop(self.overscan)
+    }
+
+    // the size of the images of render_cropped
+    pub fn cropped_size(&self) -> (usize, usize) {
+        let (width, height) = if self.ntsc.is_some() { (NTSC_WIDTH, NTSC_HEIGHT) } else { (WIDTH, HEIGHT) };
+        (self.overscan.width() * width / WIDTH, self.overscan.height() * height / HEIGHT)
+    }
+
+    // the image shown in the window
+    pub fn present(&self, frame: &Frame, frame_number: u64) -> Image {
+        let image = self.render_cropped(frame, frame_number);
+        let mut image = self.scaler.apply(&image).scale(self.scale.max(1));
+        // the NTSC filter already outputs the TV aspect ratio
+        if self.aspect_correction && self.ntsc.is_none() {
+            image = scaler::stretch(&image, PIXEL_ASPECT_RATIO);
+        }
+        if self.scanlines {
+            let rows_per_scanline = image.height / self.overscan.height();
+            scaler::scanlines(&mut image, rows_per_scanline);
+        }
+        image
+    }
+}
@@ -0,0 +1,148 @@
+; the test rom of the golden images in tests/golden, assembled with asm6:
+;     asm6 sprite_scroll.asm sprite_scroll.nes
+; sprite 0 (a ball) stands in front of a background of vertical stripes,
+; A on the first controller moves the sprite to the right, B scrolls the background
+
+    .db "NES", $1A
+    .db 1                   ; 16KB program rom
+    .db 1                   ; 8KB character rom
+    .db %00000001           ; vertical mirroring, mapper 0
+    .db 0, 0, 0, 0, 0, 0, 0, 0, 0
+
+scroll = $10
+oam = $0200
+
+PPUCTRL = $2000
+PPUMASK = $2001
+PPUSTATUS = $2002
+PPUSCROLL = $2005
+PPUADDR = $2006
+PPUDATA = $2007
+OAMDMA = $4014
+JOYPAD1 = $4016
+
+    .org $C000
+reset:
+    sei
+    cld
+    ldx #$FF
+    txs
+    lda #0
+    sta PPUCTRL
+    sta PPUMASK
+
+    ; the PPU is ready after two vertical blanks
+vblank1:
+    bit PPUSTATUS
+    bpl vblank1
+vblank2:
+    bit PPUSTATUS
+    bpl vblank2
+
+    ; the background and the sprite palettes
+    lda #$3F
+    sta PPUADDR
+    lda #$00
+    sta PPUADDR
+    ldx #0
+load_palette:
+    lda palette,x
+    sta PPUDATA
+    inx
+    cpx #32
+    bne load_palette
+
+    ; the first nametable and its attributes, every 8 bytes the tile changes from 0 to 3
+    lda #$20
+    sta PPUADDR
+    lda #$00
+    sta PPUADDR
+    ldy #4
+    ldx #0
+fill_nametable:
+    txa
+    lsr a
+    lsr a
+    lsr a
+    and #3
+    sta PPUDATA
+    inx
+    bne fill_nametable
+    dey
+    bne fill_nametable
+
+    ; all sprites are hidden below the screen but sprite 0
+    ldx #0
+    lda #$FF
+clear_oam:
+    sta oam,x
+    inx
+    bne clear_oam
+    lda #100                ; y
+    sta oam
+    lda #3                  ; tile
+    sta oam+1
+    lda #0                  ; attributes
+    sta oam+2
+    lda #120                ; x
+    sta oam+3
+
+    lda #0
+    sta scroll
+    sta PPUSCROLL
+    sta PPUSCROLL
+    lda #%10000000          ; NMI at the vertical blank
+    sta PPUCTRL
+    lda #%00011110          ; background and sprites, also in the leftmost 8 pixels
+    sta PPUMASK
+
+main:
+    jmp main
+
+nmi:
+    lda #>oam
+    sta OAMDMA
+
+    lda #1
+    sta JOYPAD1
+    lda #0
+    sta JOYPAD1
+    lda JOYPAD1             ; A
+    and #1
+    beq no_a
+    inc oam+3
+no_a:
+    lda JOYPAD1             ; B
+    and #1
+    beq no_b
+    inc scroll
+no_b:
+
+    lda scroll
+    sta PPUSCROLL
+    lda #0
+    sta PPUSCROLL
+    lda #%10000000
+    sta PPUCTRL
+    rti
+
+palette:
+    .db $0F, $11, $21, $30, $0F, $16, $27, $38, $0F, $19, $29, $3A, $0F, $13, $23, $33
+    .db $0F, $16, $27, $14, $0F, $11, $21, $30, $0F, $19, $29, $30, $0F, $13, $23, $30
+
+    .pad $FFFA, $EA
+    .dw nmi, reset, reset
+
+; the character rom
+    .dsb 16, 0              ; tile 0: color 0
+
+    .db $AA, $55, $AA, $55, $AA, $55, $AA, $55   ; tile 1: a checkerboard of color 1
+    .db $00, $00, $00, $00, $00, $00, $00, $00
+
+    .db $F0, $F0, $F0, $F0, $F0, $F0, $F0, $F0   ; tile 2: stripes
+    .db $FF, $00, $FF, $00, $FF, $00, $FF, $00
+
+    .db $3C, $7E, $FF, $FF, $FF, $FF, $7E, $3C   ; tile 3: a ball of color 3
+    .db $3C, $7E, $FF, $FF, $FF, $FF, $7E, $3C
+
+    .dsb $2000 - 64, 0